@@ -0,0 +1,45 @@
+//! How long each step of checking a code piece takes on large generated ones
+//!
+//! `cargo bench --no-default-features --bench pipeline`
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use dbm::lang::{edition::Edition, engine::Engine, formatter, lexer::lex, parser::Parser};
+
+/// A code piece of about `lines` lines of declarations, comparisons and blocks
+fn generate(lines: usize) -> String {
+    let mut code = String::new();
+
+    for i in 0..lines / 6 {
+        code.push_str(&format!(
+            "var value{i}: string = \"value {i}\"\n\
+             if value{i} != \"{i}\" do\n    var copy{i} = value{i}\nend else do\n    \
+             var other{i} = value{i}\nend\n"
+        ));
+    }
+
+    code
+}
+
+fn pipeline(c: &mut Criterion) {
+    for lines in [200, 2_000, 20_000] {
+        let code = generate(lines);
+        let tokens = lex(&code, "bench".to_string(), Edition::LATEST).unwrap();
+
+        c.bench_with_input(BenchmarkId::new("lex", lines), &code, |b, code| {
+            b.iter(|| lex(code, "bench".to_string(), Edition::LATEST).unwrap())
+        });
+        c.bench_with_input(BenchmarkId::new("parse", lines), &tokens, |b, tokens| {
+            b.iter(|| Parser::new(tokens, Edition::LATEST).parse().unwrap())
+        });
+        c.bench_with_input(BenchmarkId::new("format", lines), &code, |b, code| {
+            b.iter(|| formatter::format(code, Edition::LATEST).unwrap())
+        });
+        c.bench_with_input(BenchmarkId::new("compile", lines), &code, |b, code| {
+            let engine = Engine::new();
+            b.iter(|| engine.compile(code).unwrap())
+        });
+    }
+}
+
+criterion_group!(benches, pipeline);
+criterion_main!(benches);
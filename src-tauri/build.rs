@@ -1,3 +1,5 @@
 fn main() {
-    tauri_build::build()
+    // Only the app needs the Tauri context, the language builds without it
+    #[cfg(feature = "app")]
+    tauri_build::build();
 }
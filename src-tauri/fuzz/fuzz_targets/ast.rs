@@ -0,0 +1,19 @@
+#![no_main]
+
+use dbm::lang::{ast::SyntaxTree, edition::Edition, formatter};
+use libfuzzer_sys::fuzz_target;
+
+// Going through JSON must not lose anything, so the block editor round-trips with text
+fuzz_target!(|code: &str| {
+    let Ok(tree) = SyntaxTree::parse(code, Edition::LATEST) else {
+        return;
+    };
+
+    let json = serde_json::to_string(&tree).unwrap();
+    let printed = serde_json::from_str::<SyntaxTree>(&json).unwrap().print();
+    assert_eq!(printed, formatter::format(code, Edition::LATEST).unwrap());
+
+    // Printed code is formatted, so it comes back exactly
+    let reparsed = SyntaxTree::parse(&printed, Edition::LATEST).unwrap();
+    assert_eq!(reparsed.print(), printed);
+});
@@ -0,0 +1,19 @@
+#![no_main]
+
+use dbm::lang::{edition::Edition, lexer::lex, parser::Parser, runtime::Interpreter, scope::Scope};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|code: &str| {
+    let Ok(tokens) = lex(code, "fuzz".to_string(), Edition::LATEST) else {
+        return;
+    };
+    let Ok(program) = Parser::new(&tokens, Edition::LATEST).parse() else {
+        return;
+    };
+
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .build()
+        .unwrap();
+    let mut interpreter = Interpreter::new(Scope::new());
+    let _ = runtime.block_on(interpreter.evaluate_block(&program));
+});
@@ -0,0 +1,8 @@
+#![no_main]
+
+use dbm::lang::{edition::Edition, lexer::lex};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|code: &str| {
+    let _ = lex(code, "fuzz".to_string(), Edition::LATEST);
+});
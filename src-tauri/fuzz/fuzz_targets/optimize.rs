@@ -0,0 +1,70 @@
+#![no_main]
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use dbm::lang::{
+    builtins::BUILTINS,
+    edition::Edition,
+    lexer::lex,
+    nodes::Block,
+    optimizer::optimize,
+    parser::Parser,
+    runtime::Interpreter,
+    scope::Scope,
+    values::{self, NativeFunction, RuntimeValue},
+};
+use libfuzzer_sys::fuzz_target;
+
+/// Runs the program, returning everything which can be observed from the outside
+fn run(program: &Block, edition: Edition) -> (Result<String, String>, Vec<String>) {
+    let replies = Arc::new(Mutex::new(vec![]));
+    let replies2 = replies.clone();
+    let reply = move |args: Vec<RuntimeValue>| {
+        let content = args.iter().map(|x| x.to_string()).collect();
+        replies2.lock().unwrap().push(content);
+        Ok(values::Null::make())
+    };
+
+    let mut scope = Scope::new();
+    let message = values::Object::make(HashMap::from([
+        ("reply".to_string(), NativeFunction::make_sync(reply)),
+        (
+            "content".to_string(),
+            values::StringValue::make("!test".to_string()),
+        ),
+    ]));
+    scope.declare("message", message).unwrap();
+
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .build()
+        .unwrap();
+    let mut interpreter = Interpreter::new(scope);
+    interpreter.set_edition(edition);
+    let result = runtime
+        .block_on(interpreter.evaluate_block(program))
+        .map(|x| format!("{}: {}", x.type_name(), x))
+        .map_err(|x| x.message.clone());
+
+    let replies = replies.lock().unwrap().clone();
+    (result, replies)
+}
+
+// The optimizer must never change what a program does
+fuzz_target!(|code: &str| {
+    for edition in [Edition::V1, Edition::V2, Edition::V3] {
+        let Ok(tokens) = lex(code, "fuzz".to_string(), edition) else {
+            continue;
+        };
+        let Ok(program) = Parser::new(&tokens, edition).parse() else {
+            continue;
+        };
+
+        let globals: Vec<&str> = BUILTINS.iter().map(|x| x.name).collect();
+        let optimized = optimize(program.clone(), &globals, edition, false);
+
+        assert_eq!(run(&program, edition), run(&optimized, edition));
+    }
+});
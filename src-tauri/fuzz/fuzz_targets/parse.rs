@@ -0,0 +1,10 @@
+#![no_main]
+
+use dbm::lang::{edition::Edition, lexer::lex, parser::Parser};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|code: &str| {
+    if let Ok(tokens) = lex(code, "fuzz".to_string(), Edition::LATEST) {
+        let _ = Parser::new(&tokens, Edition::LATEST).parse();
+    }
+});
@@ -0,0 +1,10 @@
+use lsp_server::Connection;
+
+fn main() -> Result<(), Box<dyn std::error::Error + Sync + Send>> {
+    let (connection, io_threads) = Connection::stdio();
+
+    dbm::lsp::run(connection)?;
+    io_threads.join()?;
+
+    Ok(())
+}
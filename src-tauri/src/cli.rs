@@ -0,0 +1,349 @@
+use std::{
+    collections::HashMap,
+    io::{self, BufRead, Write},
+    sync::Arc,
+};
+
+use crate::{
+    errors::MakerError,
+    lang::{
+        builtins,
+        diagnostics::render,
+        edition::Edition,
+        engine::Engine,
+        lexer::lex,
+        migrate::migrate,
+        natives::NativeRegistry,
+        nodes::Block,
+        parser::Parser,
+        profiler::Profiler,
+        runtime::Interpreter,
+        values::{self, RuntimeValue},
+    },
+};
+
+const USAGE: &str = "Usage:
+    dbm                                Open the app
+    dbm run <file> [--content <text>]  Run a script with a mock message
+    dbm repl [--content <text>]        Start an interactive session
+    dbm migrate <file> [--write]       Print a script upgraded to the latest edition, or write it back with --write
+    dbm test <bot>                     Run the tests of a bot's commands, from the app's database
+    dbm docs                           Print the reference of every builtin, as markdown
+
+Options:
+    --edition <number>                 The edition the script is written in, the latest by default, or 1 for migrate
+    --profile <file>                   Write a flamegraph of where `run` spent its time, in the folded stack format";
+
+/// The subcommands `run` handles, `dbm test` is handled by the app as it needs its database
+const COMMANDS: &[&str] = &["run", "repl", "migrate", "docs", "help", "--help", "-h"];
+
+/// Runs the command line interface if a subcommand was given, returning the
+/// exit code, or `None` when the app should be opened as usual
+///
+/// Other arguments are left to the app, e.g. the `-psn_...` one macOS gives it
+pub async fn run(args: &[String]) -> Option<i32> {
+    let command = args.get(1).filter(|x| COMMANDS.contains(&x.as_str()))?;
+    let content = option(args, "--content").unwrap_or_default();
+    let edition = match option(args, "--edition") {
+        Some(number) => match number.parse().ok().and_then(Edition::from_number) {
+            Some(edition) => Some(edition),
+            None => {
+                eprintln!("Unknown edition {}", number);
+                return Some(2);
+            }
+        },
+        None => None,
+    };
+    let file = args.get(2).filter(|x| !x.starts_with("--"));
+
+    let code = match (command.as_str(), file) {
+        ("run", Some(file)) => {
+            let edition = edition.unwrap_or(Edition::LATEST);
+            run_file(file, content, edition, option(args, "--profile")).await
+        }
+        // Scripts which need migrating were written before editions existed
+        ("migrate", Some(file)) => migrate_file(
+            file,
+            edition.unwrap_or(Edition::V1),
+            args.iter().any(|x| x == "--write"),
+        ),
+        ("run" | "migrate", None) => {
+            eprintln!("{}", USAGE);
+            2
+        }
+        ("repl", _) => repl(content, edition.unwrap_or(Edition::LATEST)).await,
+        ("docs", _) => {
+            print!("{}", builtins::reference());
+            0
+        }
+        ("help" | "--help" | "-h", _) => {
+            println!("{}", USAGE);
+            0
+        }
+        _ => unreachable!("{} is not in COMMANDS", command),
+    };
+
+    Some(code)
+}
+
+fn option(args: &[String], name: &str) -> Option<String> {
+    let index = args.iter().position(|x| x == name)?;
+    args.get(index + 1).cloned()
+}
+
+/// Stand-ins for what the app gives a command, replies and logs are printed instead of sent
+pub fn mock_bindings(content: String) -> HashMap<String, RuntimeValue> {
+    let registry = NativeRegistry::new()
+        .register("message.reply", |_, args, _| async move {
+            println!("reply: {}", args[0]);
+            Ok(values::Null::make())
+        })
+        .register("print", |_, args, _| async move {
+            println!("print: {}", args[0]);
+            Ok(values::Null::make())
+        })
+        .register("log.info", |_, args, _| async move {
+            println!("info: {}", args[0]);
+            Ok(values::Null::make())
+        })
+        .register("log.warn", |_, args, _| async move {
+            println!("warn: {}", args[0]);
+            Ok(values::Null::make())
+        });
+
+    let mut message = registry.bind("message", Arc::new(()));
+    message.insert("id".to_string(), values::Snowflake::make(0));
+    message.insert("content".to_string(), values::StringValue::make(content));
+    message.insert(
+        "author".to_string(),
+        values::StringValue::make("user".to_string()),
+    );
+    message.insert("author_id".to_string(), values::Snowflake::make(0));
+    message.insert("channel".to_string(), values::Snowflake::make(0));
+
+    let mut bindings = HashMap::from([
+        ("message".to_string(), values::Object::make(message)),
+        (
+            "log".to_string(),
+            values::Object::make(registry.bind("log", Arc::new(()))),
+        ),
+    ]);
+    if let Some(print) = registry.get("print", Arc::new(())) {
+        bindings.insert("print".to_string(), print);
+    }
+    bindings
+}
+
+fn new_interpreter(content: String, edition: Edition) -> Interpreter {
+    let mut engine = Engine::new();
+    engine.set_edition(edition);
+
+    engine
+        .interpreter(mock_bindings(content))
+        .expect("only the builtins are declared before message")
+}
+
+fn compile(source: &str, context: &str, edition: Edition) -> Result<Block, MakerError> {
+    let tokens = lex(source, context.to_string(), edition)?;
+    let mut parser = Parser::new(&tokens, edition);
+    parser.parse()
+}
+
+fn read(file: &str) -> Option<String> {
+    match std::fs::read_to_string(file) {
+        Ok(source) => Some(source),
+        Err(err) => {
+            eprintln!("Failed to read {}: {}", file, err);
+            None
+        }
+    }
+}
+
+/// Prints the migrated script, the changes go to stderr so that it can be redirected,
+/// or with `write` replaces the file with it
+fn migrate_file(file: &str, edition: Edition, write: bool) -> i32 {
+    let Some(source) = read(file) else {
+        return 1;
+    };
+
+    let migration = match migrate(&source, edition) {
+        Ok(migration) => migration,
+        Err(err) => {
+            eprint!("{}", render(&err, &source));
+            return 1;
+        }
+    };
+
+    for change in &migration.changes {
+        eprintln!(
+            "{}:{}:{}: {}",
+            file, change.location.line, change.location.column, change.message
+        );
+    }
+
+    if !write {
+        print!("{}", migration.source);
+        return 0;
+    }
+
+    if let Err(err) = std::fs::write(file, &migration.source) {
+        eprintln!("Failed to write {}: {}", file, err);
+        return 1;
+    }
+
+    println!(
+        "Migrated {} from edition {} to {} with {} change(s)",
+        file,
+        migration.from,
+        migration.to,
+        migration.changes.len()
+    );
+    0
+}
+
+async fn run_file(file: &str, content: String, edition: Edition, profile: Option<String>) -> i32 {
+    let Some(source) = read(file) else {
+        return 1;
+    };
+
+    let result = match compile(&source, file, edition) {
+        Ok(program) => {
+            let mut interpreter = new_interpreter(content, edition);
+            if profile.is_some() {
+                interpreter.set_profiler(Profiler::new(file));
+            }
+
+            let result = interpreter.evaluate_block(&program).await;
+
+            if let (Some(path), Some(profile)) = (profile, interpreter.take_profile()) {
+                match std::fs::write(&path, profile.to_folded()) {
+                    Ok(_) => eprintln!("Wrote the profile to {}", path),
+                    Err(err) => eprintln!("Failed to write {}: {}", path, err),
+                }
+            }
+
+            result
+        }
+        Err(err) => Err(err),
+    };
+
+    match result {
+        Ok(_) => 0,
+        Err(err) => {
+            eprint!("{}", render(&err, &source));
+            1
+        }
+    }
+}
+
+/// Whether the error only happened because the input stopped early,
+/// e.g. a `do` without its `end` yet
+fn is_incomplete(err: &MakerError, source: &str) -> bool {
+    match &err.location {
+        Some(location) => location.start >= source.trim_end().len(),
+        None => false,
+    }
+}
+
+async fn repl(content: String, edition: Edition) -> i32 {
+    let mut interpreter = new_interpreter(content, edition);
+    let mut lines = io::stdin().lock().lines();
+    let mut source = String::new();
+
+    loop {
+        print!("{}", if source.is_empty() { ">>> " } else { "... " });
+        let _ = io::stdout().flush();
+
+        let line = match lines.next() {
+            Some(Ok(line)) => line,
+            _ => break,
+        };
+
+        source.push_str(&line);
+        source.push('\n');
+
+        if source.trim().is_empty() {
+            source.clear();
+            continue;
+        }
+
+        let program = match compile(&source, "repl", edition) {
+            Ok(program) => program,
+            // Keep reading until the block is closed
+            Err(err) if is_incomplete(&err, &source) => continue,
+            Err(err) => {
+                eprint!("{}", render(&err, &source));
+                source.clear();
+                continue;
+            }
+        };
+
+        match interpreter.evaluate_block(&program).await {
+            Ok(RuntimeValue::Null(_)) => {}
+            Ok(value) => println!("{}", value),
+            Err(err) => eprint!("{}", render(&err, &source)),
+        }
+
+        source.clear();
+    }
+
+    println!();
+    0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn migrating_only_writes_when_asked() {
+        let file = std::env::temp_dir().join("dbm-cli-migrate.dbm");
+        let file_name = file.to_str().unwrap();
+        let source = "if message.content != \"!hi\" do\n    message.reply(\"hi\")\nend";
+        std::fs::write(&file, source).unwrap();
+
+        assert_eq!(migrate_file(file_name, Edition::V1, false), 0);
+        assert_eq!(std::fs::read_to_string(&file).unwrap(), source);
+
+        assert_eq!(migrate_file(file_name, Edition::V1, true), 0);
+        let migrated = std::fs::read_to_string(&file).unwrap();
+        assert!(migrated.starts_with("if message.content == \"!hi\" do"));
+
+        let _ = std::fs::remove_file(&file);
+    }
+
+    #[test]
+    fn scripts_are_migrated_from_the_first_edition_by_default() {
+        let file = std::env::temp_dir().join("dbm-cli-migrate-default.dbm");
+        std::fs::write(&file, "if a != b do\nend").unwrap();
+        let args: Vec<String> = ["dbm", "migrate", file.to_str().unwrap(), "--write"]
+            .iter()
+            .map(|x| x.to_string())
+            .collect();
+
+        let code = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap()
+            .block_on(run(&args));
+
+        assert_eq!(code, Some(0));
+        assert_eq!(std::fs::read_to_string(&file).unwrap(), "if a == b do\nend");
+        let _ = std::fs::remove_file(&file);
+    }
+
+    #[test]
+    fn unknown_arguments_open_the_app() {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+
+        for args in [
+            vec!["dbm"],
+            vec!["dbm", "-psn_0_12345"],
+            vec!["dbm", "unknown"],
+        ] {
+            let args: Vec<String> = args.iter().map(|x| x.to_string()).collect();
+            assert_eq!(runtime.block_on(run(&args)), None);
+        }
+    }
+}
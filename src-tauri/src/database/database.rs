@@ -1,45 +1,219 @@
 use std::{fs, path::PathBuf};
 
-use super::managers::{BotManager, CodePieceManager, CommandManager, SettingsManager};
+use super::managers::{
+    BotManager, CodePieceManager, CommandManager, PluginManager, SettingsManager, TestManager,
+};
+use crate::errors::{MakerError, MakerErrorType};
 use log::info;
 use sqlx::{sqlite::SqlitePool, Executor};
 
+/// Where the app keeps a file of its own, e.g. the database, which is next to the
+/// folder it is run from
+pub fn data_path(name: &str) -> PathBuf {
+    let mut path = PathBuf::new();
+    path.push("..");
+    path.push(name);
+    path
+}
+
+/// A change to tables which already existed, main.sql only affects new databases
+struct Migration {
+    sql: &'static str,
+    /// Finds a row if the change was already made, which is only checked for databases
+    /// from before their version was kept, as those might have some of them
+    applied: &'static str,
+}
+
+/// Applied in order, `PRAGMA user_version` is how many of them a database has
+const MIGRATIONS: &[Migration] = &[
+    Migration {
+        sql: "ALTER TABLE code_pieces ADD COLUMN name TEXT DEFAULT NULL;",
+        applied: "SELECT 1 FROM pragma_table_info('code_pieces') WHERE name = 'name'",
+    },
+    Migration {
+        sql: "ALTER TABLE code_pieces ADD COLUMN bot_id INTEGER REFERENCES bots(id) DEFAULT NULL;",
+        applied: "SELECT 1 FROM pragma_table_info('code_pieces') WHERE name = 'bot_id'",
+    },
+    Migration {
+        sql: "CREATE UNIQUE INDEX code_pieces_name ON code_pieces (bot_id, name);",
+        applied: "SELECT 1 FROM sqlite_master WHERE type = 'index' AND name = 'code_pieces_name'",
+    },
+    Migration {
+        sql: "ALTER TABLE code_pieces ADD COLUMN edition INTEGER NOT NULL DEFAULT 1;",
+        applied: "SELECT 1 FROM pragma_table_info('code_pieces') WHERE name = 'edition'",
+    },
+    Migration {
+        sql: "ALTER TABLE code_pieces ADD COLUMN language TEXT NOT NULL DEFAULT 'dbm';",
+        applied: "SELECT 1 FROM pragma_table_info('code_pieces') WHERE name = 'language'",
+    },
+];
+
+/// Makes the tables which do not exist yet, then brings the others up to date
+async fn initialize(pool: &SqlitePool) -> Result<(), sqlx::Error> {
+    pool.execute(include_str!("../main.sql")).await?;
+    migrate(pool).await
+}
+
+/// Applies the migrations the database does not have yet, each along with its version
+async fn migrate(pool: &SqlitePool) -> Result<(), sqlx::Error> {
+    let version: i64 = sqlx::query_scalar("PRAGMA user_version")
+        .fetch_one(pool)
+        .await?;
+    let version = usize::try_from(version).unwrap_or_default();
+
+    for (index, migration) in MIGRATIONS.iter().enumerate().skip(version) {
+        let mut transaction = pool.begin().await?;
+
+        let applied = version == 0
+            && sqlx::query(migration.applied)
+                .fetch_optional(&mut *transaction)
+                .await?
+                .is_some();
+        if !applied {
+            info!("Applying migration {}: {}", index + 1, migration.sql);
+            transaction.execute(migration.sql).await?;
+        }
+
+        transaction
+            .execute(format!("PRAGMA user_version = {}", index + 1).as_str())
+            .await?;
+        transaction.commit().await?;
+    }
+
+    Ok(())
+}
+
 #[derive(Clone)]
 pub struct Database {
     pub bots: BotManager,
     pub options: SettingsManager,
     pub commands: CommandManager,
     pub code_pieces: CodePieceManager,
+    pub plugins: PluginManager,
+    pub tests: TestManager,
 }
 
 impl Database {
-    pub async fn new() -> Self {
-        let mut path = PathBuf::new();
-        path.push("../data.db");
+    pub async fn new() -> Result<Self, MakerError> {
+        let path = data_path("data.db");
 
         if !path.exists() {
-            fs::write(path.clone(), "").unwrap();
+            fs::write(path.clone(), "").map_err(database_error)?;
         }
 
-        let path_str = path.canonicalize().unwrap().display().to_string();
+        let path_str = path
+            .canonicalize()
+            .map_err(database_error)?
+            .display()
+            .to_string();
         info!("The database path is: {}", path_str);
 
         // Create the pool
         let pool = SqlitePool::connect(&format!("sqlite:{}", path_str))
             .await
-            .unwrap();
+            .map_err(database_error)?;
 
-        // Execute initial query
-        if let Err(err) = pool.execute(include_str!("../main.sql")).await {
-            panic!("Failed to execute init script: {}", err);
-        }
+        Self::open(pool).await
+    }
+
+    /// A database which only lives as long as it is used, for tests
+    #[cfg(test)]
+    pub async fn in_memory() -> Result<Self, MakerError> {
+        // Every connection to an in-memory database gets its own one
+        let pool = sqlx::sqlite::SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .map_err(database_error)?;
+
+        Self::open(pool).await
+    }
 
-        // Done
-        Database {
+    async fn open(pool: SqlitePool) -> Result<Self, MakerError> {
+        initialize(&pool).await.map_err(database_error)?;
+
+        Ok(Database {
             bots: BotManager { pool: pool.clone() },
             options: SettingsManager { pool: pool.clone() },
             commands: CommandManager { pool: pool.clone() },
             code_pieces: CodePieceManager { pool: pool.clone() },
-        }
+            plugins: PluginManager { pool: pool.clone() },
+            tests: TestManager { pool: pool.clone() },
+        })
+    }
+}
+
+fn database_error<E: std::error::Error + 'static>(err: E) -> MakerError {
+    MakerError::from(Some(Box::new(err)), MakerErrorType::UnknownDatabase)
+}
+
+#[cfg(test)]
+mod tests {
+    use sqlx::sqlite::SqlitePoolOptions;
+
+    use super::*;
+
+    async fn pool() -> SqlitePool {
+        // Every connection to an in-memory database gets its own one
+        SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap()
+    }
+
+    async fn version(pool: &SqlitePool) -> i64 {
+        sqlx::query_scalar("PRAGMA user_version")
+            .fetch_one(pool)
+            .await
+            .unwrap()
+    }
+
+    async fn columns(pool: &SqlitePool) -> Vec<String> {
+        sqlx::query_scalar("SELECT name FROM pragma_table_info('code_pieces')")
+            .fetch_all(pool)
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn new_databases_get_every_migration() {
+        let pool = pool().await;
+        initialize(&pool).await.unwrap();
+
+        assert_eq!(version(&pool).await, MIGRATIONS.len() as i64);
+        // Starting again finds nothing left to do
+        initialize(&pool).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn old_databases_get_the_migrations_they_miss() {
+        let pool = pool().await;
+        pool.execute(
+            "CREATE TABLE code_pieces (id INTEGER PRIMARY KEY, code TEXT NOT NULL DEFAULT '', name TEXT DEFAULT NULL);",
+        )
+        .await
+        .unwrap();
+
+        initialize(&pool).await.unwrap();
+
+        assert_eq!(version(&pool).await, MIGRATIONS.len() as i64);
+        assert_eq!(
+            columns(&pool).await,
+            vec!["id", "code", "name", "bot_id", "edition", "language"]
+        );
+    }
+
+    #[tokio::test]
+    async fn failing_migrations_are_reported_and_not_recorded() {
+        let pool = pool().await;
+        initialize(&pool).await.unwrap();
+        pool.execute("PRAGMA user_version = 4").await.unwrap();
+
+        // Only the last one is pending, and its column is already there
+        let err = migrate(&pool).await.unwrap_err();
+
+        assert!(err.to_string().contains("duplicate column"), "{}", err);
+        assert_eq!(version(&pool).await, 4);
     }
 }
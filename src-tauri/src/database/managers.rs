@@ -2,8 +2,10 @@ use log::info;
 use sqlx::sqlite::SqlitePool;
 
 use crate::errors::{MakerError, MakerErrorType};
+use crate::lang::edition::Edition;
+use crate::lang::testing::TestCase;
 
-use super::structures::{Bot, CodePiece, Command, Settings};
+use super::structures::{Bot, BotPlugin, CodePiece, Command, CommandTest, Settings};
 
 // Thanks ChatGPT :3c
 macro_rules! execute_query {
@@ -64,13 +66,11 @@ impl BotManager {
 
     pub async fn add(&self, name: &str, token: &str) -> Result<Bot, MakerError> {
         // Check if the bot already exists
-        if let Ok(_) = self.get_by_name(name).await {
-            return Err(MakerError {
-                message: format!("Bot {} already exists", name),
-                error_type: MakerErrorType::BotAlreadyExists,
-                source: None,
-                location: None,
-            });
+        if self.get_by_name(name).await.is_ok() {
+            return Err(MakerError::new(
+                format!("Bot {} already exists", name),
+                MakerErrorType::BotAlreadyExists,
+            ));
         }
 
         execute_query!(
@@ -224,8 +224,9 @@ impl CodePieceManager {
         execute_query!(
             self,
             CodePiece,
-            "INSERT INTO code_pieces DEFAULT VALUES RETURNING *;",
-            fetch_one
+            "INSERT INTO code_pieces (edition) VALUES (?1) RETURNING *;",
+            fetch_one,
+            Edition::LATEST.number()
         )
     }
 
@@ -233,9 +234,10 @@ impl CodePieceManager {
         execute_query!(
             self,
             CodePiece,
-            "INSERT INTO code_pieces (code) VALUES (?1) RETURNING *;",
+            "INSERT INTO code_pieces (code, edition) VALUES (?1, ?2) RETURNING *;",
             fetch_one,
-            code
+            code,
+            Edition::LATEST.number()
         )
     }
 
@@ -262,6 +264,100 @@ impl CodePieceManager {
         )
     }
 
+    /// Creates a library, which other code pieces of the bot can import by name
+    pub async fn create_library(&self, bot_id: u8, name: &str) -> Result<CodePiece, MakerError> {
+        if self.get_by_name(bot_id, name).await.is_ok() {
+            return Err(MakerError::new(
+                format!("Library {} already exists", name),
+                MakerErrorType::LibraryAlreadyExists,
+            ));
+        }
+
+        execute_query!(
+            self,
+            CodePiece,
+            "INSERT INTO code_pieces (bot_id, name, edition) VALUES (?1, ?2, ?3) RETURNING *;",
+            fetch_one,
+            bot_id,
+            name,
+            Edition::LATEST.number()
+        )
+    }
+
+    pub async fn get_by_name(&self, bot_id: u8, name: &str) -> Result<CodePiece, MakerError> {
+        execute_query!(
+            self,
+            CodePiece,
+            "SELECT * FROM code_pieces WHERE bot_id = ?1 AND name = ?2;",
+            fetch_one,
+            bot_id,
+            name
+        )
+    }
+
+    pub async fn get_libraries(&self, bot_id: u8) -> Result<Vec<CodePiece>, MakerError> {
+        execute_query!(
+            self,
+            CodePiece,
+            "SELECT * FROM code_pieces WHERE bot_id = ?1 AND name IS NOT NULL;",
+            fetch_all,
+            bot_id
+        )
+    }
+
+    pub async fn set_name(&self, id: u8, name: &str) -> Result<CodePiece, MakerError> {
+        let code_piece = self.get(id).await?;
+
+        if let Some(bot_id) = code_piece.bot_id {
+            if let Ok(other) = self.get_by_name(bot_id, name).await {
+                if other.id != id {
+                    return Err(MakerError::new(
+                        format!("Library {} already exists", name),
+                        MakerErrorType::LibraryAlreadyExists,
+                    ));
+                }
+            }
+        }
+
+        execute_query!(
+            self,
+            CodePiece,
+            "UPDATE code_pieces SET name = ?2 WHERE id = ?1 RETURNING *;",
+            fetch_one,
+            id,
+            name
+        )
+    }
+
+    /// Replaces the code along with the edition it is written in, used after migrating it
+    pub async fn set_with_edition(
+        &self,
+        id: u8,
+        code: &str,
+        edition: Edition,
+    ) -> Result<CodePiece, MakerError> {
+        execute_query!(
+            self,
+            CodePiece,
+            "UPDATE code_pieces SET code = ?2, edition = ?3 WHERE id = ?1 RETURNING *;",
+            fetch_one,
+            id,
+            code,
+            edition.number()
+        )
+    }
+
+    pub async fn set_language(&self, id: u8, language: &str) -> Result<CodePiece, MakerError> {
+        execute_query!(
+            self,
+            CodePiece,
+            "UPDATE code_pieces SET language = ?2 WHERE id = ?1 RETURNING *;",
+            fetch_one,
+            id,
+            language
+        )
+    }
+
     pub async fn set(&self, id: u8, code: &str) -> Result<CodePiece, MakerError> {
         execute_query!(
             self,
@@ -273,3 +369,101 @@ impl CodePieceManager {
         )
     }
 }
+
+#[derive(Clone)]
+pub struct PluginManager {
+    pub pool: SqlitePool,
+}
+
+impl PluginManager {
+    pub async fn get_enabled(&self, bot_id: u8) -> Result<Vec<BotPlugin>, MakerError> {
+        execute_query!(
+            self,
+            BotPlugin,
+            "SELECT * FROM bot_plugins WHERE bot_id = ?1;",
+            fetch_all,
+            bot_id
+        )
+    }
+
+    pub async fn enable(&self, bot_id: u8, name: &str) -> Result<Vec<BotPlugin>, MakerError> {
+        execute_query!(
+            self,
+            BotPlugin,
+            "INSERT OR IGNORE INTO bot_plugins (bot_id, name) VALUES (?1, ?2) RETURNING *;",
+            fetch_all,
+            bot_id,
+            name
+        )?;
+        self.get_enabled(bot_id).await
+    }
+
+    pub async fn disable(&self, bot_id: u8, name: &str) -> Result<Vec<BotPlugin>, MakerError> {
+        execute_query!(
+            self,
+            BotPlugin,
+            "DELETE FROM bot_plugins WHERE bot_id = ?1 AND name = ?2 RETURNING *;",
+            fetch_all,
+            bot_id,
+            name
+        )?;
+        self.get_enabled(bot_id).await
+    }
+}
+
+#[derive(Clone)]
+pub struct TestManager {
+    pub pool: SqlitePool,
+}
+
+impl TestManager {
+    pub async fn get_all(&self, command_id: u8) -> Result<Vec<CommandTest>, MakerError> {
+        execute_query!(
+            self,
+            CommandTest,
+            "SELECT * FROM command_tests WHERE command_id = ?1;",
+            fetch_all,
+            command_id
+        )
+    }
+
+    pub async fn create(&self, command_id: u8, name: &str) -> Result<CommandTest, MakerError> {
+        execute_query!(
+            self,
+            CommandTest,
+            "INSERT INTO command_tests (command_id, name) VALUES (?1, ?2) RETURNING *;",
+            fetch_one,
+            command_id,
+            name
+        )
+    }
+
+    pub async fn set(&self, id: u8, test: &TestCase) -> Result<CommandTest, MakerError> {
+        let expected_replies = serde_json::to_string(&test.expected_replies).map_err(|err| {
+            MakerError::from(Some(Box::from(err)), MakerErrorType::UnknownDatabase)
+        })?;
+
+        execute_query!(
+            self,
+            CommandTest,
+            "UPDATE command_tests SET name = ?2, content = ?3, author = ?4, channel = ?5, expected_replies = ?6 WHERE id = ?1 RETURNING *;",
+            fetch_one,
+            id,
+            &test.name,
+            &test.content,
+            &test.author,
+            &test.channel,
+            expected_replies
+        )
+    }
+
+    pub async fn delete(&self, id: u8) -> Result<Vec<CommandTest>, MakerError> {
+        execute_query!(
+            self,
+            CommandTest,
+            "DELETE FROM command_tests WHERE id = ?1 RETURNING *;",
+            fetch_all,
+            id
+        )
+    }
+}
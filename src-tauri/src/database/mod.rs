@@ -1,3 +1,4 @@
+#[allow(clippy::module_inception)]
 pub mod database;
 pub mod managers;
 pub mod structures;
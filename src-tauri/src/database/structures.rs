@@ -1,3 +1,5 @@
+use crate::lang::testing::TestCase;
+
 #[derive(sqlx::FromRow, Debug, serde::Serialize)]
 pub struct Settings {
     pub current_bot: Option<u8>,
@@ -21,8 +23,42 @@ pub struct Command {
     pub code_id: u8,
 }
 
+#[derive(sqlx::FromRow, Debug, serde::Serialize)]
+pub struct BotPlugin {
+    pub bot_id: u8,
+    pub name: String,
+}
+
 #[derive(sqlx::FromRow, Debug, serde::Serialize)]
 pub struct CodePiece {
     pub id: u8,
     pub code: String,
+    pub name: Option<String>,
+    pub bot_id: Option<u8>,
+    pub edition: u8,
+    pub language: String,
+}
+
+#[derive(sqlx::FromRow, Debug, serde::Serialize)]
+pub struct CommandTest {
+    pub id: u8,
+    pub command_id: u8,
+    pub name: String,
+    pub content: String,
+    pub author: String,
+    pub channel: String,
+    /// A JSON array of strings
+    pub expected_replies: String,
+}
+
+impl CommandTest {
+    pub fn test_case(&self) -> TestCase {
+        TestCase {
+            name: self.name.clone(),
+            content: self.content.clone(),
+            author: self.author.clone(),
+            channel: self.channel.clone(),
+            expected_replies: serde_json::from_str(&self.expected_replies).unwrap_or_default(),
+        }
+    }
 }
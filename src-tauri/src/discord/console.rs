@@ -0,0 +1,116 @@
+use std::collections::{HashMap, VecDeque};
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use tokio::sync::{mpsc, Mutex};
+
+use crate::lang::lexer::Location;
+use crate::rs2js::{self, SenderType};
+
+/// How many entries each bot's console keeps, the oldest are dropped first
+const MAX_ENTRIES: usize = 500;
+
+#[derive(serde::Serialize, Debug, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum LogLevel {
+    Print,
+    Info,
+    Warn,
+}
+
+/// Something a script wrote with `print`, `log.info` or `log.warn`
+#[derive(serde::Serialize, Debug, Clone)]
+pub struct ConsoleEntry {
+    pub level: LogLevel,
+    pub message: String,
+    /// The command which was running
+    pub command: String,
+    /// Who ran the command
+    pub user: String,
+    /// Where it was written from, Rhai's `print` does not say
+    pub location: Option<Location>,
+    /// Milliseconds since the Unix epoch
+    pub time: u64,
+}
+
+/// The consoles of every bot, by bot id, they are kept after the bot stops
+pub type Consoles = Arc<Mutex<HashMap<u8, VecDeque<ConsoleEntry>>>>;
+
+/// Writes to a bot's console on behalf of one execution of a command
+#[derive(Clone)]
+pub struct Logger {
+    pub bot_id: u8,
+    pub command: String,
+    pub user: String,
+    pub consoles: Consoles,
+    pub tx: Arc<mpsc::Sender<SenderType>>,
+}
+
+impl Logger {
+    pub async fn log(&self, level: LogLevel, message: String, location: Option<Location>) {
+        let entry = ConsoleEntry {
+            level,
+            message,
+            command: self.command.clone(),
+            user: self.user.clone(),
+            location,
+            time: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |x| x.as_millis() as u64),
+        };
+
+        {
+            let mut consoles = self.consoles.lock().await;
+            let console = consoles.entry(self.bot_id).or_default();
+            if console.len() == MAX_ENTRIES {
+                console.pop_front();
+            }
+            console.push_back(entry.clone());
+        }
+
+        let _ = self
+            .tx
+            .send(SenderType::ConsoleEntry(rs2js::ConsoleEntry {
+                bot_id: self.bot_id,
+                entry,
+            }))
+            .await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn consoles_keep_the_latest_entries() {
+        let (tx, mut rx) = mpsc::channel(MAX_ENTRIES + 10);
+        let logger = Logger {
+            bot_id: 1,
+            command: "ping".to_string(),
+            user: "someone".to_string(),
+            consoles: Consoles::default(),
+            tx: Arc::new(tx),
+        };
+
+        for i in 0..MAX_ENTRIES + 1 {
+            logger.log(LogLevel::Info, i.to_string(), None).await;
+        }
+
+        let consoles = logger.consoles.lock().await;
+        let console = &consoles[&1];
+        assert_eq!(console.len(), MAX_ENTRIES);
+        assert_eq!(console.front().unwrap().message, "1");
+        assert_eq!(console.back().unwrap().message, MAX_ENTRIES.to_string());
+        assert_eq!(console.back().unwrap().command, "ping");
+
+        // Every entry is also sent to the frontend as it is written
+        let mut sent = 0;
+        while let Ok(SenderType::ConsoleEntry(entry)) = rx.try_recv() {
+            assert_eq!(entry.bot_id, 1);
+            assert_eq!(entry.entry.message, sent.to_string());
+            sent += 1;
+        }
+        assert_eq!(sent, MAX_ENTRIES + 1);
+    }
+}
@@ -0,0 +1,187 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use serenity::async_trait;
+
+use crate::database::structures::{CodePiece, Command};
+use crate::errors::{MakerError, MakerErrorType};
+use crate::lang::{
+    builtins::BUILTINS, edition::Edition, lexer::lex, optimizer::optimize, parser::Parser,
+    profiler::Profiler, runtime::Program, scope::Scope, types, values::RuntimeValue,
+};
+
+use super::{context_name, Script, ScriptEngine};
+use crate::discord::runner::Handler;
+
+/// The language this app was made for, the only one with a debugger and profiler
+pub struct DbmEngine;
+
+impl DbmEngine {
+    /// Lexes, parses & optimizes a code piece, also used for libraries, `bindings` are
+    /// the names declared besides the builtins
+    pub fn compile_program(
+        &self,
+        code_piece: &CodePiece,
+        bindings: &[&str],
+    ) -> Result<Program, MakerError> {
+        Ok(optimized(
+            &self.parse_program(code_piece)?,
+            code_piece,
+            bindings,
+        ))
+    }
+
+    /// Lexes & parses a code piece, this is what runs while it is being debugged
+    pub fn parse_program(&self, code_piece: &CodePiece) -> Result<Program, MakerError> {
+        let context = context_name(code_piece);
+        let edition = Edition::from_number(code_piece.edition).ok_or_else(|| {
+            MakerError::new(
+                format!("{} uses unknown edition {}", context, code_piece.edition),
+                MakerErrorType::BotRunnerError,
+            )
+        })?;
+
+        let lexed = lex(&code_piece.code, context, edition)?;
+        let parsed = Parser::new(&lexed, edition).parse()?;
+
+        Ok(Program {
+            block: Arc::new(parsed),
+            edition,
+        })
+    }
+}
+
+/// Code pieces with a name are libraries, which keep all of their variables
+fn optimized(program: &Program, code_piece: &CodePiece, bindings: &[&str]) -> Program {
+    // Redeclaring any of these is an error, so those declarations can never be inlined
+    let scope = Scope::new();
+    let globals: Vec<&str> = scope
+        .variables
+        .keys()
+        .map(|x| x.as_str())
+        .chain(BUILTINS.iter().map(|x| x.name))
+        .chain(bindings.iter().copied())
+        .collect();
+    let block = (*program.block).clone();
+
+    Program {
+        block: Arc::new(optimize(
+            block,
+            &globals,
+            program.edition,
+            code_piece.name.is_some(),
+        )),
+        edition: program.edition,
+    }
+}
+
+impl ScriptEngine for DbmEngine {
+    fn compile(
+        &self,
+        code_piece: &CodePiece,
+        bindings: &[&str],
+    ) -> Result<Arc<dyn Script>, MakerError> {
+        let unoptimized = self.parse_program(code_piece)?;
+
+        Ok(Arc::new(DbmScript {
+            program: optimized(&unoptimized, code_piece, bindings),
+            unoptimized,
+        }))
+    }
+
+    fn check(&self, code: &str, edition: Edition) -> Vec<MakerError> {
+        let result = lex(code, "code".to_string(), edition)
+            .and_then(|tokens| Parser::new(&tokens, edition).parse());
+
+        match result {
+            Ok(program) => types::check(&program, BUILTINS),
+            Err(err) => vec![err],
+        }
+    }
+}
+
+pub struct DbmScript {
+    program: Program,
+    unoptimized: Program,
+}
+
+#[async_trait]
+impl Script for DbmScript {
+    async fn run(
+        &self,
+        handler: &Handler,
+        command: &Command,
+        bindings: HashMap<String, RuntimeValue>,
+    ) -> Result<(), MakerError> {
+        let mut interpreter = handler.engine.interpreter(bindings)?;
+        interpreter.set_edition(self.program.edition);
+
+        let debugging = handler
+            .attach_debugger(&mut interpreter, command.code_id)
+            .await;
+        // Time spent paused in the debugger would drown out everything else
+        if !debugging && handler.is_profiling().await {
+            interpreter.set_profiler(Profiler::new(command.name.clone()));
+        }
+
+        // Breakpoints and steps are on the code as written, which the optimizer changes
+        let program = if debugging {
+            &self.unoptimized
+        } else {
+            &self.program
+        };
+        let result = interpreter.evaluate_block(&program.block).await;
+
+        if let Some(profile) = interpreter.take_profile() {
+            handler.add_profile(command.code_id, profile).await;
+        }
+
+        if debugging {
+            drop(interpreter);
+            handler.detach_debugger().await;
+        }
+
+        result.map(|_| ())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lang::nodes::Expression;
+
+    fn code_piece(code: &str) -> CodePiece {
+        CodePiece {
+            id: 1,
+            code: code.to_string(),
+            name: None,
+            bot_id: Some(1),
+            edition: Edition::LATEST.number(),
+            language: "dbm".to_string(),
+        }
+    }
+
+    fn declarations(program: &Program) -> usize {
+        program
+            .block
+            .nodes
+            .iter()
+            .filter(|x| matches!(x, Expression::VariableDeclaration(_)))
+            .count()
+    }
+
+    #[test]
+    fn declarations_over_globals_are_not_inlined() {
+        let code_piece =
+            code_piece("var weather = 1\nvar assert = 2\nvar other = 3\nweather\nassert\nother");
+
+        let program = DbmEngine.compile_program(&code_piece, &[]).unwrap();
+        assert_eq!(declarations(&program), 1);
+
+        // Declaring over an enabled plugin has to fail when it runs, like it would unoptimized
+        let program = DbmEngine
+            .compile_program(&code_piece, &["weather"])
+            .unwrap();
+        assert_eq!(declarations(&program), 2);
+    }
+}
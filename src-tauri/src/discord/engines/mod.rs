@@ -0,0 +1,63 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use serenity::async_trait;
+
+use crate::database::structures::{CodePiece, Command};
+use crate::errors::{MakerError, MakerErrorType};
+use crate::lang::{edition::Edition, values::RuntimeValue};
+
+use super::runner::Handler;
+
+pub mod dbm;
+pub mod rhai;
+
+/// A language code pieces can be written in, chosen by their `language` column
+pub trait ScriptEngine: Send + Sync {
+    /// Compiles a code piece up front, so each message only has to run it, `bindings` are
+    /// the names declared besides the builtins, e.g. the enabled plugins
+    fn compile(
+        &self,
+        code_piece: &CodePiece,
+        bindings: &[&str],
+    ) -> Result<Arc<dyn Script>, MakerError>;
+
+    /// The errors the editor should show for `code`, `edition` only matters to dbm
+    fn check(&self, code: &str, edition: Edition) -> Vec<MakerError>;
+}
+
+/// A compiled code piece
+#[async_trait]
+pub trait Script: Send + Sync {
+    /// Runs the code piece for a command, `bindings` are the Discord natives
+    /// from `generate_from_message`, e.g. `message`
+    async fn run(
+        &self,
+        handler: &Handler,
+        command: &Command,
+        bindings: HashMap<String, RuntimeValue>,
+    ) -> Result<(), MakerError>;
+}
+
+/// The languages which can be picked for a code piece
+pub const LANGUAGES: &[&str] = &["dbm", "rhai"];
+
+pub fn engine_for(language: &str) -> Result<&'static dyn ScriptEngine, MakerError> {
+    match language {
+        "dbm" => Ok(&dbm::DbmEngine),
+        "rhai" => Ok(&rhai::RhaiEngine),
+        _ => Err(MakerError::new(
+            format!("Unknown language {}", language),
+            MakerErrorType::BotRunnerError,
+        )
+        .with_note(format!("the languages are {}", LANGUAGES.join(", ")))),
+    }
+}
+
+/// The name errors in a code piece refer to it by
+pub fn context_name(code_piece: &CodePiece) -> String {
+    match &code_piece.name {
+        Some(name) => name.clone(),
+        None => format!("code_piece_{}", code_piece.id),
+    }
+}
@@ -0,0 +1,482 @@
+use std::any::TypeId;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use ::rhai::{Dynamic, Engine, EvalAltResult, FnPtr, Map, ParseError, Position, Scope, AST};
+use serenity::async_trait;
+use tokio::runtime::Handle;
+
+use crate::database::structures::{CodePiece, Command};
+use crate::discord::runner::Handler;
+use crate::errors::{MakerError, MakerErrorType, StackFrame};
+use crate::lang::{
+    edition::Edition,
+    lexer::Location,
+    values::{self, NativeFunction, RuntimeValue},
+};
+
+use super::{context_name, Script, ScriptEngine};
+
+/// How many operations a script may run per message, so an endless loop can not hang the bot
+const MAX_OPERATIONS: u64 = 1_000_000;
+/// Natives without a signature can be called with up to this many arguments
+const MAX_ARGS: usize = 8;
+
+/// Code pieces written in Rhai, see https://rhai.rs
+///
+/// They get the same natives as dbm code pieces, `message.reply("hi")` works in both
+pub struct RhaiEngine;
+
+impl ScriptEngine for RhaiEngine {
+    fn compile(
+        &self,
+        code_piece: &CodePiece,
+        _bindings: &[&str],
+    ) -> Result<Arc<dyn Script>, MakerError> {
+        let script = Source {
+            code: code_piece.code.clone(),
+            context: context_name(code_piece).into(),
+        };
+        let ast = Engine::new()
+            .compile(&script.code)
+            .map_err(|err| script.parse_error(err))?;
+
+        Ok(Arc::new(RhaiScript {
+            ast: Arc::new(ast),
+            source: Arc::new(script),
+        }))
+    }
+
+    fn check(&self, code: &str, _edition: Edition) -> Vec<MakerError> {
+        let script = Source {
+            code: code.to_string(),
+            context: "code".into(),
+        };
+
+        match Engine::new().compile(code) {
+            Ok(_) => vec![],
+            Err(err) => vec![script.parse_error(err)],
+        }
+    }
+}
+
+/// The code of a script, to turn Rhai's positions into locations
+struct Source {
+    code: String,
+    context: Arc<str>,
+}
+
+impl Source {
+    fn location(&self, position: Position) -> Option<Location> {
+        let line = position.line()?;
+        let column = position.position().unwrap_or(1);
+
+        // Rhai counts columns in characters, locations are in bytes
+        let line_start: usize = self
+            .code
+            .split_inclusive('\n')
+            .take(line - 1)
+            .map(|x| x.len())
+            .sum();
+        let rest = &self.code[line_start..];
+        let start = line_start
+            + rest
+                .char_indices()
+                .nth(column - 1)
+                .map(|(i, _)| i)
+                .unwrap_or(rest.len());
+        let end = start
+            + self.code[start..]
+                .chars()
+                .next()
+                .map_or(0, |x| x.len_utf8());
+
+        Some(Location {
+            start,
+            end,
+            line: line as u32,
+            column: column as u32,
+            end_line: line as u32,
+            end_column: column as u32 + 1,
+            context: self.context.clone(),
+        })
+    }
+
+    fn error(&self, message: String, position: Position, error_type: MakerErrorType) -> MakerError {
+        match self.location(position) {
+            Some(location) => MakerError::lang(message, location, error_type),
+            None => MakerError::new(message, error_type),
+        }
+    }
+
+    fn parse_error(&self, err: ParseError) -> MakerError {
+        self.error(err.0.to_string(), err.1, MakerErrorType::ParserError)
+    }
+
+    fn runtime_error(&self, err: EvalAltResult) -> MakerError {
+        match err {
+            // An error from a native, which already knows what went wrong
+            EvalAltResult::ErrorRuntime(value, position) if value.is::<MakerError>() => {
+                let err = value.cast::<MakerError>();
+                match self.location(position) {
+                    Some(location) => err.with_location(location),
+                    None => err,
+                }
+            }
+            EvalAltResult::ErrorInFunctionCall(function, _, inner, position) => {
+                let mut err = self.runtime_error(*inner);
+                if let Some(location) = self.location(position) {
+                    err.stack.push(StackFrame {
+                        function,
+                        location,
+                        native: false,
+                    });
+                }
+                err
+            }
+            mut err => {
+                let position = err.take_position();
+                self.error(err.to_string(), position, MakerErrorType::RuntimeError)
+            }
+        }
+    }
+}
+
+pub struct RhaiScript {
+    ast: Arc<AST>,
+    source: Arc<Source>,
+}
+
+#[async_trait]
+impl Script for RhaiScript {
+    async fn run(
+        &self,
+        _handler: &Handler,
+        _command: &Command,
+        bindings: HashMap<String, RuntimeValue>,
+    ) -> Result<(), MakerError> {
+        let ast = self.ast.clone();
+        let source = self.source.clone();
+        let runtime = Handle::current();
+
+        // Rhai can not await, so the natives block this thread on their futures instead
+        let result =
+            tokio::task::spawn_blocking(move || execute(&ast, &source, bindings, runtime)).await;
+
+        match result {
+            Ok(result) => result,
+            Err(err) => Err(MakerError::new(
+                format!("The script stopped unexpectedly: {}", err),
+                MakerErrorType::RuntimeError,
+            )),
+        }
+    }
+}
+
+/// Runs a script on the current thread, the natives in `bindings` are run on `runtime`
+fn execute(
+    ast: &AST,
+    source: &Arc<Source>,
+    bindings: HashMap<String, RuntimeValue>,
+    runtime: Handle,
+) -> Result<(), MakerError> {
+    let mut bridge = Bridge {
+        engine: Engine::new(),
+        runtime,
+        source: source.clone(),
+        natives: 0,
+    };
+    bridge.engine.set_max_operations(MAX_OPERATIONS);
+
+    let mut scope = Scope::new();
+    for (name, value) in bindings {
+        match value {
+            // Rhai already has print, which can only be hooked into
+            RuntimeValue::NativeFunction(native) if name == "print" => {
+                bridge.register_print(native)
+            }
+            RuntimeValue::NativeFunction(native) => {
+                bridge.register_native(&name, name.clone(), native, false)
+            }
+            value => {
+                let value = bridge.bind(value, &name);
+                scope.push_dynamic(name, value);
+            }
+        }
+    }
+
+    bridge
+        .engine
+        .run_ast_with_scope(&mut scope, ast)
+        .map_err(|err| source.runtime_error(*err))
+}
+
+/// Makes values from the dbm runtime usable in a Rhai engine
+struct Bridge {
+    engine: Engine,
+    runtime: Handle,
+    source: Arc<Source>,
+    /// How many natives have been registered, to give each a unique name
+    natives: usize,
+}
+
+impl Bridge {
+    /// Converts a binding, registering the natives in it, `path` is its name in dbm, e.g. `message`
+    fn bind(&mut self, value: RuntimeValue, path: &str) -> Dynamic {
+        match value {
+            RuntimeValue::Object(object) => {
+                let mut map = Map::new();
+                for (name, value) in object.items {
+                    let value = self.bind(value, &format!("{}.{}", path, name));
+                    map.insert(name.into(), value);
+                }
+                Dynamic::from_map(map)
+            }
+            RuntimeValue::NativeFunction(native) => {
+                let name = format!("__native_{}", self.natives);
+                self.natives += 1;
+                self.register_native(&name, path.to_string(), native, true);
+
+                // Calling a property which is a function pointer calls the function
+                // with the object as its first argument
+                match FnPtr::new(name) {
+                    Ok(pointer) => Dynamic::from(pointer),
+                    Err(_) => Dynamic::UNIT,
+                }
+            }
+            value => to_dynamic(value),
+        }
+    }
+
+    /// Registers `native` as `name` for every number of arguments it can be called with,
+    /// `member` is whether it is called on an object
+    fn register_native(&mut self, name: &str, path: String, native: NativeFunction, member: bool) {
+        // Missing arguments are still registered, so the signature can say which one is missing
+        let arities = match native.signature {
+            Some(signature) => 0..=signature.params.len(),
+            None => 0..=MAX_ARGS,
+        };
+
+        for arity in arities {
+            let mut types = vec![TypeId::of::<Dynamic>(); arity];
+            if member {
+                types.insert(0, TypeId::of::<Map>());
+            }
+
+            let native = native.clone();
+            let path = path.clone();
+            let runtime = self.runtime.clone();
+            let source = self.source.clone();
+
+            self.engine
+                .register_raw_fn(name, types, move |context, args| {
+                    let call = source.location(context.call_position());
+                    let args: Vec<RuntimeValue> = args
+                        .iter()
+                        .skip(member as usize)
+                        .map(|x| to_runtime((**x).clone()))
+                        .collect();
+
+                    let result = check_args(&native, &path, args, call.as_ref())
+                        .and_then(|args| runtime.block_on((native.func)(args, call.as_ref())));
+
+                    result.map(to_dynamic).map_err(|err| {
+                        Box::new(EvalAltResult::ErrorRuntime(
+                            Dynamic::from(err),
+                            context.call_position(),
+                        ))
+                    })
+                });
+        }
+    }
+
+    /// Sends what the script prints to `native`, Rhai does not say where `print` was called
+    fn register_print(&mut self, native: NativeFunction) {
+        let runtime = self.runtime.clone();
+
+        self.engine.on_print(move |text| {
+            let args = vec![values::StringValue::make(text.to_string())];
+            let _ = runtime.block_on((native.func)(args, None));
+        });
+    }
+}
+
+/// Checks the arguments of a call to `native` like dbm code pieces do, natives expect
+/// all of them even when Rhai does not know where the call is
+fn check_args(
+    native: &NativeFunction,
+    path: &str,
+    args: Vec<RuntimeValue>,
+    call: Option<&Location>,
+) -> Result<Vec<RuntimeValue>, MakerError> {
+    let Some(signature) = native.signature else {
+        return Ok(args);
+    };
+    let location = call.cloned().unwrap_or_else(Location::no_location);
+    let locations = vec![location.clone(); args.len()];
+
+    signature.check_args(path, args, &locations, &location)
+}
+
+/// Converts a value returned by a native, functions in it can not be called from Rhai
+fn to_dynamic(value: RuntimeValue) -> Dynamic {
+    match value {
+        RuntimeValue::Null(_) | RuntimeValue::NativeFunction(_) | RuntimeValue::Function(_) => {
+            Dynamic::UNIT
+        }
+        RuntimeValue::StringValue(string) => Dynamic::from(string.value),
+        // Rhai has no types for these, so they are given as they are written, and in seconds
+        value @ (RuntimeValue::Snowflake(_) | RuntimeValue::DateTime(_)) => {
+            Dynamic::from(value.to_string())
+        }
+        RuntimeValue::Duration(duration) => {
+            Dynamic::from_float(duration.value.num_milliseconds() as f64 / 1000.0)
+        }
+        RuntimeValue::Number(number) => {
+            if number.value.fract() == 0.0 && number.value.abs() < i64::MAX as f64 {
+                Dynamic::from_int(number.value as i64)
+            } else {
+                Dynamic::from_float(number.value)
+            }
+        }
+        RuntimeValue::Boolean(boolean) => Dynamic::from_bool(boolean.value),
+        RuntimeValue::Object(object) => Dynamic::from_map(
+            object
+                .items
+                .into_iter()
+                .map(|(name, value)| (name.into(), to_dynamic(value)))
+                .collect(),
+        ),
+    }
+}
+
+/// Converts an argument given to a native, anything dbm has no type for becomes a string
+fn to_runtime(value: Dynamic) -> RuntimeValue {
+    if value.is_unit() {
+        return values::Null::make();
+    }
+    if let Ok(int) = value.as_int() {
+        return values::Number::make(int as f64);
+    }
+    if let Ok(float) = value.as_float() {
+        return values::Number::make(float);
+    }
+    if let Ok(boolean) = value.as_bool() {
+        return values::Boolean::make(boolean);
+    }
+    if value.is_map() {
+        return values::Object::make(
+            value
+                .cast::<Map>()
+                .into_iter()
+                .map(|(name, value)| (name.to_string(), to_runtime(value)))
+                .collect(),
+        );
+    }
+
+    values::StringValue::make(value.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lang::natives::NativeRegistry;
+
+    fn warn() -> NativeFunction {
+        let natives = NativeRegistry::<()>::new()
+            .register(
+                "log.warn",
+                |_, _, _| async move { Ok(values::Null::make()) },
+            )
+            .bind("log", Arc::new(()));
+
+        match natives.get("warn") {
+            Some(RuntimeValue::NativeFunction(native)) => native.clone(),
+            _ => panic!("log.warn is not a native"),
+        }
+    }
+
+    #[test]
+    fn arguments_are_checked_without_a_location() {
+        let err = check_args(&warn(), "log.warn", vec![], None).unwrap_err();
+
+        assert!(
+            err.message.starts_with("Missing argument"),
+            "{}",
+            err.message
+        );
+        assert_eq!(&*err.location.as_ref().unwrap().context, "<unknown>");
+    }
+
+    #[test]
+    fn arguments_are_checked_at_the_call() {
+        let call = Source {
+            code: "log.warn();".to_string(),
+            context: "code".into(),
+        }
+        .location(Position::new(1, 9));
+        let err = check_args(&warn(), "log.warn", vec![], call.as_ref()).unwrap_err();
+        let location = err.location.as_ref().unwrap();
+
+        assert_eq!((location.line, location.column), (1, 9));
+        assert!(check_args(&warn(), "log.warn", vec![values::Number::make(1.0)], None).is_ok());
+    }
+
+    #[test]
+    fn syntax_errors_point_into_the_script() {
+        let errors = RhaiEngine.check("let x = 1;\nlet = 2;", Edition::LATEST);
+        let location = errors[0].location.as_ref().unwrap();
+
+        assert_eq!(errors.len(), 1);
+        assert_eq!(location.line, 2);
+        assert_eq!(&*location.context, "code");
+    }
+
+    /// Runs a script with a `message` whose `reply` keeps what it was given
+    async fn run_with_message(code: &str) -> (Result<(), MakerError>, Vec<String>) {
+        let replies = Arc::new(std::sync::Mutex::new(vec![]));
+        let message = NativeRegistry::new()
+            .register(
+                "message.reply",
+                |replies: Arc<std::sync::Mutex<Vec<String>>>, args, _| async move {
+                    replies.lock().unwrap().push(args[0].to_string());
+                    Ok(values::Null::make())
+                },
+            )
+            .bind("message", replies.clone());
+        let bindings = HashMap::from([("message".to_string(), values::Object::make(message))]);
+
+        let source = Arc::new(Source {
+            code: code.to_string(),
+            context: "code".into(),
+        });
+        let ast = Engine::new().compile(code).unwrap();
+        let runtime = Handle::current();
+
+        let result = tokio::task::spawn_blocking(move || execute(&ast, &source, bindings, runtime))
+            .await
+            .unwrap();
+        let replies = replies.lock().unwrap().clone();
+        (result, replies)
+    }
+
+    #[tokio::test]
+    async fn natives_can_be_called_as_methods() {
+        let (result, replies) = run_with_message("message.reply(\"x\");\nmessage.reply(1);").await;
+
+        assert!(result.is_ok());
+        assert_eq!(replies, vec!["x", "1"]);
+
+        let (result, replies) = run_with_message("message.reply();").await;
+        let err = result.unwrap_err();
+        let location = err.location.as_ref().unwrap();
+
+        assert!(
+            err.message.starts_with("Missing argument"),
+            "{}",
+            err.message
+        );
+        assert_eq!((location.line, location.column), (1, 9));
+        assert!(replies.is_empty());
+    }
+}
@@ -1,2 +1,7 @@
+pub mod console;
+pub mod engines;
 pub mod native_functions;
+pub mod plugins;
 pub mod runner;
+pub mod symbols;
+pub mod testing;
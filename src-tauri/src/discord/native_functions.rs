@@ -1,114 +1,116 @@
 use std::collections::HashMap;
-use std::future::Future;
-use std::pin::Pin;
 use std::sync::Arc;
+use std::sync::OnceLock;
 
 use serenity::model::prelude::*;
 use serenity::prelude::*;
-use tokio::sync::Mutex;
 
+use crate::discord::console::{LogLevel, Logger};
 use crate::errors::MakerError;
 use crate::errors::MakerErrorType;
 use crate::lang;
-use crate::lang::lexer::Location;
+use crate::lang::natives::NativeRegistry;
 use crate::lang::values;
 use crate::lang::values::RuntimeValue;
 use crate::lang::values::StringValue; // Ensure this is imported
 
-macro_rules! create_maker_future {
-    ($ctx:expr, $e:expr) => {{
-        let ctx = Arc::clone(&$ctx);
-
-        let func = move |args: Vec<RuntimeValue>| {
-            let ctx2 = Arc::clone(&ctx);
-            let future = async move { ($e)(ctx2, args).await };
-
-            let value: Pin<
-                Box<dyn Future<Output = Result<lang::values::RuntimeValue, MakerError>> + Send>,
-            > = Box::pin(future);
+struct MakerContext {
+    pub ctx: Arc<Context>,
+    pub msg: Arc<Message>,
+    pub logger: Logger,
+}
 
-            Ok(lang::values::RuntimeValue::Future(
-                lang::values::MakerFuture {
-                    value: Arc::new(Mutex::new(value)),
-                },
-            ))
-        };
+/// The implementations of the natives declared in `BUILTINS`
+fn registry() -> &'static NativeRegistry<MakerContext> {
+    static REGISTRY: OnceLock<NativeRegistry<MakerContext>> = OnceLock::new();
 
-        lang::values::RuntimeValue::NativeFunction(lang::values::NativeFunction {
-            func: Arc::from(func),
-        })
-    }};
+    REGISTRY.get_or_init(|| {
+        NativeRegistry::<MakerContext>::new()
+            .register("message.reply", |ctx2, args, _| async move {
+                let result = ctx2
+                    .msg
+                    .reply(&ctx2.ctx.http, args[0].to_string())
+                    .await
+                    .map_err(|err| {
+                        MakerError::from(Some(Box::from(err)), MakerErrorType::RuntimeError)
+                    })?;
+                let _msg2 =
+                    generate_from_message(ctx2.ctx.clone(), Arc::from(result), ctx2.logger.clone());
+                Ok(lang::values::Null::make())
+            })
+            .register("print", |ctx2, args, location| async move {
+                ctx2.logger
+                    .log(LogLevel::Print, args[0].to_string(), location)
+                    .await;
+                Ok(lang::values::Null::make())
+            })
+            .register("log.info", |ctx2, args, location| async move {
+                ctx2.logger
+                    .log(LogLevel::Info, args[0].to_string(), location)
+                    .await;
+                Ok(lang::values::Null::make())
+            })
+            .register("log.warn", |ctx2, args, location| async move {
+                ctx2.logger
+                    .log(LogLevel::Warn, args[0].to_string(), location)
+                    .await;
+                Ok(lang::values::Null::make())
+            })
+    })
 }
 
-macro_rules! get_arg {
-    ($args:ident, $index:expr, $type:ident) => {{
-        if $index >= $args.len() {
-            return Err(MakerError::lang(
-                format!(
-                    "Not enough args provided! Missing arg at argument {}",
-                    $index
-                ),
-                Location::no_location(),
-                MakerErrorType::RuntimeError,
-            ));
-        }
-        match &$args[$index] {
-            RuntimeValue::$type(a) => a.clone(),
-            _ => {
-                return Err(MakerError::lang(
-                    format!("Invalid type for argument {}", $index),
-                    Location::no_location(),
-                    MakerErrorType::RuntimeError,
-                ))
-            }
-        }
-    }};
+/// Everything a command can use which comes from the message that ran it, by name
+pub fn generate_bindings(
+    ctx: Arc<Context>,
+    msg: Arc<Message>,
+    logger: Logger,
+) -> HashMap<String, RuntimeValue> {
+    let context = Arc::from(MakerContext {
+        ctx: ctx.clone(),
+        msg: msg.clone(),
+        logger: logger.clone(),
+    });
+    let mut bindings = HashMap::new();
 
-    ($args:ident, $index:expr) => {{
-        if $index >= $args.len() {
-            return Err(MakerError::lang(
-                format!(
-                    "Not enough args provided! Missing arg at argument {}",
-                    $index
-                ),
-                Location::no_location(),
-                MakerErrorType::RuntimeError,
-            ));
-        }
-        $args[$index].clone()
-    }};
-}
+    if let Some(print) = registry().get("print", context.clone()) {
+        bindings.insert("print".to_string(), print);
+    }
+    bindings.insert(
+        "log".to_string(),
+        values::Object::make(registry().bind("log", context)),
+    );
+    bindings.insert(
+        "message".to_string(),
+        generate_from_message(ctx, msg, logger),
+    );
 
-struct MakerContext {
-    pub ctx: Arc<Context>,
-    pub msg: Arc<Message>,
+    bindings
 }
 
-pub fn generate_from_message(ctx: Arc<Context>, msg: Arc<Message>) -> RuntimeValue {
-    let context = Arc::from(MakerContext { ctx, msg });
-    values::Object::make(HashMap::from([
-        (
-            "reply".to_string(),
-            create_maker_future!(
-                Arc::clone(&context),
-                |ctx2: Arc<MakerContext>, args: Vec<RuntimeValue>| async move {
-                    let result = ctx2
-                        .msg
-                        .reply(&ctx2.ctx.http, get_arg!(args, 0).to_string())
-                        .await
-                        .unwrap();
-                    let _msg2 = generate_from_message(ctx2.ctx.clone(), Arc::from(result));
-                    Ok(lang::values::Null::make())
-                }
-            ),
-        ),
-        (
-            "id".to_string(),
-            StringValue::make(context.msg.id.to_string()),
-        ),
-        (
-            "content".to_string(),
-            StringValue::make(context.msg.content.to_string()),
-        ),
-    ]))
+fn generate_from_message(ctx: Arc<Context>, msg: Arc<Message>, logger: Logger) -> RuntimeValue {
+    let context = Arc::from(MakerContext { ctx, msg, logger });
+    let mut items = registry().bind("message", context.clone());
+
+    items.insert(
+        "id".to_string(),
+        values::Snowflake::make(context.msg.id.get()),
+    );
+    items.insert(
+        "content".to_string(),
+        StringValue::make(context.msg.content.to_string()),
+    );
+    items.insert(
+        "author".to_string(),
+        StringValue::make(context.msg.author.name.to_string()),
+    );
+    items.insert(
+        "author_id".to_string(),
+        values::Snowflake::make(context.msg.author.id.get()),
+    );
+    items.insert(
+        "channel".to_string(),
+        values::Snowflake::make(context.msg.channel_id.get()),
+    );
+
+    values::Object::make(items)
 }
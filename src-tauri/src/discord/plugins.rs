@@ -0,0 +1,437 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use serde_json::{Map, Number, Value};
+use wasmi::{
+    Config, Engine, Linker, Memory, Module, Store, StoreLimits, StoreLimitsBuilder, TypedFunc,
+};
+
+use crate::database::database::data_path;
+use crate::errors::{MakerError, MakerErrorType};
+use crate::lang::values::{self, NativeFunction, RuntimeValue};
+
+/// Where plugins are loaded from, each `name.wasm` is available to scripts as `name`
+pub fn plugins_directory() -> PathBuf {
+    data_path("plugins")
+}
+
+/// The most memory a plugin can grow to
+const MAX_MEMORY: usize = 16 * 1024 * 1024;
+/// Roughly how many instructions a plugin can run per call, so a loop can not hang the bot
+const FUEL_PER_CALL: u64 = 10_000_000;
+/// Exports starting with this are natives, `dbm_native_reverse` is called as `name.reverse`
+const NATIVE_PREFIX: &str = "dbm_native_";
+
+/// A plugin in the plugins directory, and whether the bot uses it
+#[derive(serde::Serialize, Debug, Clone)]
+pub struct PluginInfo {
+    pub name: String,
+    pub natives: Vec<String>,
+    pub enabled: bool,
+    /// Why the plugin could not be loaded, it has no natives then
+    pub error: Option<String>,
+}
+
+/// A loaded WebAssembly module, which can only touch its own memory
+///
+/// The ABI is small, a plugin exports:
+/// - `memory`
+/// - `dbm_alloc(len: i32) -> i32`, giving the host somewhere to write the arguments
+/// - `dbm_native_<name>(ptr: i32, len: i32) -> i64` for each native, the arguments are
+///   a JSON array at `ptr..ptr + len`, and it returns `(ptr << 32) | len` of a JSON
+///   `{"ok": value}` or `{"error": "message"}`
+///
+/// Plugins can not import anything, so they have no way to reach outside of the sandbox
+struct Plugin {
+    name: String,
+    store: Store<StoreLimits>,
+    memory: Memory,
+    alloc: TypedFunc<i32, i32>,
+    natives: HashMap<String, TypedFunc<(i32, i32), i64>>,
+}
+
+impl Plugin {
+    fn load(directory: &Path, name: &str) -> Result<Plugin, MakerError> {
+        if !is_identifier(name) {
+            return Err(plugin_error(
+                name,
+                "the name has to be usable as a variable, so only letters, digits and _",
+            ));
+        }
+
+        let path = directory.join(format!("{}.wasm", name));
+        let bytes = fs::read(path).map_err(|err| plugin_error(name, err))?;
+        Plugin::from_bytes(name, &bytes)
+    }
+
+    fn from_bytes(name: &str, bytes: &[u8]) -> Result<Plugin, MakerError> {
+        let mut config = Config::default();
+        config.consume_fuel(true);
+        let engine = Engine::new(&config);
+        let module = Module::new(&engine, bytes).map_err(|err| plugin_error(name, err))?;
+
+        if let Some(import) = module.imports().next() {
+            return Err(plugin_error(
+                name,
+                format!(
+                    "plugins can not import anything, but it imports {}.{}",
+                    import.module(),
+                    import.name()
+                ),
+            ));
+        }
+
+        let mut store = Store::new(
+            &engine,
+            StoreLimitsBuilder::new()
+                .memory_size(MAX_MEMORY)
+                .instances(1)
+                .build(),
+        );
+        store.limiter(|limits| limits);
+        store
+            .set_fuel(FUEL_PER_CALL)
+            .map_err(|err| plugin_error(name, err))?;
+
+        let instance = Linker::<StoreLimits>::new(&engine)
+            .instantiate(&mut store, &module)
+            .and_then(|x| x.start(&mut store))
+            .map_err(|err| plugin_error(name, err))?;
+
+        let memory = instance
+            .get_memory(&store, "memory")
+            .ok_or_else(|| plugin_error(name, "it does not export its memory"))?;
+        let alloc = instance
+            .get_typed_func::<i32, i32>(&store, "dbm_alloc")
+            .map_err(|err| plugin_error(name, format!("dbm_alloc: {}", err)))?;
+
+        let mut natives = HashMap::new();
+        for export in module.exports() {
+            if let Some(native) = export.name().strip_prefix(NATIVE_PREFIX) {
+                let func = instance
+                    .get_typed_func::<(i32, i32), i64>(&store, export.name())
+                    .map_err(|err| plugin_error(name, format!("{}: {}", export.name(), err)))?;
+                natives.insert(native.to_string(), func);
+            }
+        }
+
+        Ok(Plugin {
+            name: name.to_string(),
+            store,
+            memory,
+            alloc,
+            natives,
+        })
+    }
+
+    fn call(&mut self, native: &str, args: Vec<RuntimeValue>) -> Result<RuntimeValue, MakerError> {
+        let func = self.natives[native];
+        let path = format!("{}.{}", self.name, native);
+        let error = |err: String| {
+            MakerError::new(
+                format!("{} failed: {}", path, err),
+                MakerErrorType::RuntimeError,
+            )
+        };
+
+        // Each call gets the same budget, whatever the previous ones used
+        self.store
+            .set_fuel(FUEL_PER_CALL)
+            .map_err(|err| error(err.to_string()))?;
+
+        let input = Value::Array(args.iter().map(to_json).collect()).to_string();
+        let ptr = self
+            .alloc
+            .call(&mut self.store, input.len() as i32)
+            .map_err(|err| error(err.to_string()))?;
+        self.memory
+            .write(&mut self.store, ptr as u32 as usize, input.as_bytes())
+            .map_err(|err| error(err.to_string()))?;
+
+        let packed = func
+            .call(&mut self.store, (ptr, input.len() as i32))
+            .map_err(|err| error(err.to_string()))? as u64;
+
+        // Read it in place, the plugin could claim any length
+        let start = (packed >> 32) as usize;
+        let end = start + (packed & 0xFFFF_FFFF) as usize;
+        let output = self
+            .memory
+            .data(&self.store)
+            .get(start..end)
+            .ok_or_else(|| error("the output is outside of its memory".to_string()))?;
+
+        let output: Value = serde_json::from_slice(output)
+            .map_err(|err| error(format!("invalid output, {}", err)))?;
+        match output {
+            Value::Object(mut result) => match (result.remove("ok"), result.remove("error")) {
+                (_, Some(message)) => Err(error(match message {
+                    Value::String(message) => message,
+                    message => message.to_string(),
+                })),
+                (Some(value), None) => Ok(from_json(value)),
+                (None, None) => Ok(values::Null::make()),
+            },
+            _ => Err(error("the output has to be an object".to_string())),
+        }
+    }
+}
+
+fn plugin_error(name: &str, err: impl ToString) -> MakerError {
+    MakerError::new(
+        format!("Failed to load plugin {}: {}", name, err.to_string()),
+        MakerErrorType::PluginError,
+    )
+}
+
+fn is_identifier(name: &str) -> bool {
+    !name.is_empty()
+        && !name.starts_with(|x: char| x.is_ascii_digit())
+        && name.chars().all(|x| x.is_ascii_alphanumeric() || x == '_')
+}
+
+/// Loads a plugin, giving an object of its natives, e.g. `text.reverse`
+///
+/// The plugin keeps its memory between calls until the object is dropped
+pub fn load(name: &str) -> Result<RuntimeValue, MakerError> {
+    let plugin = Plugin::load(&plugins_directory(), name)?;
+    let names: Vec<String> = plugin.natives.keys().cloned().collect();
+    let plugin = Arc::new(Mutex::new(plugin));
+
+    let natives = names
+        .into_iter()
+        .map(|native| {
+            let plugin = plugin.clone();
+            let path = format!("{}.{}", name, native);
+            let func = NativeFunction::make_sync({
+                let native = native.clone();
+                move |args| match plugin.lock() {
+                    Ok(mut plugin) => plugin.call(&native, args),
+                    // A call panicked while holding the lock, so its memory could be in any state
+                    Err(_) => Err(MakerError::new(
+                        format!("{} can not be called anymore, as the plugin crashed", path),
+                        MakerErrorType::RuntimeError,
+                    )),
+                }
+            });
+            (native, func)
+        })
+        .collect();
+
+    Ok(values::Object::make(natives))
+}
+
+/// Every plugin in the plugins directory, `enabled` are the names of the ones the bot uses
+pub fn list(enabled: &[String]) -> Vec<PluginInfo> {
+    list_in(&plugins_directory(), enabled)
+}
+
+/// A plugin which fails to load is still listed, with why, so the others can be used
+fn list_in(directory: &Path, enabled: &[String]) -> Vec<PluginInfo> {
+    let entries = match fs::read_dir(directory) {
+        Ok(entries) => entries,
+        // Nobody has added a plugin yet
+        Err(_) => return vec![],
+    };
+
+    let mut plugins = vec![];
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if path.extension().and_then(|x| x.to_str()) != Some("wasm") {
+            continue;
+        }
+
+        let name = match path.file_stem().and_then(|x| x.to_str()) {
+            Some(name) => name.to_string(),
+            None => continue,
+        };
+        let (natives, error) = match Plugin::load(directory, &name) {
+            Ok(plugin) => {
+                let mut natives: Vec<String> = plugin.natives.into_keys().collect();
+                natives.sort();
+                (natives, None)
+            }
+            Err(err) => (vec![], Some(err.message.clone())),
+        };
+
+        plugins.push(PluginInfo {
+            enabled: enabled.contains(&name),
+            name,
+            natives,
+            error,
+        });
+    }
+
+    plugins.sort_by(|a, b| a.name.cmp(&b.name));
+    plugins
+}
+
+fn to_json(value: &RuntimeValue) -> Value {
+    match value {
+        RuntimeValue::Null(_) | RuntimeValue::NativeFunction(_) | RuntimeValue::Function(_) => {
+            Value::Null
+        }
+        RuntimeValue::StringValue(string) => Value::String(string.value.clone()),
+        RuntimeValue::Snowflake(_) | RuntimeValue::DateTime(_) => Value::String(value.to_string()),
+        RuntimeValue::Duration(duration) => {
+            Number::from_f64(duration.value.num_milliseconds() as f64 / 1000.0)
+                .map(Value::Number)
+                .unwrap_or(Value::Null)
+        }
+        RuntimeValue::Number(number) => Number::from_f64(number.value)
+            .map(Value::Number)
+            .unwrap_or(Value::Null),
+        RuntimeValue::Boolean(boolean) => Value::Bool(boolean.value),
+        RuntimeValue::Object(object) => Value::Object(
+            object
+                .items
+                .iter()
+                .map(|(key, value)| (key.clone(), to_json(value)))
+                .collect::<Map<String, Value>>(),
+        ),
+    }
+}
+
+/// Arrays have no dbm type yet, so they become objects keyed by their index
+fn from_json(value: Value) -> RuntimeValue {
+    match value {
+        Value::Null => values::Null::make(),
+        Value::Bool(boolean) => values::Boolean::make(boolean),
+        Value::Number(number) => values::Number::make(number.as_f64().unwrap_or(f64::NAN)),
+        Value::String(string) => values::StringValue::make(string),
+        Value::Array(items) => values::Object::make(
+            items
+                .into_iter()
+                .enumerate()
+                .map(|(i, value)| (i.to_string(), from_json(value)))
+                .collect(),
+        ),
+        Value::Object(items) => values::Object::make(
+            items
+                .into_iter()
+                .map(|(key, value)| (key, from_json(value)))
+                .collect(),
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `echo` replies with its arguments, `fail` always fails, and `spin` never returns
+    const PLUGIN: &str = r#"
+        (module
+            (memory (export "memory") 1)
+            (data (i32.const 10) "{\"ok\":")
+            (data (i32.const 1000) "{\"error\":\"no\"}")
+            (func (export "dbm_alloc") (param i32) (result i32)
+                i32.const 16)
+            (func (export "dbm_native_echo") (param $ptr i32) (param $len i32) (result i64)
+                (i32.store8 (i32.add (local.get $ptr) (local.get $len)) (i32.const 125))
+                (i64.or
+                    (i64.shl (i64.const 10) (i64.const 32))
+                    (i64.extend_i32_u (i32.add (local.get $len) (i32.const 7)))))
+            (func (export "dbm_native_fail") (param i32 i32) (result i64)
+                (i64.or (i64.shl (i64.const 1000) (i64.const 32)) (i64.const 14)))
+            (func (export "dbm_native_spin") (param i32 i32) (result i64)
+                (loop $forever (br $forever))
+                (i64.const 0))
+            (func (export "dbm_native_outside") (param i32 i32) (result i64)
+                (i64.or (i64.shl (i64.const 65530) (i64.const 32)) (i64.const 16))))
+    "#;
+
+    fn plugin(wat: &str) -> Result<Plugin, MakerError> {
+        Plugin::from_bytes("text", &wat::parse_str(wat).unwrap())
+    }
+
+    #[test]
+    fn natives_get_their_arguments_as_json() {
+        let mut plugin = plugin(PLUGIN).unwrap();
+        let mut natives: Vec<&String> = plugin.natives.keys().collect();
+        natives.sort();
+        assert_eq!(natives, vec!["echo", "fail", "outside", "spin"]);
+
+        let args = vec![
+            values::Number::make(1.5),
+            values::StringValue::make("a".to_string()),
+            values::Boolean::make(true),
+        ];
+        let RuntimeValue::Object(result) = plugin.call("echo", args).unwrap() else {
+            panic!("arrays become objects");
+        };
+
+        assert_eq!(result.items["0"].to_string(), "1.5");
+        assert_eq!(result.items["1"].to_string(), "a");
+        assert_eq!(result.items["2"].to_string(), "true");
+    }
+
+    #[test]
+    fn failing_natives_are_errors() {
+        let mut plugin = plugin(PLUGIN).unwrap();
+
+        assert_eq!(
+            plugin.call("fail", vec![]).unwrap_err().message,
+            "text.fail failed: no"
+        );
+        assert_eq!(
+            plugin.call("outside", vec![]).unwrap_err().message,
+            "text.outside failed: the output is outside of its memory"
+        );
+    }
+
+    #[test]
+    fn every_call_has_its_own_fuel() {
+        let mut plugin = plugin(PLUGIN).unwrap();
+
+        let err = plugin.call("spin", vec![]).unwrap_err();
+        assert!(err.message.starts_with("text.spin failed: "));
+
+        assert!(plugin.call("echo", vec![]).is_ok());
+    }
+
+    #[test]
+    fn plugins_can_not_leave_the_sandbox() {
+        let err = plugin("(module (import \"env\" \"f\" (func)))")
+            .err()
+            .unwrap();
+        assert_eq!(
+            err.message,
+            "Failed to load plugin text: plugins can not import anything, but it imports env.f"
+        );
+
+        // 17 MiB
+        let err = plugin("(module (memory (export \"memory\") 272))")
+            .err()
+            .unwrap();
+        assert_eq!(
+            err.message,
+            "Failed to load plugin text: out of bounds memory allocation"
+        );
+    }
+
+    #[test]
+    fn plugins_which_fail_to_load_are_listed_with_why() {
+        let directory = std::env::temp_dir().join("dbm-plugins-list");
+        let _ = fs::remove_dir_all(&directory);
+        fs::create_dir_all(&directory).unwrap();
+        fs::write(directory.join("text.wasm"), wat::parse_str(PLUGIN).unwrap()).unwrap();
+        fs::write(directory.join("broken.wasm"), "not wasm").unwrap();
+        fs::write(directory.join("notes.txt"), "").unwrap();
+
+        let plugins = list_in(&directory, &["text".to_string()]);
+        let names: Vec<&str> = plugins.iter().map(|x| x.name.as_str()).collect();
+        assert_eq!(names, vec!["broken", "text"]);
+
+        assert!(plugins[0].natives.is_empty() && !plugins[0].enabled);
+        assert!(plugins[0]
+            .error
+            .as_ref()
+            .unwrap()
+            .starts_with("Failed to load plugin broken: "));
+        assert_eq!(plugins[1].natives, vec!["echo", "fail", "outside", "spin"]);
+        assert!(plugins[1].enabled && plugins[1].error.is_none());
+    }
+}
@@ -1,7 +1,13 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
 
-use lang::{lexer::lex, parser::Parser, runtime::Interpreter, scope::Scope};
+use lang::{
+    debugger::{DebugCommand, Debugger},
+    engine::Engine,
+    profiler::Profile,
+    runtime::Interpreter,
+    values::RuntimeValue,
+};
 use log::info;
 use serenity::all::GatewayIntents;
 use serenity::async_trait;
@@ -14,9 +20,13 @@ use tokio::task; // Ensure this is imported
 
 use crate::database::database::Database;
 use crate::database::structures::Bot;
+use crate::database::structures::BotPlugin;
 use crate::database::structures::CodePiece;
 use crate::database::structures::Command;
-use crate::discord::native_functions::generate_from_message;
+use crate::discord::console::{ConsoleEntry, Consoles, Logger};
+use crate::discord::engines::{dbm::DbmEngine, engine_for, Script};
+use crate::discord::native_functions::generate_bindings;
+use crate::discord::plugins;
 use crate::errors::MakerError;
 use crate::errors::MakerErrorType;
 use crate::lang;
@@ -24,9 +34,178 @@ use crate::rs2js;
 use crate::rs2js::tx_error;
 use crate::rs2js::SenderType;
 
-struct Handler {
+pub struct Handler {
+    bot_id: u8,
     commands: Vec<Command>,
+    programs: HashMap<u8, Arc<dyn Script>>,
+    /// Has the libraries, which dbm code pieces can import
+    pub(super) engine: Engine,
+    /// The natives of the bot's plugins, by plugin name
+    plugins: HashMap<String, RuntimeValue>,
+    tx: Arc<mpsc::Sender<SenderType>>,
+    debug_sessions: Arc<tokio::sync::Mutex<HashMap<u8, DebugSession>>>,
+    profiling_sessions: Arc<tokio::sync::Mutex<HashMap<u8, ProfilingSession>>>,
+    consoles: Consoles,
+}
+
+/// The profiles of a bot's executions, by code piece
+#[derive(Default)]
+pub struct ProfilingSession {
+    /// Whether new executions are profiled, the profiles are kept after stopping
+    pub enabled: bool,
+    pub profiles: HashMap<u8, Profile>,
+}
+
+/// A bot which is being debugged, executions of `code_id` run with the debugger
+pub struct DebugSession {
+    pub code_id: u8,
+    pub breakpoints: HashSet<u32>,
+    pub pause_on_start: bool,
+    /// Talks to the execution which is currently being debugged, if any
+    commands: Option<mpsc::UnboundedSender<DebugCommand>>,
+}
+
+impl Handler {
+    /// Gives the interpreter a debugger if this code piece is being debugged,
+    /// returns whether it did
+    pub(super) async fn attach_debugger(&self, interpreter: &mut Interpreter, code_id: u8) -> bool {
+        let mut sessions = self.debug_sessions.lock().await;
+
+        // Only one execution is debugged at a time
+        let session = match sessions.get_mut(&self.bot_id) {
+            Some(session) if session.code_id == code_id && session.commands.is_none() => session,
+            _ => return false,
+        };
+
+        let (paused_tx, mut paused_rx) = mpsc::unbounded_channel();
+        let (commands_tx, commands_rx) = mpsc::unbounded_channel();
+        session.commands = Some(commands_tx);
+
+        interpreter.set_debugger(Debugger::new(
+            session.breakpoints.clone(),
+            session.pause_on_start,
+            paused_tx,
+            commands_rx,
+        ));
+
+        // Forward the pauses to the frontend, this stops once the debugger is dropped
+        let tx = self.tx.clone();
+        let bot_id = self.bot_id;
+        tokio::spawn(async move {
+            while let Some(snapshot) = paused_rx.recv().await {
+                let _ = tx
+                    .send(SenderType::DebugPaused(rs2js::DebugPaused {
+                        bot_id,
+                        code_id,
+                        snapshot,
+                    }))
+                    .await;
+            }
+        });
+
+        true
+    }
+
+    pub(super) async fn is_profiling(&self) -> bool {
+        match self.profiling_sessions.lock().await.get(&self.bot_id) {
+            Some(session) => session.enabled,
+            None => false,
+        }
+    }
+
+    /// Adds the profile of an execution to the bot's session and sends the result to the frontend
+    pub(super) async fn add_profile(&self, code_id: u8, profile: Profile) {
+        let merged = {
+            let mut sessions = self.profiling_sessions.lock().await;
+            let session = sessions.entry(self.bot_id).or_default();
+
+            match session.profiles.get_mut(&code_id) {
+                Some(existing) => existing.merge(profile),
+                None => {
+                    session.profiles.insert(code_id, profile);
+                }
+            }
+
+            session.profiles[&code_id].clone()
+        };
+
+        let _ = self
+            .tx
+            .send(SenderType::ProfileUpdate(rs2js::ProfileUpdate {
+                bot_id: self.bot_id,
+                code_id,
+                profile: merged,
+            }))
+            .await;
+    }
+
+    pub(super) async fn detach_debugger(&self) {
+        if let Some(session) = self.debug_sessions.lock().await.get_mut(&self.bot_id) {
+            session.commands = None;
+        }
+
+        let _ = self
+            .tx
+            .send(SenderType::DebugFinished(rs2js::DebugFinished {
+                bot_id: self.bot_id,
+            }))
+            .await;
+    }
+}
+
+/// Compiles each code piece with the engine of its language, so each message only has to run it
+fn compile_code_pieces(
     code_pieces: Vec<CodePiece>,
+    plugins: &HashMap<String, RuntimeValue>,
+) -> Result<HashMap<u8, Arc<dyn Script>>, MakerError> {
+    let mut programs = HashMap::new();
+    let bindings: Vec<&str> = plugins.keys().map(|x| x.as_str()).collect();
+
+    for code_piece in code_pieces {
+        let engine = engine_for(&code_piece.language)?;
+        programs.insert(code_piece.id, engine.compile(&code_piece, &bindings)?);
+    }
+
+    Ok(programs)
+}
+
+/// Libraries only have their own globals, so `message` is not declared in them
+pub(super) fn compile_libraries(libraries: Vec<CodePiece>) -> Result<Engine, MakerError> {
+    let mut engine = Engine::new();
+
+    for library in libraries {
+        if library.language != "dbm" {
+            return Err(MakerError::new(
+                format!(
+                    "Library {} is written in {}, but libraries have to be written in dbm",
+                    library.name.unwrap_or_default(),
+                    library.language
+                ),
+                MakerErrorType::BotRunnerError,
+            ));
+        }
+
+        // Libraries can not see the plugins, only their own globals
+        let program = DbmEngine.compile_program(&library, &[])?;
+        if let Some(name) = library.name {
+            engine.register_program(&name, program);
+        }
+    }
+
+    Ok(engine)
+}
+
+/// Plugins are loaded once per run, so they keep their memory between messages
+pub(super) fn load_plugins(
+    enabled: Vec<BotPlugin>,
+) -> Result<HashMap<String, RuntimeValue>, MakerError> {
+    let mut loaded = HashMap::new();
+
+    for plugin in enabled {
+        loaded.insert(plugin.name.clone(), plugins::load(&plugin.name)?);
+    }
+
+    Ok(loaded)
 }
 
 #[async_trait]
@@ -40,7 +219,11 @@ impl EventHandler for Handler {
         }
 
         // Get the content & args
-        let content: &str = &(&msg.content.to_lowercase())[prefix.len()..];
+        let lowercase = msg.content.to_lowercase();
+        let content: &str = match lowercase.strip_prefix(prefix) {
+            Some(content) => content,
+            None => return,
+        };
         let mut args = content.split(' ').collect::<Vec<&str>>();
         let cmd_name = args.remove(0);
 
@@ -50,28 +233,31 @@ impl EventHandler for Handler {
         if let Some(cmd) = cmd {
             info!("Executing command {}", cmd.name);
 
-            // Get the code contents
-            let contents = self
-                .code_pieces
-                .iter()
-                .find(|x| x.id == cmd.code_id)
-                .unwrap();
-
-            // Lex & Parse
-            let lexed = lex(contents.code.clone(), "test".to_string());
-            let parsed = Parser::new(lexed.unwrap()).parse().unwrap();
-            let mut scope = Scope::new();
+            // Get the compiled code
+            let script = match self.programs.get(&cmd.code_id) {
+                Some(script) => script.clone(),
+                None => return,
+            };
 
-            // Get the funcs for the message
-            let funcs = generate_from_message(Arc::from(_ctx), Arc::from(msg));
-            scope.declare("message", funcs).unwrap();
+            // Get the funcs for the message, and run it with the engine of its language
+            let logger = Logger {
+                bot_id: self.bot_id,
+                command: cmd.name.clone(),
+                user: msg.author.name.clone(),
+                consoles: self.consoles.clone(),
+                tx: self.tx.clone(),
+            };
+            let mut bindings = self.plugins.clone();
+            bindings.extend(generate_bindings(Arc::from(_ctx), Arc::from(msg), logger));
+            let result = script.run(self, cmd, bindings).await;
 
-            // Run it
-            let mut interpreter = Interpreter::new(scope);
-            interpreter
-                .evaluate(lang::nodes::Expression::Block(parsed))
-                .await
-                .unwrap();
+            if let Err(err) = result {
+                info!("Command {} failed: {}", cmd.name, err);
+                let _ = self
+                    .tx
+                    .send(SenderType::Error(rs2js::Error { error: err }))
+                    .await;
+            }
         }
     }
 }
@@ -82,8 +268,14 @@ pub struct RunnerContext {
     pub bot: Bot,
 }
 
+/// The task of a running bot and the sender which stops it
+type RunningBot = (task::JoinHandle<()>, oneshot::Sender<()>);
+
 pub struct BotRunner {
-    running_bots: Arc<tokio::sync::Mutex<HashMap<u8, (task::JoinHandle<()>, oneshot::Sender<()>)>>>,
+    running_bots: Arc<tokio::sync::Mutex<HashMap<u8, RunningBot>>>,
+    debug_sessions: Arc<tokio::sync::Mutex<HashMap<u8, DebugSession>>>,
+    profiling_sessions: Arc<tokio::sync::Mutex<HashMap<u8, ProfilingSession>>>,
+    consoles: Consoles,
     tx: Arc<mpsc::Sender<SenderType>>,
 }
 
@@ -91,10 +283,109 @@ impl BotRunner {
     pub fn new(tx: mpsc::Sender<SenderType>) -> BotRunner {
         BotRunner {
             running_bots: Arc::new(tokio::sync::Mutex::new(HashMap::new())),
+            debug_sessions: Arc::new(tokio::sync::Mutex::new(HashMap::new())),
+            profiling_sessions: Arc::new(tokio::sync::Mutex::new(HashMap::new())),
+            consoles: Arc::new(tokio::sync::Mutex::new(HashMap::new())),
             tx: Arc::new(tx),
         }
     }
 
+    /// Makes the next executions of the code piece run with the debugger
+    pub async fn start_debugging(
+        &self,
+        bot_id: u8,
+        code_id: u8,
+        breakpoints: Vec<u32>,
+        pause_on_start: bool,
+    ) {
+        info!("Debugging code piece {} of bot {}", code_id, bot_id);
+        self.stop_debugging(bot_id).await;
+
+        self.debug_sessions.lock().await.insert(
+            bot_id,
+            DebugSession {
+                code_id,
+                breakpoints: breakpoints.into_iter().collect(),
+                pause_on_start,
+                commands: None,
+            },
+        );
+    }
+
+    pub async fn stop_debugging(&self, bot_id: u8) {
+        let removed = self.debug_sessions.lock().await.remove(&bot_id);
+
+        // Stop whatever is paused right now too
+        if let Some(DebugSession {
+            commands: Some(commands),
+            ..
+        }) = removed
+        {
+            let _ = commands.send(DebugCommand::Stop);
+        }
+    }
+
+    /// Resumes the execution which is paused in the debugger
+    pub async fn debug_resume(&self, bot_id: u8, command: DebugCommand) -> Result<(), MakerError> {
+        let sessions = self.debug_sessions.lock().await;
+        let commands = sessions.get(&bot_id).and_then(|x| x.commands.as_ref());
+
+        match commands {
+            Some(commands) => commands.send(command).map_err(|_| {
+                MakerError::new(
+                    "The execution has already finished",
+                    MakerErrorType::BotRunnerError,
+                )
+            }),
+            None => Err(MakerError::new(
+                format!("Bot {} is not being debugged", bot_id),
+                MakerErrorType::BotRunnerError,
+            )),
+        }
+    }
+
+    /// Makes the next executions of the bot's commands record how long each node takes,
+    /// this clears the profiles of the previous session
+    pub async fn start_profiling(&self, bot_id: u8) {
+        info!("Profiling bot {}", bot_id);
+        self.profiling_sessions.lock().await.insert(
+            bot_id,
+            ProfilingSession {
+                enabled: true,
+                profiles: HashMap::new(),
+            },
+        );
+    }
+
+    pub async fn stop_profiling(&self, bot_id: u8) {
+        if let Some(session) = self.profiling_sessions.lock().await.get_mut(&bot_id) {
+            session.enabled = false;
+        }
+    }
+
+    /// The profile of every profiled execution of a code piece combined
+    pub async fn get_profile(&self, bot_id: u8, code_id: u8) -> Option<Profile> {
+        self.profiling_sessions
+            .lock()
+            .await
+            .get(&bot_id)?
+            .profiles
+            .get(&code_id)
+            .cloned()
+    }
+
+    /// What the bot's scripts have printed, oldest first
+    pub async fn get_console(&self, bot_id: u8) -> Vec<ConsoleEntry> {
+        match self.consoles.lock().await.get(&bot_id) {
+            Some(console) => console.iter().cloned().collect(),
+            None => vec![],
+        }
+    }
+
+    pub async fn clear_console(&self, bot_id: u8) {
+        self.consoles.lock().await.remove(&bot_id);
+    }
+
     pub async fn run(&self, context: RunnerContext) {
         // Check if it is already started
         if self.running_bots.lock().await.contains_key(&context.bot.id) {
@@ -121,20 +412,62 @@ impl BotRunner {
             .get_all(context.bot.id)
             .await
             .unwrap();
+        let libraries = context
+            .database
+            .code_pieces
+            .get_libraries(context.bot.id)
+            .await
+            .unwrap();
+        let enabled_plugins = context
+            .database
+            .plugins
+            .get_enabled(context.bot.id)
+            .await
+            .unwrap();
+
+        // Compile the code pieces, so errors show up now rather than per message
+        let compiled = load_plugins(enabled_plugins).and_then(|plugins| {
+            Ok((
+                compile_code_pieces(code_pieces, &plugins)?,
+                compile_libraries(libraries)?,
+                plugins,
+            ))
+        });
+        let (programs, engine, plugins) = match compiled {
+            Ok(compiled) => compiled,
+            Err(err) => {
+                info!("Failed to compile bot {}: {}", context.bot.id, err);
+                self.tx
+                    .send(SenderType::Error(rs2js::Error { error: err }))
+                    .await
+                    .unwrap();
+                return;
+            }
+        };
 
         let intents = GatewayIntents::GUILD_MESSAGES | GatewayIntents::MESSAGE_CONTENT;
         let (stop_tx, stop_rx) = oneshot::channel();
 
         let context2 = context.clone();
         let running_bots = self.running_bots.clone();
+        let debug_sessions = self.debug_sessions.clone();
+        let profiling_sessions = self.profiling_sessions.clone();
+        let consoles = self.consoles.clone();
         let tx = self.tx.clone();
 
         // Spawn a new task for the bot
         let bot_task = tokio::spawn(async move {
             let mut client = Client::builder(context2.bot.token.clone(), intents)
                 .event_handler(Handler {
+                    bot_id: context2.bot.id,
                     commands,
-                    code_pieces,
+                    programs,
+                    engine,
+                    plugins,
+                    tx: tx.clone(),
+                    debug_sessions,
+                    profiling_sessions,
+                    consoles,
                 })
                 .await
                 .expect("Error creating client");
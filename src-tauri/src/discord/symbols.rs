@@ -0,0 +1,152 @@
+use std::collections::{HashMap, HashSet};
+
+use tokio::sync::Mutex;
+
+use crate::database::{database::Database, structures::CodePiece};
+use crate::errors::MakerError;
+use crate::lang::symbols::SymbolIndex;
+
+/// The symbol index of each bot whose symbols have been looked up, by bot id
+///
+/// Each is built the first time it is needed, and kept up to date by `update`
+#[derive(Default)]
+pub struct Symbols {
+    indexes: Mutex<HashMap<u8, SymbolIndex>>,
+}
+
+impl Symbols {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Re-indexes a code piece after it was changed, in the index of whichever bot has it
+    pub async fn update(&self, code_piece: &CodePiece) {
+        let mut indexes = self.indexes.lock().await;
+        for index in indexes.values_mut() {
+            if index.contains(code_piece.id) {
+                index_code_piece(index, code_piece);
+            }
+        }
+    }
+
+    /// Runs `f` with the index of a bot
+    pub async fn with_index<T>(
+        &self,
+        database: &Database,
+        bot_id: u8,
+        f: impl FnOnce(&SymbolIndex) -> T,
+    ) -> Result<T, MakerError> {
+        let mut code_pieces = database.code_pieces.get_all(bot_id).await?;
+        code_pieces.extend(database.code_pieces.get_libraries(bot_id).await?);
+
+        let mut indexes = self.indexes.lock().await;
+        let index = indexes.entry(bot_id).or_default();
+
+        // Commands and libraries are made without going through `update`
+        let ids: HashSet<u8> = code_pieces.iter().map(|x| x.id).collect();
+        for id in index.code_pieces() {
+            if !ids.contains(&id) {
+                index.remove(id);
+            }
+        }
+        for code_piece in &code_pieces {
+            if !index.contains(code_piece.id) {
+                index_code_piece(index, code_piece);
+            }
+        }
+
+        Ok(f(index))
+    }
+}
+
+/// Only dbm code pieces have symbols, the others can not import libraries either
+fn index_code_piece(index: &mut SymbolIndex, code_piece: &CodePiece) {
+    if code_piece.language == "dbm" {
+        index.update(
+            code_piece.id,
+            code_piece.name.clone(),
+            &code_piece.code,
+            code_piece.edition,
+        );
+    } else {
+        index.remove(code_piece.id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// How many times the library's `greeting` is used, by code piece
+    async fn greetings(
+        symbols: &Symbols,
+        database: &Database,
+        bot_id: u8,
+        library: u8,
+    ) -> Vec<(u8, usize)> {
+        symbols
+            .with_index(database, bot_id, |index| {
+                index.find_references(library, 1, 5)
+            })
+            .await
+            .unwrap()
+            .unwrap()
+            .iter()
+            .map(|x| (x.code_piece_id, x.locations.len()))
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn indexes_follow_the_code_pieces_of_the_bot() {
+        let database = Database::in_memory().await.unwrap();
+        let bot = database.bots.add("Indexed", "token").await.unwrap();
+        let library = database
+            .code_pieces
+            .create_library(bot.id, "utils")
+            .await
+            .unwrap();
+        database
+            .code_pieces
+            .set(library.id, "var greeting = \"hi\"")
+            .await
+            .unwrap();
+        let symbols = Symbols::new();
+
+        assert_eq!(
+            greetings(&symbols, &database, bot.id, library.id).await,
+            vec![(library.id, 1)]
+        );
+
+        // Made without going through `update`
+        let command = database.commands.create("greet", bot.id).await.unwrap();
+        let code_piece = database.code_pieces.create().await.unwrap();
+        database
+            .commands
+            .set_code_piece(command.id, code_piece.id)
+            .await
+            .unwrap();
+        let code_piece = database
+            .code_pieces
+            .set(
+                code_piece.id,
+                "var utils = import \"utils\"\nmessage.reply(utils.greeting)",
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            greetings(&symbols, &database, bot.id, library.id).await,
+            vec![(library.id, 1), (code_piece.id, 1)]
+        );
+
+        let code_piece = database
+            .code_pieces
+            .set_language(code_piece.id, "rhai")
+            .await
+            .unwrap();
+        symbols.update(&code_piece).await;
+        assert_eq!(
+            greetings(&symbols, &database, bot.id, library.id).await,
+            vec![(library.id, 1)]
+        );
+    }
+}
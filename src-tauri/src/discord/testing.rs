@@ -0,0 +1,214 @@
+use std::collections::HashMap;
+
+use log::info;
+
+use crate::database::database::Database;
+use crate::database::structures::Command;
+use crate::discord::engines::dbm::DbmEngine;
+use crate::discord::runner::{compile_libraries, load_plugins};
+use crate::errors::{MakerError, MakerErrorType};
+use crate::lang::testing::{self, TestResult};
+use crate::lang::values::RuntimeValue;
+
+/// The results of one command's tests
+#[derive(serde::Serialize, Debug, Clone)]
+pub struct CommandTestResults {
+    pub command_id: u8,
+    pub command: String,
+    pub results: Vec<TestResult>,
+}
+
+/// Runs the tests of a bot's commands against a simulated message, or only those of
+/// `command_id`, commands without tests are left out
+pub async fn run_tests(
+    database: &Database,
+    bot_id: u8,
+    command_id: Option<u8>,
+) -> Result<Vec<CommandTestResults>, MakerError> {
+    let commands = database.commands.get_all(bot_id).await?;
+    let engine = compile_libraries(database.code_pieces.get_libraries(bot_id).await?)?;
+    let plugins = load_plugins(database.plugins.get_enabled(bot_id).await?)?;
+
+    let mut all_results = vec![];
+    for command in commands {
+        if command_id.is_some_and(|x| x != command.id) {
+            continue;
+        }
+
+        let tests = database.tests.get_all(command.id).await?;
+        if tests.is_empty() {
+            continue;
+        }
+
+        info!("Testing command {}", command.name);
+        let program = compile_command(database, &command, &plugins).await;
+
+        let mut results = vec![];
+        for test in tests {
+            let test = test.test_case();
+            results.push(match &program {
+                Ok(program) => testing::run(&engine, program, &test, plugins.clone()).await,
+                Err(err) => testing::failed(&test, err.clone()),
+            });
+        }
+
+        all_results.push(CommandTestResults {
+            command_id: command.id,
+            command: command.name,
+            results,
+        });
+    }
+
+    Ok(all_results)
+}
+
+async fn compile_command(
+    database: &Database,
+    command: &Command,
+    plugins: &HashMap<String, RuntimeValue>,
+) -> Result<crate::lang::runtime::Program, MakerError> {
+    let code_piece = database.code_pieces.get(command.code_id).await?;
+
+    // The other languages run through their own engines, which only know about Discord
+    if code_piece.language != "dbm" {
+        return Err(MakerError::new(
+            format!(
+                "{} is written in {}, only dbm commands can be tested",
+                command.name, code_piece.language
+            ),
+            MakerErrorType::BotRunnerError,
+        ));
+    }
+
+    let bindings: Vec<&str> = plugins.keys().map(|x| x.as_str()).collect();
+    DbmEngine.compile_program(&code_piece, &bindings)
+}
+
+/// `dbm test <bot>`, prints the results of every test of the bot and returns the exit code
+pub async fn cli(bot_name: Option<&String>) -> i32 {
+    let Some(bot_name) = bot_name else {
+        eprintln!("Usage: dbm test <bot>");
+        return 2;
+    };
+
+    let results = match Database::new().await {
+        Ok(database) => match database.bots.get_by_name(bot_name).await {
+            Ok(bot) => run_tests(&database, bot.id, None).await,
+            Err(err) => Err(err),
+        },
+        Err(err) => Err(err),
+    };
+    let results = match results {
+        Ok(results) => results,
+        Err(err) => {
+            eprintln!("{}", err);
+            return 1;
+        }
+    };
+
+    let (mut passed, mut failed) = (0, 0);
+    for command in &results {
+        println!("{}", command.command);
+        for result in &command.results {
+            print!("  {}", result.render());
+            if result.passed {
+                passed += 1;
+            } else {
+                failed += 1;
+            }
+        }
+    }
+    println!("{} passed, {} failed", passed, failed);
+
+    if failed == 0 {
+        0
+    } else {
+        1
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lang::testing::TestCase;
+
+    async fn command(database: &Database, bot_id: u8, name: &str, code: &str) -> Command {
+        let command = database.commands.create(name, bot_id).await.unwrap();
+        let code_piece = database.code_pieces.create().await.unwrap();
+        database.code_pieces.set(code_piece.id, code).await.unwrap();
+        database
+            .commands
+            .set_code_piece(command.id, code_piece.id)
+            .await
+            .unwrap()
+    }
+
+    async fn test(database: &Database, command: &Command, expected_replies: &[&str]) {
+        let test = database.tests.create(command.id, "test").await.unwrap();
+        let test_case = TestCase {
+            name: format!("replies {:?}", expected_replies),
+            content: format!("!{}", command.name),
+            author: "someone".to_string(),
+            channel: "1".to_string(),
+            expected_replies: expected_replies.iter().map(|x| x.to_string()).collect(),
+        };
+        database.tests.set(test.id, &test_case).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn commands_are_tested_with_the_bots_libraries() {
+        let database = Database::in_memory().await.unwrap();
+        let bot = database.bots.add("Tested", "token").await.unwrap();
+        let utils = database
+            .code_pieces
+            .create_library(bot.id, "utils")
+            .await
+            .unwrap();
+        database
+            .code_pieces
+            .set(utils.id, "var greeting = \"hi\"")
+            .await
+            .unwrap();
+
+        let greet = command(
+            &database,
+            bot.id,
+            "greet",
+            "var utils = import \"utils\"\nmessage.reply(utils.greeting)\nprint(message.author)",
+        )
+        .await;
+        test(&database, &greet, &["hi"]).await;
+        test(&database, &greet, &["hello"]).await;
+        // Commands without tests are left out
+        command(&database, bot.id, "untested", "message.reply(1)").await;
+        let rhai = command(&database, bot.id, "rhai", "message.reply(\"hi\");").await;
+        database
+            .code_pieces
+            .set_language(rhai.code_id, "rhai")
+            .await
+            .unwrap();
+        test(&database, &rhai, &["hi"]).await;
+
+        let results = run_tests(&database, bot.id, None).await.unwrap();
+        let commands: Vec<&str> = results.iter().map(|x| x.command.as_str()).collect();
+        assert_eq!(commands, vec!["greet", "rhai"]);
+
+        let greet_results = &results[0].results;
+        assert!(greet_results[0].passed);
+        assert!(!greet_results[1].passed);
+        assert_eq!(greet_results[1].replies, vec!["hi"]);
+        assert_eq!(greet_results[0].logs.len(), 1);
+        assert!(greet_results[0].logs[0].contains("someone"));
+
+        let rhai_result = &results[1].results[0];
+        assert!(!rhai_result.passed);
+        assert_eq!(
+            rhai_result.error.as_ref().unwrap().message,
+            "rhai is written in rhai, only dbm commands can be tested"
+        );
+
+        let results = run_tests(&database, bot.id, Some(rhai.id)).await.unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].command_id, rhai.id);
+    }
+}
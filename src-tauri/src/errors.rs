@@ -1,4 +1,8 @@
-use std::{error::Error, fmt};
+use std::{
+    error::Error,
+    fmt,
+    ops::{Deref, DerefMut},
+};
 
 use serde::ser::SerializeStruct;
 
@@ -8,21 +12,61 @@ use crate::lang::lexer::Location;
 pub enum MakerErrorType {
     UnknownDatabase,
     BotAlreadyExists,
+    LibraryAlreadyExists,
     LexerError,
     ParserError,
     RuntimeError,
+    TypeError,
     BotRunnerError,
+    PluginError,
+    ExportError,
+    AssertionError,
+    SymbolError,
 }
 
+/// A secondary location attached to an error, e.g. where a block was opened
+#[derive(serde::Serialize, Debug, Clone)]
+pub struct Label {
+    pub location: Location,
+    pub message: String,
+}
+
+/// A call which was running when an error happened, `location` is where it was called from
+#[derive(serde::Serialize, Debug, Clone)]
+pub struct StackFrame {
+    pub function: String,
+    pub location: Location,
+    pub native: bool,
+}
+
+/// What an error is about, boxed by `MakerError` so that results carrying one stay small
 #[derive(Debug, Clone)]
-pub struct MakerError {
+pub struct ErrorDetails {
     pub message: String,
     pub error_type: MakerErrorType,
     pub source: Option<String>,
     pub location: Option<Location>,
+    pub labels: Vec<Label>,
+    pub notes: Vec<String>,
+    pub stack: Vec<StackFrame>,
 }
 
-unsafe impl Send for MakerError {}
+#[derive(Debug, Clone)]
+pub struct MakerError(Box<ErrorDetails>);
+
+impl Deref for MakerError {
+    type Target = ErrorDetails;
+
+    fn deref(&self) -> &ErrorDetails {
+        &self.0
+    }
+}
+
+impl DerefMut for MakerError {
+    fn deref_mut(&mut self) -> &mut ErrorDetails {
+        &mut self.0
+    }
+}
 
 impl fmt::Display for MakerError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
@@ -30,13 +74,23 @@ impl fmt::Display for MakerError {
             f,
             "[{:?}] Maker Error: {}: {:?}",
             self.error_type, self.message, self.source
-        )
+        )?;
+
+        if let Some(location) = &self.location {
+            write!(
+                f,
+                " at {}:{}:{}",
+                location.context, location.line, location.column
+            )?;
+        }
+
+        Ok(())
     }
 }
 
 impl MakerError {
     pub fn from(error: Option<Box<dyn Error>>, error_type: MakerErrorType) -> Self {
-        Self {
+        Self::with_details(ErrorDetails {
             message: if let Some(ref error) = error {
                 error.to_string()
             } else {
@@ -45,16 +99,22 @@ impl MakerError {
             source: error.map(|x| x.to_string()),
             error_type,
             location: None,
-        }
+            labels: vec![],
+            notes: vec![],
+            stack: vec![],
+        })
     }
 
     pub fn new<S: Into<String>>(message: S, error_type: MakerErrorType) -> Self {
-        Self {
+        Self::with_details(ErrorDetails {
             message: message.into(),
             source: None,
             error_type,
             location: None,
-        }
+            labels: vec![],
+            notes: vec![],
+            stack: vec![],
+        })
     }
 
     pub fn lang<S: Into<String>>(
@@ -62,12 +122,42 @@ impl MakerError {
         location: Location,
         error_type: MakerErrorType,
     ) -> Self {
-        Self {
+        Self::with_details(ErrorDetails {
             location: Some(location),
             message: message.into(),
             source: None,
             error_type,
+            labels: vec![],
+            notes: vec![],
+            stack: vec![],
+        })
+    }
+
+    fn with_details(details: ErrorDetails) -> Self {
+        Self(Box::new(details))
+    }
+
+    /// Points at another place in the code which is relevant to the error
+    pub fn with_label<S: Into<String>>(mut self, location: Location, message: S) -> Self {
+        self.labels.push(Label {
+            location,
+            message: message.into(),
+        });
+        self
+    }
+
+    /// Adds a hint on how to fix the error
+    pub fn with_note<S: Into<String>>(mut self, note: S) -> Self {
+        self.notes.push(note.into());
+        self
+    }
+
+    /// Gives the error a location, unless it already knows where it happened
+    pub fn with_location(mut self, location: Location) -> Self {
+        if self.location.is_none() {
+            self.location = Some(location);
         }
+        self
     }
 }
 
@@ -79,7 +169,7 @@ impl serde::Serialize for MakerError {
     where
         S: serde::Serializer,
     {
-        let mut state = serializer.serialize_struct("MakerError", 2)?;
+        let mut state = serializer.serialize_struct("MakerError", 7)?;
 
         state.serialize_field("message", &self.message)?;
         state.serialize_field("error_type", &self.error_type)?;
@@ -98,6 +188,31 @@ impl serde::Serialize for MakerError {
             state.serialize_field("location", &None::<String>)?;
         }
 
+        state.serialize_field("labels", &self.labels)?;
+        state.serialize_field("notes", &self.notes)?;
+        state.serialize_field("stack", &self.stack)?;
+
         state.end()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn errors_stay_small() {
+        assert!(std::mem::size_of::<MakerError>() <= 8);
+        assert!(std::mem::size_of::<Result<(), MakerError>>() <= 8);
+    }
+
+    #[test]
+    fn details_are_reachable_through_the_error() {
+        let mut error = MakerError::new("oops", MakerErrorType::RuntimeError).with_note("a note");
+        error.message.push('!');
+
+        assert_eq!(error.message, "oops!");
+        assert_eq!(error.notes, vec!["a note".to_string()]);
+        assert!(error.location.is_none());
+    }
+}
@@ -0,0 +1,251 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    path::{Path, PathBuf},
+};
+
+use crate::errors::{MakerError, MakerErrorType};
+use crate::lang::{edition::Edition, lexer::lex, parser::Parser};
+
+use transpiler::Transpiler;
+
+pub mod transpiler;
+
+/// What the exported scripts run on, copied into the crate as `src/runtime.rs`
+const RUNTIME: &str = include_str!("runtime.rs.in");
+
+/// Where bots are exported to by the app, each into a directory named after the bot
+pub const EXPORTS_DIRECTORY: &str = "../exports";
+
+/// The serenity version the app itself uses
+const SERENITY_VERSION: &str = "0.12.2";
+
+/// A command or library to export
+pub struct ExportedScript {
+    pub name: String,
+    pub code: String,
+    pub edition: u8,
+    pub language: String,
+}
+
+pub struct ExportedBot {
+    pub name: String,
+    pub prefix: String,
+    pub commands: Vec<ExportedScript>,
+    pub libraries: Vec<ExportedScript>,
+}
+
+/// Turns a bot into the files of a Rust crate which runs it with serenity, without
+/// the app, the token is read from the `DISCORD_TOKEN` environment variable
+///
+/// Each command becomes an async function in `src/commands.rs` which calls the
+/// Discord API directly, and each library a function in `src/libraries.rs`
+pub fn generate(bot: &ExportedBot) -> Result<Vec<(PathBuf, String)>, MakerError> {
+    let mut names = HashSet::new();
+    let libraries: HashMap<String, String> = bot
+        .libraries
+        .iter()
+        .map(|x| (x.name.clone(), function_name(&x.name, &mut names)))
+        .collect();
+
+    let mut library_functions = vec![];
+    for library in &bot.libraries {
+        let body = transpile(library, false, &libraries)?;
+        library_functions.push(format!(
+            "/// `import {:?}`\npub fn {}(imports: &mut Imports) -> Result<Value, Error> {{\n    let mut scope = Scope::new();\n    {};\n    Ok(scope.into_object())\n}}\n",
+            library.name, libraries[&library.name], body
+        ));
+    }
+
+    let mut command_functions = vec![];
+    let mut dispatch = vec![];
+    let mut dispatched = HashSet::new();
+    for command in &bot.commands {
+        // Like the app, only the first command with a name ever runs
+        if !dispatched.insert(command.name.to_lowercase()) {
+            continue;
+        }
+
+        let function = function_name(&command.name, &mut names);
+        let body = transpile(command, true, &libraries)?;
+
+        command_functions.push(format!(
+            "/// `{}{}`\npub async fn {}(ctx: &Context, msg: &Message) -> Result<Value, Error> {{\n    let mut scope = Scope::new();\n    let mut imports = Imports::default();\n    scope.declare(\"message\", runtime::message(msg))?;\n    Ok({})\n}}\n",
+            bot.prefix, command.name, function, body
+        ));
+        dispatch.push(format!(
+            "            {:?} => commands::{}(&ctx, &msg).await,\n",
+            command.name.to_lowercase(),
+            function
+        ));
+    }
+
+    let header = "// Generated by dbm, changes are lost when the bot is exported again\n#![allow(unused_imports, unused_mut, unused_variables, unused_parens, unused_braces, path_statements, clippy::all)]\n\n";
+
+    Ok(vec![
+        (PathBuf::from("Cargo.toml"), cargo_toml(&bot.name)),
+        (PathBuf::from("src/runtime.rs"), RUNTIME.to_string()),
+        (
+            PathBuf::from("src/commands.rs"),
+            format!(
+                "{}use serenity::model::prelude::*;\nuse serenity::prelude::*;\n\nuse crate::libraries;\nuse crate::runtime::{{self, Error, Imports, Scope, Value}};\n\n{}",
+                header,
+                command_functions.join("\n")
+            ),
+        ),
+        (
+            PathBuf::from("src/libraries.rs"),
+            format!(
+                "{}use crate::libraries;\nuse crate::runtime::{{self, Error, Imports, Scope, Value}};\n\n{}",
+                header,
+                library_functions.join("\n")
+            ),
+        ),
+        (
+            PathBuf::from("src/main.rs"),
+            main_rs(&bot.prefix, &dispatch.concat()),
+        ),
+    ])
+}
+
+/// Writes the crate of `generate` into `directory`, creating it if needed
+pub fn write(bot: &ExportedBot, directory: &Path) -> Result<(), MakerError> {
+    for (path, contents) in generate(bot)? {
+        let path = directory.join(path);
+        fs::create_dir_all(path.parent().unwrap_or(directory))
+            .and_then(|_| fs::write(&path, contents))
+            .map_err(|err| {
+                MakerError::new(
+                    format!("Failed to write {}: {}", path.display(), err),
+                    MakerErrorType::ExportError,
+                )
+            })?;
+    }
+
+    Ok(())
+}
+
+fn transpile(
+    script: &ExportedScript,
+    has_message: bool,
+    libraries: &HashMap<String, String>,
+) -> Result<String, MakerError> {
+    if script.language != "dbm" {
+        return Err(MakerError::new(
+            format!(
+                "{} is written in {}, only dbm can be exported",
+                script.name, script.language
+            ),
+            MakerErrorType::ExportError,
+        ));
+    }
+
+    let edition = Edition::from_number(script.edition).ok_or_else(|| {
+        MakerError::new(
+            format!("{} uses unknown edition {}", script.name, script.edition),
+            MakerErrorType::ExportError,
+        )
+    })?;
+
+    let tokens = lex(&script.code, script.name.clone(), edition)?;
+    let block = Parser::new(&tokens, edition).parse()?;
+
+    Transpiler::new(edition, has_message, libraries).block(&block)
+}
+
+/// A Rust identifier for a command or library, unique among `taken`
+fn function_name(name: &str, taken: &mut HashSet<String>) -> String {
+    let base: String = name
+        .to_lowercase()
+        .chars()
+        .map(|x| if x.is_ascii_alphanumeric() { x } else { '_' })
+        .collect();
+    let base = format!("script_{}", base);
+
+    let mut function = base.clone();
+    let mut i = 2;
+    while taken.contains(&function) {
+        function = format!("{}_{}", base, i);
+        i += 1;
+    }
+
+    taken.insert(function.clone());
+    function
+}
+
+fn cargo_toml(name: &str) -> String {
+    let package: String = name
+        .to_lowercase()
+        .chars()
+        .map(|x| if x.is_ascii_alphanumeric() { x } else { '-' })
+        .collect();
+
+    // Cargo wants it to start with a letter
+    let package = match package.trim_matches('-') {
+        "" => "bot".to_string(),
+        package if package.starts_with(|x: char| x.is_ascii_digit()) => format!("bot-{}", package),
+        package => package.to_string(),
+    };
+
+    format!(
+        "[package]\nname = \"{}\"\nversion = \"0.1.0\"\nedition = \"2021\"\n\n[dependencies]\nserenity = \"{}\"\ntokio = {{ version = \"1\", features = [\"rt-multi-thread\", \"macros\"] }}\n",
+        package, SERENITY_VERSION
+    )
+}
+
+fn main_rs(prefix: &str, dispatch: &str) -> String {
+    format!(
+        r#"// Generated by dbm, changes are lost when the bot is exported again
+mod commands;
+mod libraries;
+mod runtime;
+
+use serenity::async_trait;
+use serenity::model::prelude::*;
+use serenity::prelude::*;
+
+const PREFIX: &str = {:?};
+
+struct Handler;
+
+#[async_trait]
+impl EventHandler for Handler {{
+    async fn message(&self, ctx: Context, msg: Message) {{
+        if msg.author.bot {{
+            return;
+        }}
+
+        let lowercase = msg.content.to_lowercase();
+        let Some(content) = lowercase.strip_prefix(PREFIX) else {{
+            return;
+        }};
+        let name = content.split(' ').next().unwrap_or_default();
+
+        let result = match name {{
+{}            _ => return,
+        }};
+
+        if let Err(err) = result {{
+            eprintln!("Command {{}} failed: {{}}", name, err);
+        }}
+    }}
+}}
+
+#[tokio::main]
+async fn main() {{
+    let token = std::env::var("DISCORD_TOKEN").expect("DISCORD_TOKEN has to be set to the bot's token");
+    let intents = GatewayIntents::GUILD_MESSAGES | GatewayIntents::MESSAGE_CONTENT;
+
+    let mut client = Client::builder(token, intents)
+        .event_handler(Handler)
+        .await
+        .expect("Error creating client");
+
+    if let Err(err) = client.start().await {{
+        eprintln!("Bot error: {{}}", err);
+    }}
+}}
+"#,
+        prefix, dispatch
+    )
+}
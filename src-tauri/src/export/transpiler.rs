@@ -0,0 +1,350 @@
+use std::{
+    cell::RefCell,
+    collections::{HashMap, HashSet},
+};
+
+use crate::errors::{MakerError, MakerErrorType};
+use crate::lang::{
+    builtins,
+    edition::Edition,
+    lexer::Location,
+    natives::{Literal, ParamDefault, Signature},
+    nodes::{Block, Call, Expression},
+    types::Type,
+};
+
+/// The Rust code each native is turned into, `args` are Rust expressions of the
+/// already checked arguments, one per parameter
+///
+/// These are what `discord::native_functions` does, without going through the interpreter
+fn native_call(path: &str, args: &[String]) -> Option<String> {
+    Some(match path {
+        "message.reply" => format!(
+            "{{ msg.reply(&ctx.http, {}.to_string()).await.map_err(|err| err.to_string())?; Value::Null }}",
+            args[0]
+        ),
+        "print" => format!("{{ println!(\"{{}}\", {}); Value::Null }}", args[0]),
+        "log.info" => format!("{{ println!(\"[info] {{}}\", {}); Value::Null }}", args[0]),
+        "log.warn" => format!("{{ eprintln!(\"[warn] {{}}\", {}); Value::Null }}", args[0]),
+        "assert" => format!("runtime::assert({}, {})?", args[0], args[1]),
+        "assert_eq" => format!(
+            "runtime::assert_eq({}, {}, {})?",
+            args[0], args[1], args[2]
+        ),
+        _ => return None,
+    })
+}
+
+/// Turns the syntax tree of one code piece into the body of a Rust function
+pub struct Transpiler<'a> {
+    edition: Edition,
+    /// Whether `message` is declared, it is not in libraries
+    has_message: bool,
+    /// The Rust function of each library, by the name it is imported with
+    libraries: &'a HashMap<String, String>,
+    /// The variables declared so far, which hide the builtins of `SHADOWABLE`
+    declared: RefCell<HashSet<String>>,
+}
+
+impl<'a> Transpiler<'a> {
+    pub fn new(
+        edition: Edition,
+        has_message: bool,
+        libraries: &'a HashMap<String, String>,
+    ) -> Transpiler<'a> {
+        Transpiler {
+            edition,
+            has_message,
+            libraries,
+            declared: RefCell::new(HashSet::new()),
+        }
+    }
+
+    fn error(&self, message: impl Into<String>, location: &Location) -> MakerError {
+        MakerError::lang(message, location.clone(), MakerErrorType::ExportError)
+    }
+
+    /// A Rust block which evaluates to the `Value` of the last node
+    pub fn block(&self, block: &Block) -> Result<String, MakerError> {
+        if block.nodes.is_empty() {
+            return Ok("Value::Null".to_string());
+        }
+
+        let mut nodes = vec![];
+        for node in &block.nodes {
+            nodes.push(self.expression(node)?);
+        }
+
+        Ok(format!("{{ {} }}", nodes.join("; ")))
+    }
+
+    fn expression(&self, expression: &Expression) -> Result<String, MakerError> {
+        Ok(match expression {
+            // ----- Special -----
+            Expression::Block(block) => self.block(block)?,
+            // ----- Statements -----
+            Expression::VariableDeclaration(dec) => {
+                let mut value = self.expression(&dec.value)?;
+
+                if let Some(annotation) = &dec.type_annotation {
+                    match Type::from_name(&annotation.name) {
+                        None => {
+                            return Err(self.error(
+                                format!("Unknown type {}", annotation.name),
+                                &annotation.location,
+                            ))
+                        }
+                        // Exported bots have IDs as strings, and no times at all
+                        Some(Type::Snowflake | Type::DateTime | Type::Duration) => {
+                            return Err(self.error(
+                                format!("The type {} can not be exported yet", annotation.name),
+                                &annotation.location,
+                            ))
+                        }
+                        Some(_) => {}
+                    }
+                    value = format!("runtime::expect({}, {:?})?", value, annotation.name);
+                }
+
+                self.declared.borrow_mut().insert(dec.name.name.clone());
+                format!("scope.declare({:?}, {})?", dec.name.name, value)
+            }
+            Expression::FunctionDeclaration(dec) => {
+                return Err(self.error(
+                    format!("The function {} can not be exported yet", dec.name.name),
+                    &dec.name.location,
+                ))
+            }
+            Expression::IfBlock(block) => {
+                let alternate = match &block.alternate {
+                    Some(alternate) => self.expression(alternate)?,
+                    None => "Value::Null".to_string(),
+                };
+
+                format!(
+                    "if ({}).is_truthy() {{ {} }} else {{ {} }}",
+                    self.expression(&block.test)?,
+                    self.block(&block.success)?,
+                    alternate
+                )
+            }
+            // ----- Expressions -----
+            Expression::Call(call) => self.call(call)?,
+            Expression::Member(member) => {
+                if let Some(path) = self.native_path(expression) {
+                    return Err(self.error(
+                        format!("{} can only be called in exported bots", path),
+                        &member.location,
+                    ));
+                }
+
+                let key = match &*member.right {
+                    Expression::Identifier(ident) => format!("Some({:?})", ident.name),
+                    _ => "None".to_string(),
+                };
+                format!(
+                    "runtime::member({}, {})?",
+                    self.expression(&member.left)?,
+                    key
+                )
+            }
+            Expression::Logical(logical) => {
+                // `!=` meant `==` in the first edition
+                let negate = if self.edition.compare(logical.operator, true) {
+                    ""
+                } else {
+                    "!"
+                };
+
+                format!(
+                    "Value::Boolean({}({}).equals(&{}))",
+                    negate,
+                    self.expression(&logical.left)?,
+                    self.expression(&logical.right)?
+                )
+            }
+            // ----- Literals -----
+            Expression::Identifier(ident) => {
+                if let Some(path) = self.native_path(expression) {
+                    return Err(self.error(
+                        format!("{} can only be called in exported bots", path),
+                        &ident.location,
+                    ));
+                }
+
+                format!("scope.get({:?})?", ident.name)
+            }
+            Expression::Number(number) => format!("Value::Number({:?})", number.value),
+            Expression::StringNode(string) => {
+                format!("Value::String({:?}.to_string())", string.value)
+            }
+            Expression::Import(import) => match self.libraries.get(&import.name.value) {
+                Some(function) => format!(
+                    "imports.import({:?}, libraries::{})?",
+                    import.name.value, function
+                ),
+                None => {
+                    return Err(self.error(
+                        format!("There is no library called {}", import.name.value),
+                        &import.name.location,
+                    ))
+                }
+            },
+        })
+    }
+
+    /// The native a node refers to, e.g. `message.reply`
+    fn native_path(&self, expression: &Expression) -> Option<String> {
+        let path = match expression {
+            Expression::Identifier(ident) => ident.name.clone(),
+            Expression::Member(member) => match (&*member.left, &*member.right) {
+                (Expression::Identifier(object), Expression::Identifier(name)) => {
+                    format!("{}.{}", object.name, name.name)
+                }
+                _ => return None,
+            },
+            _ => return None,
+        };
+
+        let object = path.split('.').next().unwrap_or_default();
+        if builtins::SHADOWABLE.contains(&object) && self.declared.borrow().contains(object) {
+            return None;
+        }
+
+        // Like in the app, libraries only get the natives every script has
+        if !self.has_message
+            && !matches!(path.as_str(), "assert" | "assert_eq" | "to_snowflake")
+            && !path.starts_with("time.")
+        {
+            return None;
+        }
+
+        match builtins::find_path(&path)?.ty {
+            Type::Function(_) => Some(path),
+            _ => None,
+        }
+    }
+
+    fn call(&self, call: &Call) -> Result<String, MakerError> {
+        let path = self.native_path(&call.callee).ok_or_else(|| {
+            self.error(
+                "Only natives like message.reply and print can be called in exported bots",
+                &call.location,
+            )
+        })?;
+
+        let signature = match builtins::find_path(&path).map(|x| x.ty) {
+            Some(Type::Function(Some(signature))) => signature,
+            _ => {
+                return Err(self.error(
+                    format!("{} has no signature, so it can not be exported", path),
+                    &call.location,
+                ))
+            }
+        };
+
+        let args = self.args(&path, signature, call)?;
+        native_call(&path, &args)
+            .ok_or_else(|| self.error(format!("{} can not be exported yet", path), &call.location))
+    }
+
+    /// Like `Signature::check_args`, what can be checked before the bot runs is
+    fn args(
+        &self,
+        path: &str,
+        signature: &Signature,
+        call: &Call,
+    ) -> Result<Vec<String>, MakerError> {
+        let note = format!("the function is {}", signature.describe(path));
+
+        if call.args.len() > signature.params.len() {
+            return Err(self
+                .error(
+                    format!(
+                        "{} takes {} argument(s) but {} were given",
+                        path,
+                        signature.params.len(),
+                        call.args.len()
+                    ),
+                    &call.args[signature.params.len()].get_location(),
+                )
+                .with_note(note));
+        }
+
+        let mut args = vec![];
+        for (i, param) in signature.params.iter().enumerate() {
+            let arg = match (call.args.get(i), &param.default) {
+                (Some(arg), ParamDefault::Optional) => format!(
+                    "match {} {{ Value::Null => Value::Null, value => runtime::expect(value, {:?})? }}",
+                    self.expression(arg)?,
+                    param.ty.to_string()
+                ),
+                (Some(arg), _) => format!(
+                    "runtime::expect({}, {:?})?",
+                    self.expression(arg)?,
+                    param.ty.to_string()
+                ),
+                (None, ParamDefault::Required) => {
+                    return Err(self
+                        .error(
+                            format!("Missing argument {} ({}) of {}", i, param.name, path),
+                            &call.location,
+                        )
+                        .with_note(note))
+                }
+                (None, ParamDefault::Optional) => "Value::Null".to_string(),
+                (None, ParamDefault::Value(value)) => literal(value),
+            };
+            args.push(arg);
+        }
+
+        Ok(args)
+    }
+}
+
+fn literal(value: &Literal) -> String {
+    match value {
+        Literal::Null => "Value::Null".to_string(),
+        Literal::Number(number) => format!("Value::Number({:?})", number),
+        Literal::String(string) => format!("Value::String({:?}.to_string())", string),
+        Literal::Boolean(boolean) => format!("Value::Boolean({})", boolean),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lang::{lexer::lex, parser::Parser};
+
+    fn transpile(code: &str) -> Result<String, MakerError> {
+        let tokens = lex(code, "test".to_string(), Edition::LATEST)?;
+        let block = Parser::new(&tokens, Edition::LATEST).parse()?;
+        Transpiler::new(Edition::LATEST, true, &HashMap::new()).block(&block)
+    }
+
+    #[test]
+    fn natives_are_called_directly() {
+        let rust = transpile("message.reply(1)").unwrap();
+
+        assert!(rust.contains(
+            "msg.reply(&ctx.http, runtime::expect(Value::Number(1.0), \"any\")?.to_string())"
+        ));
+    }
+
+    #[test]
+    fn declared_variables_hide_shadowable_natives() {
+        let err = transpile("print(assert)").unwrap_err();
+        assert_eq!(err.message, "assert can only be called in exported bots");
+
+        let rust = transpile("var assert = 1\nprint(assert)").unwrap();
+        assert!(rust.contains("scope.get(\"assert\")"));
+        assert!(!rust.contains("runtime::assert("));
+    }
+
+    #[test]
+    fn functions_are_not_exported_yet() {
+        let err = transpile("fn f() do\nend").unwrap_err();
+
+        assert_eq!(err.message, "The function f can not be exported yet");
+    }
+}
@@ -0,0 +1,128 @@
+use std::{collections::HashMap, sync::Arc, sync::OnceLock};
+
+use crate::errors::{MakerError, MakerErrorType};
+
+use super::{
+    lexer::Location,
+    natives::NativeRegistry,
+    values::{self, RuntimeValue},
+};
+
+/// `assert` and `assert_eq`, which every script has like `true` and `false`
+fn registry() -> &'static NativeRegistry<()> {
+    static REGISTRY: OnceLock<NativeRegistry<()>> = OnceLock::new();
+
+    REGISTRY.get_or_init(|| {
+        NativeRegistry::new()
+            .register("assert", |_, args, location| async move {
+                if args[0].is_truthy() {
+                    return Ok(values::Null::make());
+                }
+
+                Err(failure(&args[1], "Assertion failed".to_string(), location))
+            })
+            .register("assert_eq", |_, args, location| async move {
+                if args[0].equals(&args[1]) {
+                    return Ok(values::Null::make());
+                }
+
+                Err(failure(
+                    &args[2],
+                    format!(
+                        "Assertion failed, {} is not equal to {}",
+                        describe(&args[0]),
+                        describe(&args[1])
+                    ),
+                    location,
+                ))
+            })
+    })
+}
+
+pub fn natives() -> HashMap<String, RuntimeValue> {
+    ["assert", "assert_eq"]
+        .into_iter()
+        .filter_map(|name| Some((name.to_string(), registry().get(name, Arc::new(()))?)))
+        .collect()
+}
+
+/// `message` is what the script said went wrong, if anything
+fn failure(message: &RuntimeValue, default: String, location: Option<Location>) -> MakerError {
+    let error = match message {
+        RuntimeValue::Null(_) => MakerError::new(default, MakerErrorType::AssertionError),
+        message => MakerError::new(
+            format!("{}: {}", default, message),
+            MakerErrorType::AssertionError,
+        ),
+    };
+
+    match location {
+        Some(location) => error.with_location(location),
+        None => error,
+    }
+}
+
+/// A value in an error, strings are quoted so `"1"` and `1` can be told apart
+fn describe(value: &RuntimeValue) -> String {
+    match value {
+        RuntimeValue::StringValue(string) => format!("{:?}", string.value),
+        value => format!("{} ({})", value, value.type_name()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lang::engine::Engine;
+
+    async fn run(source: &str) -> Result<RuntimeValue, MakerError> {
+        let engine = Engine::new();
+        let program = engine.compile(source)?;
+        engine.eval(&program, HashMap::new()).await
+    }
+
+    #[tokio::test]
+    async fn passing_assertions_do_nothing() {
+        let result = run("assert(1)\nassert_eq(\"a\", \"a\", \"same\")\n2").await;
+
+        assert_eq!(result.unwrap().to_string(), "2");
+    }
+
+    #[tokio::test]
+    async fn failing_assertions_stop_the_script_where_they_are() {
+        let err = run("var x = 1\nassert(x == 2)\nx").await.unwrap_err();
+        assert_eq!(err.message, "Assertion failed");
+        assert!(matches!(err.error_type, MakerErrorType::AssertionError));
+        assert_eq!(err.location.as_ref().unwrap().line, 2);
+
+        let err = run("assert(\"\", \"it is empty\")").await.unwrap_err();
+        assert_eq!(err.message, "Assertion failed: it is empty");
+    }
+
+    #[tokio::test]
+    async fn failing_equality_shows_both_sides() {
+        let err = run("assert_eq(\"1\", 1)").await.unwrap_err();
+        assert_eq!(
+            err.message,
+            "Assertion failed, \"1\" is not equal to 1 (number)"
+        );
+
+        let err = run("assert_eq(1, true, \"nope\")").await.unwrap_err();
+        assert_eq!(
+            err.message,
+            "Assertion failed, 1 (number) is not equal to true (boolean): nope"
+        );
+    }
+
+    #[tokio::test]
+    async fn arguments_are_checked() {
+        let err = run("assert()").await.unwrap_err();
+        assert_eq!(err.message, "Missing argument 0 (condition) of assert");
+
+        let err = run("assert(false, 1)").await.unwrap_err();
+        assert_eq!(
+            err.message,
+            "Invalid type for argument 1 (message), expected string but found number"
+        );
+    }
+}
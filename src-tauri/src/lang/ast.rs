@@ -0,0 +1,117 @@
+use crate::errors::MakerError;
+
+use super::{
+    edition::Edition,
+    formatter::format_block,
+    lexer::{lex_with_comments, Comment, Location},
+    nodes::Block,
+    parser::Parser,
+};
+
+/// A `// comment`, kept next to the tree as the parser does not see them
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
+pub struct SyntaxComment {
+    /// Everything after the `//`
+    pub value: String,
+    /// Decides which statements it ends up between when printed
+    #[serde(default)]
+    pub location: Location,
+}
+
+/// A parsed code piece which can be turned back into source code, e.g. for
+/// editing it as blocks instead of text
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
+pub struct SyntaxTree {
+    pub edition: u8,
+    pub block: Block,
+    pub comments: Vec<SyntaxComment>,
+}
+
+impl SyntaxTree {
+    pub fn parse(source: &str, edition: Edition) -> Result<SyntaxTree, MakerError> {
+        let (tokens, comments) = lex_with_comments(source, "code".to_string(), edition)?;
+        let block = Parser::new(&tokens, edition).parse()?;
+
+        Ok(SyntaxTree {
+            edition: edition.number(),
+            block,
+            comments: comments
+                .into_iter()
+                .map(|x| SyntaxComment {
+                    value: x.value.to_string(),
+                    location: x.location,
+                })
+                .collect(),
+        })
+    }
+
+    /// The source code of the tree, in the style of the formatter
+    ///
+    /// Formatted code gives back exactly what was parsed, anything else
+    /// comes back the same as pressing Format would make it
+    pub fn print(&self) -> String {
+        let mut comments: Vec<Comment> = self
+            .comments
+            .iter()
+            .map(|x| Comment {
+                value: &x.value,
+                location: x.location.clone(),
+            })
+            .collect();
+        // The formatter puts them back in order of where they were
+        comments.sort_by_key(|x| x.location.start);
+
+        format_block(&self.block, &comments)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lang::formatter::format;
+
+    /// Like the editor does, the tree goes to JSON and back before it is printed
+    fn round_trip(source: &str, edition: Edition) -> String {
+        let tree = SyntaxTree::parse(source, edition).unwrap();
+        let json = serde_json::to_string(&tree).unwrap();
+
+        serde_json::from_str::<SyntaxTree>(&json).unwrap().print()
+    }
+
+    #[test]
+    fn formatted_code_comes_back_the_same() {
+        let source = "// Greets\nvar name: string = message.author\nif name == \"\" do\n    // Nobody\n    message.reply(\"Hi\")\nend\nfn twice(x: number) -> number do\n    x\nend\n";
+
+        assert_eq!(round_trip(source, Edition::LATEST), source);
+    }
+
+    #[test]
+    fn other_code_comes_back_formatted() {
+        let source = "var  x=1 // one\nmessage.reply( x,\n2)";
+
+        assert_eq!(
+            round_trip(source, Edition::LATEST),
+            format(source, Edition::LATEST).unwrap()
+        );
+    }
+
+    #[test]
+    fn trees_are_parsed_in_their_edition() {
+        let tree = SyntaxTree::parse("var fn = 1", Edition::V2).unwrap();
+
+        assert_eq!(tree.edition, 2);
+        assert!(SyntaxTree::parse("var fn = 1", Edition::V3).is_err());
+    }
+
+    #[test]
+    fn edited_trees_do_not_need_locations() {
+        let json = r#"{
+            "edition": 3,
+            "block": {"nodes": [{"type": "Call", "callee": {"type": "Identifier", "name": "print"}, "args": [{"type": "StringNode", "value": "hi"}]}]},
+            "comments": []
+        }"#;
+        let tree: SyntaxTree = serde_json::from_str(json).unwrap();
+
+        assert_eq!(tree.print(), "print(\"hi\")\n");
+    }
+}
@@ -0,0 +1,622 @@
+use super::{
+    natives::{Literal, Param, ParamDefault, Signature},
+    types::Type,
+};
+
+/// Something which every script can use without declaring it, documented for the editor
+#[derive(Debug, PartialEq)]
+pub struct Builtin {
+    pub name: &'static str,
+    pub documentation: &'static str,
+    pub ty: Type,
+    pub members: &'static [Builtin],
+}
+
+impl Builtin {
+    /// How it is shown in the editor, e.g. `message.id: snowflake`, `object` is
+    /// the name of the builtin this is a member of
+    pub fn detail(&self, object: Option<&str>) -> String {
+        let path = match object {
+            Some(object) => format!("{}.{}", object, self.name),
+            None => self.name.to_string(),
+        };
+
+        match self.ty {
+            Type::Function(Some(signature)) => signature.describe(&path),
+            ty => format!("{}: {}", path, ty),
+        }
+    }
+}
+
+const MESSAGE_MEMBERS: &[Builtin] = &[
+    Builtin {
+        name: "reply",
+        documentation: "Replies to the message with `content`, which is turned into text",
+        ty: Type::Function(Some(&Signature {
+            params: &[Param {
+                name: "content",
+                ty: Type::Any,
+                default: ParamDefault::Required,
+            }],
+            returns: Type::Null,
+        })),
+        members: &[],
+    },
+    Builtin {
+        name: "id",
+        documentation: "The ID of the message",
+        ty: Type::Snowflake,
+        members: &[],
+    },
+    Builtin {
+        name: "content",
+        documentation: "The text content of the message",
+        ty: Type::String,
+        members: &[],
+    },
+    Builtin {
+        name: "author",
+        documentation: "The username of who sent the message",
+        ty: Type::String,
+        members: &[],
+    },
+    Builtin {
+        name: "author_id",
+        documentation:
+            "The ID of who sent the message, `.created_at` is when their account was made",
+        ty: Type::Snowflake,
+        members: &[],
+    },
+    Builtin {
+        name: "channel",
+        documentation: "The ID of the channel the message was sent in",
+        ty: Type::Snowflake,
+        members: &[],
+    },
+];
+
+const LOG_MEMBERS: &[Builtin] = &[
+    Builtin {
+        name: "info",
+        documentation: "Writes `message` to the bot's console as information",
+        ty: Type::Function(Some(&Signature {
+            params: &[Param {
+                name: "message",
+                ty: Type::Any,
+                default: ParamDefault::Required,
+            }],
+            returns: Type::Null,
+        })),
+        members: &[],
+    },
+    Builtin {
+        name: "warn",
+        documentation: "Writes `message` to the bot's console as a warning",
+        ty: Type::Function(Some(&Signature {
+            params: &[Param {
+                name: "message",
+                ty: Type::Any,
+                default: ParamDefault::Required,
+            }],
+            returns: Type::Null,
+        })),
+        members: &[],
+    },
+];
+
+const TIME_MEMBERS: &[Builtin] = &[
+    Builtin {
+        name: "now",
+        documentation: "The current time",
+        ty: Type::Function(Some(&Signature {
+            params: &[],
+            returns: Type::DateTime,
+        })),
+        members: &[],
+    },
+    Builtin {
+        name: "from_timestamp",
+        documentation: "The time `seconds` after the Unix epoch (1970-01-01 UTC)",
+        ty: Type::Function(Some(&Signature {
+            params: &[Param {
+                name: "seconds",
+                ty: Type::Number,
+                default: ParamDefault::Required,
+            }],
+            returns: Type::DateTime,
+        })),
+        members: &[],
+    },
+    Builtin {
+        name: "parse",
+        documentation:
+            "Reads a time written like `2024-05-01T12:00:00Z`, with an offset or Z at the end",
+        ty: Type::Function(Some(&Signature {
+            params: &[Param {
+                name: "text",
+                ty: Type::String,
+                default: ParamDefault::Required,
+            }],
+            returns: Type::DateTime,
+        })),
+        members: &[],
+    },
+    Builtin {
+        name: "seconds",
+        documentation: "A duration of `amount` seconds",
+        ty: Type::Function(Some(&Signature {
+            params: &[Param {
+                name: "amount",
+                ty: Type::Number,
+                default: ParamDefault::Required,
+            }],
+            returns: Type::Duration,
+        })),
+        members: &[],
+    },
+    Builtin {
+        name: "minutes",
+        documentation: "A duration of `amount` minutes",
+        ty: Type::Function(Some(&Signature {
+            params: &[Param {
+                name: "amount",
+                ty: Type::Number,
+                default: ParamDefault::Required,
+            }],
+            returns: Type::Duration,
+        })),
+        members: &[],
+    },
+    Builtin {
+        name: "hours",
+        documentation: "A duration of `amount` hours",
+        ty: Type::Function(Some(&Signature {
+            params: &[Param {
+                name: "amount",
+                ty: Type::Number,
+                default: ParamDefault::Required,
+            }],
+            returns: Type::Duration,
+        })),
+        members: &[],
+    },
+    Builtin {
+        name: "days",
+        documentation: "A duration of `amount` days",
+        ty: Type::Function(Some(&Signature {
+            params: &[Param {
+                name: "amount",
+                ty: Type::Number,
+                default: ParamDefault::Required,
+            }],
+            returns: Type::Duration,
+        })),
+        members: &[],
+    },
+];
+
+const SNOWFLAKE_MEMBERS: &[Builtin] = &[Builtin {
+    name: "created_at",
+    documentation: "When the message, user, channel etc. with this ID was made",
+    ty: Type::DateTime,
+    members: &[],
+}];
+
+const DATETIME_MEMBERS: &[Builtin] = &[
+    Builtin {
+        name: "timestamp",
+        documentation: "The seconds since the Unix epoch (1970-01-01 UTC)",
+        ty: Type::Number,
+        members: &[],
+    },
+    Builtin {
+        name: "add",
+        documentation: "The time `duration` later",
+        ty: Type::Function(Some(&Signature {
+            params: &[Param {
+                name: "duration",
+                ty: Type::Duration,
+                default: ParamDefault::Required,
+            }],
+            returns: Type::DateTime,
+        })),
+        members: &[],
+    },
+    Builtin {
+        name: "sub",
+        documentation: "The time `duration` earlier",
+        ty: Type::Function(Some(&Signature {
+            params: &[Param {
+                name: "duration",
+                ty: Type::Duration,
+                default: ParamDefault::Required,
+            }],
+            returns: Type::DateTime,
+        })),
+        members: &[],
+    },
+    Builtin {
+        name: "since",
+        documentation: "How long after `other` this is, negative if it is before it",
+        ty: Type::Function(Some(&Signature {
+            params: &[Param {
+                name: "other",
+                ty: Type::DateTime,
+                default: ParamDefault::Required,
+            }],
+            returns: Type::Duration,
+        })),
+        members: &[],
+    },
+    Builtin {
+        name: "is_before",
+        documentation: "Whether this is earlier than `other`",
+        ty: Type::Function(Some(&Signature {
+            params: &[Param {
+                name: "other",
+                ty: Type::DateTime,
+                default: ParamDefault::Required,
+            }],
+            returns: Type::Boolean,
+        })),
+        members: &[],
+    },
+    Builtin {
+        name: "is_after",
+        documentation: "Whether this is later than `other`",
+        ty: Type::Function(Some(&Signature {
+            params: &[Param {
+                name: "other",
+                ty: Type::DateTime,
+                default: ParamDefault::Required,
+            }],
+            returns: Type::Boolean,
+        })),
+        members: &[],
+    },
+    Builtin {
+        name: "format",
+        documentation: "Writes the time with a strftime `pattern` in `timezone`, e.g. `\"%H:%M\"` and `\"Europe/London\"`",
+        ty: Type::Function(Some(&Signature {
+            params: &[
+                Param {
+                    name: "pattern",
+                    ty: Type::String,
+                    default: ParamDefault::Value(Literal::String("%Y-%m-%d %H:%M")),
+                },
+                Param {
+                    name: "timezone",
+                    ty: Type::String,
+                    default: ParamDefault::Value(Literal::String("UTC")),
+                },
+            ],
+            returns: Type::String,
+        })),
+        members: &[],
+    },
+    Builtin {
+        name: "relative",
+        documentation: "How long ago this is, like `3 hours ago` or `in 2 days`",
+        ty: Type::Function(Some(&Signature {
+            params: &[],
+            returns: Type::String,
+        })),
+        members: &[],
+    },
+];
+
+const DURATION_MEMBERS: &[Builtin] = &[
+    Builtin {
+        name: "seconds",
+        documentation: "The whole duration in seconds, including the fraction",
+        ty: Type::Number,
+        members: &[],
+    },
+    Builtin {
+        name: "minutes",
+        documentation: "The whole duration in minutes, including the fraction",
+        ty: Type::Number,
+        members: &[],
+    },
+    Builtin {
+        name: "hours",
+        documentation: "The whole duration in hours, including the fraction",
+        ty: Type::Number,
+        members: &[],
+    },
+    Builtin {
+        name: "days",
+        documentation: "The whole duration in days, including the fraction",
+        ty: Type::Number,
+        members: &[],
+    },
+    Builtin {
+        name: "add",
+        documentation: "This and `other` together",
+        ty: Type::Function(Some(&Signature {
+            params: &[Param {
+                name: "other",
+                ty: Type::Duration,
+                default: ParamDefault::Required,
+            }],
+            returns: Type::Duration,
+        })),
+        members: &[],
+    },
+    Builtin {
+        name: "sub",
+        documentation: "This without `other`",
+        ty: Type::Function(Some(&Signature {
+            params: &[Param {
+                name: "other",
+                ty: Type::Duration,
+                default: ParamDefault::Required,
+            }],
+            returns: Type::Duration,
+        })),
+        members: &[],
+    },
+    Builtin {
+        name: "is_longer",
+        documentation: "Whether this is longer than `other`",
+        ty: Type::Function(Some(&Signature {
+            params: &[Param {
+                name: "other",
+                ty: Type::Duration,
+                default: ParamDefault::Required,
+            }],
+            returns: Type::Boolean,
+        })),
+        members: &[],
+    },
+    Builtin {
+        name: "is_shorter",
+        documentation: "Whether this is shorter than `other`",
+        ty: Type::Function(Some(&Signature {
+            params: &[Param {
+                name: "other",
+                ty: Type::Duration,
+                default: ParamDefault::Required,
+            }],
+            returns: Type::Boolean,
+        })),
+        members: &[],
+    },
+];
+
+/// The types of values which have members of their own, e.g. `message.id.created_at`
+pub const VALUE_TYPES: &[Builtin] = &[
+    Builtin {
+        name: "snowflake",
+        documentation: "A Discord ID, like `message.id`, it can be used wherever a string can",
+        ty: Type::Snowflake,
+        members: SNOWFLAKE_MEMBERS,
+    },
+    Builtin {
+        name: "datetime",
+        documentation: "A point in time, made with `time`",
+        ty: Type::DateTime,
+        members: DATETIME_MEMBERS,
+    },
+    Builtin {
+        name: "duration",
+        documentation: "An amount of time, made with `time` or `datetime.since`",
+        ty: Type::Duration,
+        members: DURATION_MEMBERS,
+    },
+];
+
+pub const BUILTINS: &[Builtin] = &[
+    Builtin {
+        name: "message",
+        documentation: "The Discord message which ran the command",
+        ty: Type::Object(MESSAGE_MEMBERS),
+        members: MESSAGE_MEMBERS,
+    },
+    Builtin {
+        name: "print",
+        documentation:
+            "Writes `value` to the bot's console, along with the command and user which ran it",
+        ty: Type::Function(Some(&Signature {
+            params: &[Param {
+                name: "value",
+                ty: Type::Any,
+                default: ParamDefault::Required,
+            }],
+            returns: Type::Null,
+        })),
+        members: &[],
+    },
+    Builtin {
+        name: "log",
+        documentation: "Writes to the bot's console at a level, like `print`",
+        ty: Type::Object(LOG_MEMBERS),
+        members: LOG_MEMBERS,
+    },
+    Builtin {
+        name: "time",
+        documentation: "Makes datetimes and durations",
+        ty: Type::Object(TIME_MEMBERS),
+        members: TIME_MEMBERS,
+    },
+    Builtin {
+        name: "to_snowflake",
+        documentation: "Reads a Discord ID which was kept as a string",
+        ty: Type::Function(Some(&Signature {
+            params: &[Param {
+                name: "id",
+                ty: Type::String,
+                default: ParamDefault::Required,
+            }],
+            returns: Type::Snowflake,
+        })),
+        members: &[],
+    },
+    Builtin {
+        name: "assert",
+        documentation:
+            "Stops the script with an error if `condition` is not truthy, with `message` if given",
+        ty: Type::Function(Some(&Signature {
+            params: &[
+                Param {
+                    name: "condition",
+                    ty: Type::Any,
+                    default: ParamDefault::Required,
+                },
+                Param {
+                    name: "message",
+                    ty: Type::String,
+                    default: ParamDefault::Optional,
+                },
+            ],
+            returns: Type::Null,
+        })),
+        members: &[],
+    },
+    Builtin {
+        name: "assert_eq",
+        documentation:
+            "Stops the script with an error if `left == right` is not true, showing both",
+        ty: Type::Function(Some(&Signature {
+            params: &[
+                Param {
+                    name: "left",
+                    ty: Type::Any,
+                    default: ParamDefault::Required,
+                },
+                Param {
+                    name: "right",
+                    ty: Type::Any,
+                    default: ParamDefault::Required,
+                },
+                Param {
+                    name: "message",
+                    ty: Type::String,
+                    default: ParamDefault::Optional,
+                },
+            ],
+            returns: Type::Null,
+        })),
+        members: &[],
+    },
+    Builtin {
+        name: "true",
+        documentation: "The boolean true",
+        ty: Type::Boolean,
+        members: &[],
+    },
+    Builtin {
+        name: "false",
+        documentation: "The boolean false",
+        ty: Type::Boolean,
+        members: &[],
+    },
+];
+
+/// Builtins which were added after code pieces could already use their names, so
+/// declaring a variable with one of them replaces the builtin instead of failing
+pub const SHADOWABLE: &[&str] = &["assert", "assert_eq", "time", "to_snowflake"];
+
+pub fn find(name: &str) -> Option<&'static Builtin> {
+    BUILTINS.iter().find(|x| x.name == name)
+}
+
+pub fn find_member(object: &str, name: &str) -> Option<&'static Builtin> {
+    find(object)?.members.iter().find(|x| x.name == name)
+}
+
+/// Finds a builtin by its full name, e.g. `message.reply`, or a member of a
+/// value type, e.g. `datetime.add`
+pub fn find_path(path: &str) -> Option<&'static Builtin> {
+    match path.split_once('.') {
+        Some((object, name)) => find_member(object, name).or_else(|| {
+            VALUE_TYPES
+                .iter()
+                .find(|x| x.name == object)?
+                .members
+                .iter()
+                .find(|x| x.name == name)
+        }),
+        None => find(path),
+    }
+}
+
+/// The reference documentation of every builtin, as markdown
+pub fn reference() -> String {
+    let mut reference = "# Reference\n".to_string();
+
+    for builtin in BUILTINS.iter().chain(VALUE_TYPES) {
+        reference.push_str(&document(builtin, None));
+
+        for member in builtin.members {
+            reference.push_str(&document(member, Some(builtin.name)));
+        }
+    }
+
+    reference
+}
+
+fn document(builtin: &Builtin, object: Option<&str>) -> String {
+    let heading = if object.is_some() { "###" } else { "##" };
+    let path = match object {
+        Some(object) => format!("{}.{}", object, builtin.name),
+        None => builtin.name.to_string(),
+    };
+
+    let mut document = format!(
+        "\n{} {}\n```\n{}\n```\n{}\n",
+        heading,
+        path,
+        builtin.detail(object),
+        builtin.documentation
+    );
+
+    if let Type::Function(Some(signature)) = builtin.ty {
+        if !signature.params.is_empty() {
+            document.push_str("\n| Parameter | Type | Default |\n| --- | --- | --- |\n");
+
+            for param in signature.params {
+                let default = match &param.default {
+                    ParamDefault::Required => "required".to_string(),
+                    ParamDefault::Optional => "null".to_string(),
+                    ParamDefault::Value(value) => format!("`{}`", value),
+                };
+                document.push_str(&format!(
+                    "| `{}` | `{}` | {} |\n",
+                    param.name, param.ty, default
+                ));
+            }
+        }
+
+        document.push_str(&format!("\nReturns `{}`\n", signature.returns));
+    }
+
+    document
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn paths_find_members_of_builtins_and_value_types() {
+        assert_eq!(find_path("message.reply").unwrap().name, "reply");
+        assert_eq!(find_path("datetime.format").unwrap().name, "format");
+        assert_eq!(find_path("print").unwrap().name, "print");
+        assert!(find_path("message.nothing").is_none());
+        assert!(find_path("nothing.reply").is_none());
+    }
+
+    #[test]
+    fn the_reference_documents_every_parameter() {
+        let reference = reference();
+
+        assert!(reference.starts_with("# Reference\n"));
+        assert!(reference.contains("\n## message\n"));
+        assert!(reference.contains(
+            "\n### datetime.format\n```\ndatetime.format(pattern: string = \"%Y-%m-%d %H:%M\", timezone: string = \"UTC\") -> string\n```\n"
+        ));
+        assert!(reference.contains("| `timezone` | `string` | `\"UTC\"` |\n"));
+        assert!(reference.contains("| `message` | `string` | null |\n"));
+    }
+}
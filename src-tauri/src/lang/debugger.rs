@@ -0,0 +1,285 @@
+use std::collections::HashSet;
+
+use tokio::sync::mpsc;
+
+use crate::errors::{MakerError, MakerErrorType};
+
+use super::{lexer::Location, scope::Scope};
+
+/// What to do after the debugger has paused
+#[derive(Debug, Clone, Copy, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DebugCommand {
+    Continue,
+    StepIn,
+    StepOver,
+    StepOut,
+    Stop,
+}
+
+#[derive(serde::Serialize, Debug, Clone)]
+pub struct DebugVariable {
+    pub name: String,
+    pub type_name: String,
+    pub value: String,
+}
+
+/// Sent every time the debugger pauses, right before `location` is evaluated
+#[derive(serde::Serialize, Debug, Clone)]
+pub struct DebugSnapshot {
+    pub location: Location,
+    pub depth: usize,
+    pub variables: Vec<DebugVariable>,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Step {
+    /// Only pause on breakpoints
+    Run,
+    /// Pause before the next node
+    In,
+    /// Pause before the next node which is not nested deeper than this
+    Over(usize),
+    /// Pause before the next node which is less nested than this
+    Out(usize),
+}
+
+pub struct Debugger {
+    breakpoints: HashSet<u32>,
+    step: Step,
+    last_line: Option<u32>,
+    paused: mpsc::UnboundedSender<DebugSnapshot>,
+    commands: mpsc::UnboundedReceiver<DebugCommand>,
+}
+
+impl Debugger {
+    /// Creates a debugger which pauses on the given lines, or before
+    /// the first node if `pause_on_start` is set
+    pub fn new(
+        breakpoints: HashSet<u32>,
+        pause_on_start: bool,
+        paused: mpsc::UnboundedSender<DebugSnapshot>,
+        commands: mpsc::UnboundedReceiver<DebugCommand>,
+    ) -> Debugger {
+        Debugger {
+            breakpoints,
+            step: if pause_on_start { Step::In } else { Step::Run },
+            last_line: None,
+            paused,
+            commands,
+        }
+    }
+
+    fn should_pause(&self, location: &Location, depth: usize) -> bool {
+        let stepped = match self.step {
+            Step::Run => false,
+            Step::In => true,
+            Step::Over(over) => depth <= over,
+            Step::Out(out) => depth < out,
+        };
+
+        // Breakpoints only trigger once per line, not for every node on it
+        stepped
+            || (self.breakpoints.contains(&location.line) && self.last_line != Some(location.line))
+    }
+
+    /// Called before every node, waits for the frontend if the debugger should pause here
+    pub async fn before(
+        &mut self,
+        location: &Location,
+        depth: usize,
+        scope: &Scope,
+    ) -> Result<(), MakerError> {
+        let pause = self.should_pause(location, depth);
+        // Kept for every node, so coming back to a line pauses on it again
+        self.last_line = Some(location.line);
+        if !pause {
+            return Ok(());
+        }
+
+        let mut variables: Vec<DebugVariable> = scope
+            .variables
+            .iter()
+            .map(|(name, value)| DebugVariable {
+                name: name.clone(),
+                type_name: value.type_name().to_string(),
+                value: value.to_string(),
+            })
+            .collect();
+        variables.sort_by(|a, b| a.name.cmp(&b.name));
+
+        let _ = self.paused.send(DebugSnapshot {
+            location: location.clone(),
+            depth,
+            variables,
+        });
+
+        // Nobody listening anymore is the same as being told to stop
+        let command = self.commands.recv().await.unwrap_or(DebugCommand::Stop);
+
+        self.step = match command {
+            DebugCommand::Continue => Step::Run,
+            DebugCommand::StepIn => Step::In,
+            DebugCommand::StepOver => Step::Over(depth),
+            DebugCommand::StepOut => Step::Out(depth),
+            DebugCommand::Stop => {
+                return Err(MakerError::lang(
+                    "Execution was stopped by the debugger",
+                    location.clone(),
+                    MakerErrorType::RuntimeError,
+                ))
+            }
+        };
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lang::{edition::Edition, lexer::lex, parser::Parser, runtime::Interpreter};
+
+    const SOURCE: &str = "var x = 1\nfn f(a) do\n    var b = a\n    b\nend\nvar y = f(x)\ny";
+
+    /// Runs `SOURCE`, answering each pause with the next of `commands`, and gives
+    /// every pause with the result
+    async fn debug(
+        breakpoints: &[u32],
+        pause_on_start: bool,
+        commands: &[DebugCommand],
+    ) -> (Vec<DebugSnapshot>, Result<String, MakerError>) {
+        debug_source(SOURCE, breakpoints, pause_on_start, commands).await
+    }
+
+    async fn debug_source(
+        source: &str,
+        breakpoints: &[u32],
+        pause_on_start: bool,
+        commands: &[DebugCommand],
+    ) -> (Vec<DebugSnapshot>, Result<String, MakerError>) {
+        let tokens = lex(source, "test".to_string(), Edition::LATEST).unwrap();
+        let block = Parser::new(&tokens, Edition::LATEST).parse().unwrap();
+        let (paused, mut snapshots) = mpsc::unbounded_channel();
+        let (send, receive) = mpsc::unbounded_channel();
+
+        let mut interpreter = Interpreter::new(Scope::new());
+        interpreter.set_debugger(Debugger::new(
+            breakpoints.iter().copied().collect(),
+            pause_on_start,
+            paused,
+            receive,
+        ));
+
+        let run = async move {
+            let result = interpreter.evaluate_block(&block).await;
+            // Along with the debugger, which closes the channel `answer` waits on
+            drop(interpreter);
+            result.map(|x| x.to_string())
+        };
+        let answer = async move {
+            let mut pauses = vec![];
+            let mut commands = commands.iter();
+            while let Some(snapshot) = snapshots.recv().await {
+                pauses.push(snapshot);
+                match commands.next() {
+                    Some(command) => send.send(*command).unwrap(),
+                    // Like the frontend going away
+                    None => break,
+                }
+            }
+            pauses
+        };
+
+        let (result, pauses) = tokio::join!(run, answer);
+        (pauses, result)
+    }
+
+    /// The line and depth of each pause
+    fn lines(pauses: &[DebugSnapshot]) -> Vec<(u32, usize)> {
+        pauses.iter().map(|x| (x.location.line, x.depth)).collect()
+    }
+
+    #[tokio::test]
+    async fn stepping_in_pauses_before_every_node() {
+        let (pauses, result) = debug(&[], true, &[DebugCommand::StepIn; 11]).await;
+
+        assert_eq!(
+            lines(&pauses),
+            vec![
+                (1, 0),
+                (1, 1),
+                (2, 0),
+                (6, 0),
+                (6, 1),
+                (6, 2),
+                (6, 2),
+                (3, 2),
+                (3, 3),
+                (4, 2),
+                (7, 0),
+            ]
+        );
+        assert_eq!(result.unwrap(), "1");
+    }
+
+    #[tokio::test]
+    async fn stepping_over_and_out_skips_nested_nodes() {
+        let (pauses, _) = debug(&[6], false, &[DebugCommand::StepOver; 2]).await;
+        assert_eq!(lines(&pauses), vec![(6, 0), (7, 0)]);
+
+        let commands = [DebugCommand::StepOut, DebugCommand::Continue];
+        let (pauses, result) = debug(&[3], false, &commands).await;
+        assert_eq!(lines(&pauses), vec![(3, 2), (7, 0)]);
+        assert_eq!(result.unwrap(), "1");
+    }
+
+    #[tokio::test]
+    async fn breakpoints_pause_once_per_line() {
+        let (pauses, result) = debug(&[1, 3], false, &[DebugCommand::Continue; 2]).await;
+
+        assert_eq!(lines(&pauses), vec![(1, 0), (3, 2)]);
+        assert_eq!(result.unwrap(), "1");
+    }
+
+    #[tokio::test]
+    async fn breakpoints_pause_every_time_their_line_is_reached() {
+        let source = "fn f(a) do\n    a\nend\nf(1)\nf(2)";
+        let (pauses, result) =
+            debug_source(source, &[2], false, &[DebugCommand::Continue; 2]).await;
+
+        assert_eq!(lines(&pauses), vec![(2, 1), (2, 1)]);
+        assert_eq!(result.unwrap(), "2");
+    }
+
+    #[tokio::test]
+    async fn snapshots_show_the_variables_in_scope() {
+        let (pauses, _) = debug(&[4], false, &[DebugCommand::Continue]).await;
+        let variables: Vec<(&str, &str, &str)> = pauses[0]
+            .variables
+            .iter()
+            .filter(|x| ["a", "b", "x", "y"].contains(&x.name.as_str()))
+            .map(|x| (x.name.as_str(), x.type_name.as_str(), x.value.as_str()))
+            .collect();
+
+        assert_eq!(
+            variables,
+            vec![
+                ("a", "number", "1"),
+                ("b", "number", "1"),
+                ("x", "number", "1")
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn scripts_can_be_stopped() {
+        for commands in [&[DebugCommand::StepIn, DebugCommand::Stop][..], &[]] {
+            let (pauses, result) = debug(&[], true, commands).await;
+            let err = result.unwrap_err();
+
+            assert_eq!(lines(&pauses).len(), commands.len().max(1));
+            assert_eq!(err.message, "Execution was stopped by the debugger");
+        }
+    }
+}
@@ -0,0 +1,237 @@
+use std::collections::BTreeMap;
+
+use crate::errors::{Label, MakerError, MakerErrorType, StackFrame};
+
+use super::lexer::Location;
+
+/// An error in a form the editor can use to mark up the code
+#[derive(serde::Serialize, Debug, Clone)]
+pub struct Diagnostic {
+    pub message: String,
+    pub error_type: MakerErrorType,
+    pub location: Option<Location>,
+    pub labels: Vec<Label>,
+    pub notes: Vec<String>,
+    pub stack: Vec<StackFrame>,
+    pub code_frame: String,
+}
+
+impl Diagnostic {
+    pub fn from_error(error: &MakerError, source: &str) -> Diagnostic {
+        Diagnostic {
+            message: error.message.clone(),
+            error_type: error.error_type.clone(),
+            location: error.location.clone(),
+            labels: error.labels.clone(),
+            notes: error.notes.clone(),
+            stack: error.stack.clone(),
+            code_frame: render(error, source),
+        }
+    }
+}
+
+struct Annotation<'a> {
+    location: &'a Location,
+    message: &'a str,
+    primary: bool,
+}
+
+/// Renders the error with an annotated snippet of the source code, e.g.
+///
+/// ```text
+/// error[ParserError]: Expected end
+///  --> code_piece_1:3:1
+///   |
+/// 1 | if a do
+///   |      -- the block starts here
+/// ...
+/// 3 |
+///   | ^
+///   = note: every do needs a matching end
+/// ```
+pub fn render(error: &MakerError, source: &str) -> String {
+    let mut output = format!("error[{:?}]: {}\n", error.error_type, error.message);
+
+    let location = match &error.location {
+        Some(location) => location,
+        None => {
+            render_stack(&mut output, &error.stack, 0);
+            render_notes(&mut output, &error.notes, 0);
+            return output;
+        }
+    };
+
+    // Collect everything which should be underlined, grouped by line
+    let mut lines: BTreeMap<u32, Vec<Annotation>> = BTreeMap::new();
+    lines.entry(location.line).or_default().push(Annotation {
+        location,
+        message: "",
+        primary: true,
+    });
+    for label in &error.labels {
+        lines
+            .entry(label.location.line)
+            .or_default()
+            .push(Annotation {
+                location: &label.location,
+                message: &label.message,
+                primary: false,
+            });
+    }
+
+    let gutter = lines.keys().last().unwrap_or(&1).to_string().len();
+    let source_lines: Vec<&str> = source.lines().collect();
+
+    output.push_str(&format!(
+        "{}--> {}:{}:{}\n",
+        " ".repeat(gutter),
+        location.context,
+        location.line,
+        location.column
+    ));
+    output.push_str(&format!("{} |\n", " ".repeat(gutter)));
+
+    let mut previous: Option<u32> = None;
+    for (line, annotations) in &lines {
+        if let Some(previous) = previous {
+            if line - previous > 1 {
+                output.push_str("...\n");
+            }
+        }
+        previous = Some(*line);
+
+        let text = source_lines
+            .get((*line as usize).saturating_sub(1))
+            .copied()
+            .unwrap_or("");
+        output.push_str(&format!("{:>gutter$} | {}\n", line, text));
+
+        for annotation in annotations {
+            output.push_str(&format!(
+                "{} | {}\n",
+                " ".repeat(gutter),
+                underline(text, annotation)
+            ));
+        }
+    }
+
+    render_stack(&mut output, &error.stack, gutter);
+    render_notes(&mut output, &error.notes, gutter);
+    output
+}
+
+/// Creates the line of ^ or - which sits under an annotated piece of code
+fn underline(text: &str, annotation: &Annotation) -> String {
+    let location = annotation.location;
+    let start = location.column.saturating_sub(1) as usize;
+
+    // Keep tabs so the underline lines up with the code above it
+    let padding: String = text
+        .chars()
+        .chain(std::iter::repeat(' '))
+        .take(start)
+        .map(|c| if c == '\t' { '\t' } else { ' ' })
+        .collect();
+
+    let width = if location.end_line == location.line {
+        location.end_column.saturating_sub(location.column) as usize
+    } else {
+        text.chars().count().saturating_sub(start)
+    }
+    .max(1);
+
+    let marker = if annotation.primary { "^" } else { "-" };
+    let mut result = format!("{}{}", padding, marker.repeat(width));
+
+    if !annotation.message.is_empty() {
+        result.push(' ');
+        result.push_str(annotation.message);
+    }
+
+    result
+}
+
+fn render_stack(output: &mut String, stack: &[StackFrame], gutter: usize) {
+    for frame in stack {
+        output.push_str(&format!(
+            "{} = in `{}`{}, called from {}:{}:{}\n",
+            " ".repeat(gutter),
+            frame.function,
+            if frame.native { " (native)" } else { "" },
+            frame.location.context,
+            frame.location.line,
+            frame.location.column
+        ));
+    }
+}
+
+fn render_notes(output: &mut String, notes: &[String], gutter: usize) {
+    for note in notes {
+        output.push_str(&format!("{} = note: {}\n", " ".repeat(gutter), note));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    use crate::lang::{edition::Edition, engine::Engine, lexer::lex, parser::Parser};
+
+    fn parse_error(source: &str) -> MakerError {
+        let tokens = lex(source, "code_piece_1".to_string(), Edition::LATEST).unwrap();
+        Parser::new(&tokens, Edition::LATEST).parse().unwrap_err()
+    }
+
+    #[test]
+    fn labels_are_drawn_under_their_own_lines() {
+        let source = "if a do\n    b\n";
+
+        assert_eq!(
+            render(&parse_error(source), source),
+            "error[ParserError]: Expected end
+ --> code_piece_1:3:1
+  |
+1 | if a do
+  |      -- the block starts here
+...
+3 | 
+  | ^
+  = note: every do needs a matching end
+"
+        );
+    }
+
+    #[tokio::test]
+    async fn runtime_errors_show_where_they_were_called_from() {
+        let source = "fn f() do\n\tassert(1 == 2)\nend\nf()";
+        let engine = Engine::new();
+        let program = engine.compile_named(source, "code_piece_2").unwrap();
+        let err = engine.eval(&program, HashMap::new()).await.unwrap_err();
+
+        let diagnostic = Diagnostic::from_error(&err, source);
+        assert_eq!(diagnostic.stack.len(), 2);
+        // The underline keeps the tab, so it lines up however wide tabs are shown
+        assert_eq!(
+            diagnostic.code_frame,
+            "error[AssertionError]: Assertion failed
+ --> code_piece_2:2:2
+  |
+2 | \tassert(1 == 2)
+  | \t^^^^^^^^^^^^^^
+  = in `assert` (native), called from code_piece_2:2:2
+  = in `f`, called from code_piece_2:4:1
+"
+        );
+    }
+
+    #[test]
+    fn errors_without_a_location_only_have_their_notes() {
+        let err = MakerError::new("Oops", MakerErrorType::RuntimeError).with_note("a note");
+
+        assert_eq!(
+            render(&err, "var x = 1"),
+            "error[RuntimeError]: Oops\n = note: a note\n"
+        );
+    }
+}
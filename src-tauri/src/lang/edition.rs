@@ -0,0 +1,73 @@
+use super::lexer::LogicalOperator;
+
+/// A version of the language, code pieces keep the edition they were written
+/// in so that changes to the language never break them
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Edition {
+    /// Everything before editions existed: `!=` compares for equality,
+    /// `import` is not a keyword and blocks can only contain expressions
+    V1,
+    /// `!=` compares for inequality, `import` is a keyword and blocks can
+    /// contain `var`s and `if`s
+    V2,
+    /// `fn` is a keyword, so functions can be declared
+    V3,
+}
+
+impl Edition {
+    /// What new code pieces are written in
+    pub const LATEST: Edition = Edition::V3;
+
+    pub fn from_number(number: u8) -> Option<Edition> {
+        match number {
+            1 => Some(Edition::V1),
+            2 => Some(Edition::V2),
+            3 => Some(Edition::V3),
+            _ => None,
+        }
+    }
+
+    pub fn number(self) -> u8 {
+        match self {
+            Edition::V1 => 1,
+            Edition::V2 => 2,
+            Edition::V3 => 3,
+        }
+    }
+
+    /// The result of a comparison, given whether both sides are equal
+    pub fn compare(self, operator: LogicalOperator, equal: bool) -> bool {
+        match operator {
+            LogicalOperator::Neq if self >= Edition::V2 => !equal,
+            // `!=` used to be the same as `==`
+            _ => equal,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn editions_are_stored_as_their_number() {
+        for edition in [Edition::V1, Edition::V2, Edition::V3] {
+            assert_eq!(Edition::from_number(edition.number()), Some(edition));
+        }
+        assert_eq!(Edition::from_number(0), None);
+        assert_eq!(Edition::from_number(Edition::LATEST.number() + 1), None);
+    }
+
+    #[test]
+    fn not_equal_only_compares_for_inequality_from_edition_2() {
+        for (edition, equal, expected) in [
+            (Edition::V1, true, true),
+            (Edition::V1, false, false),
+            (Edition::V2, true, false),
+            (Edition::V3, false, true),
+        ] {
+            assert_eq!(edition.compare(LogicalOperator::Neq, equal), expected);
+            assert_eq!(edition.compare(LogicalOperator::Eq, equal), equal);
+        }
+    }
+}
@@ -0,0 +1,307 @@
+use std::{collections::HashMap, future::Future, sync::Arc};
+
+use crate::errors::MakerError;
+
+use super::{
+    edition::Edition,
+    lexer::lex,
+    optimizer::optimize,
+    parser::Parser,
+    runtime::{Interpreter, Modules, Program},
+    scope::Scope,
+    values::{NativeFunction, RuntimeValue},
+};
+
+/// Everything needed to run scripts outside of the app, e.g.
+///
+/// ```ignore
+/// let mut engine = Engine::new();
+/// engine.register_fn("shout", |args| Ok(StringValue::make(args[0].to_string().to_uppercase())));
+/// engine.register_module("greetings", "var hello = \"Hello\"")?;
+///
+/// let program = engine.compile("var greetings = import \"greetings\"\nshout(greetings.hello)")?;
+/// let result = engine.eval(&program, HashMap::new()).await?;
+/// ```
+pub struct Engine {
+    edition: Edition,
+    natives: Arc<HashMap<String, RuntimeValue>>,
+    modules: Arc<Modules>,
+}
+
+impl Default for Engine {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Engine {
+    pub fn new() -> Engine {
+        Engine {
+            edition: Edition::LATEST,
+            natives: Arc::new(HashMap::new()),
+            modules: Arc::new(HashMap::new()),
+        }
+    }
+
+    /// The edition which `compile` and `register_module` use, the latest by default
+    pub fn set_edition(&mut self, edition: Edition) -> &mut Self {
+        self.edition = edition;
+        self
+    }
+
+    /// Makes a value available to every script and library as `name`
+    pub fn register_value(&mut self, name: &str, value: RuntimeValue) -> &mut Self {
+        Arc::make_mut(&mut self.natives).insert(name.to_string(), value);
+        self
+    }
+
+    /// Makes a function available to every script and library as `name`
+    pub fn register_fn<F>(&mut self, name: &str, func: F) -> &mut Self
+    where
+        F: Fn(Vec<RuntimeValue>) -> Result<RuntimeValue, MakerError> + Send + Sync + 'static,
+    {
+        self.register_value(name, NativeFunction::make_sync(func))
+    }
+
+    /// Like `register_fn`, for functions which have to await something
+    pub fn register_async_fn<F, R>(&mut self, name: &str, func: F) -> &mut Self
+    where
+        F: Fn(Vec<RuntimeValue>) -> R + Send + Sync + 'static,
+        R: Future<Output = Result<RuntimeValue, MakerError>> + Send + 'static,
+    {
+        self.register_value(name, NativeFunction::make(func))
+    }
+
+    /// Makes `source` importable as `import "name"`
+    pub fn register_module(&mut self, name: &str, source: &str) -> Result<&mut Self, MakerError> {
+        let program = self.compile_program(source, name, true)?;
+        Ok(self.register_program(name, program))
+    }
+
+    /// Like `register_module`, for a library which was already compiled, e.g. in another edition
+    pub fn register_program(&mut self, name: &str, program: Program) -> &mut Self {
+        Arc::make_mut(&mut self.modules).insert(name.to_string(), program);
+        self
+    }
+
+    pub fn compile(&self, source: &str) -> Result<Program, MakerError> {
+        self.compile_named(source, "script")
+    }
+
+    /// Compiles `source`, `context` is the name errors refer to it by
+    pub fn compile_named(&self, source: &str, context: &str) -> Result<Program, MakerError> {
+        self.compile_program(source, context, false)
+    }
+
+    /// `library` is whether it will be imported, which keeps all of its variables
+    fn compile_program(
+        &self,
+        source: &str,
+        context: &str,
+        library: bool,
+    ) -> Result<Program, MakerError> {
+        let tokens = lex(source, context.to_string(), self.edition)?;
+        let block = Parser::new(&tokens, self.edition).parse()?;
+
+        // Redeclaring a global is an error, so those can never be inlined
+        let scope = Scope::new();
+        let globals: Vec<&str> = scope
+            .variables
+            .keys()
+            .chain(self.natives.keys())
+            .map(|x| x.as_str())
+            .collect();
+
+        Ok(Program {
+            block: Arc::new(optimize(block, &globals, self.edition, library)),
+            edition: self.edition,
+        })
+    }
+
+    /// An interpreter with everything registered and `bindings` declared, for when
+    /// more control is needed than `eval` gives, e.g. to attach a debugger
+    pub fn interpreter(
+        &self,
+        bindings: HashMap<String, RuntimeValue>,
+    ) -> Result<Interpreter, MakerError> {
+        let mut scope = Scope::new();
+
+        for (name, value) in self.natives.iter().chain(bindings.iter()) {
+            scope.declare(name, value.clone())?;
+        }
+
+        let mut interpreter = Interpreter::new(scope);
+        interpreter.set_edition(self.edition);
+        interpreter.set_modules(self.modules.clone());
+        interpreter.set_globals(self.natives.clone());
+        Ok(interpreter)
+    }
+
+    /// Runs a program with `bindings` declared, they are only visible to the program itself
+    ///
+    /// A binding should not be declared again by the program, as the optimizer
+    /// does not know about them
+    pub async fn eval(
+        &self,
+        program: &Program,
+        bindings: HashMap<String, RuntimeValue>,
+    ) -> Result<RuntimeValue, MakerError> {
+        let mut interpreter = self.interpreter(bindings)?;
+        interpreter.set_edition(program.edition);
+        interpreter.evaluate_block(&program.block).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lang::values;
+
+    async fn eval(engine: &Engine, source: &str) -> Result<String, MakerError> {
+        let program = engine.compile(source)?;
+        Ok(engine.eval(&program, HashMap::new()).await?.to_string())
+    }
+
+    #[tokio::test]
+    async fn scripts_can_declare_over_assertions() {
+        let engine = Engine::new();
+
+        assert_eq!(
+            eval(&engine, "var assert = \"mine\"\nassert")
+                .await
+                .unwrap(),
+            "mine"
+        );
+        assert_eq!(
+            eval(&engine, "var assert_eq = 1\nvar assert_eq = 2")
+                .await
+                .unwrap_err()
+                .message,
+            "The variable assert_eq already exists"
+        );
+    }
+
+    #[tokio::test]
+    async fn scripts_can_declare_over_time_natives() {
+        let engine = Engine::new();
+
+        assert_eq!(
+            eval(&engine, "var time = \"noon\"\nvar to_snowflake = 1\ntime")
+                .await
+                .unwrap(),
+            "noon"
+        );
+        let timestamp = eval(
+            &engine,
+            "var now = time.now()\nvar time = now\ntime.timestamp",
+        )
+        .await
+        .unwrap();
+        assert!(timestamp.parse::<f64>().unwrap() > 1_700_000_000.0);
+    }
+
+    #[tokio::test]
+    async fn libraries_export_what_they_declare_over() {
+        let mut engine = Engine::new();
+        engine
+            .register_module("checks", "var assert = \"mine\"")
+            .unwrap();
+
+        let result = eval(&engine, "var checks = import \"checks\"\nchecks.assert").await;
+        assert_eq!(result.unwrap(), "mine");
+    }
+
+    #[tokio::test]
+    async fn functions_return_their_last_value() {
+        let engine = Engine::new();
+        let source = "fn pick(a: string, b) -> string do\n    if a == \"\" do\n        b\n    end else do\n        a\n    end\nend\npick(\"\", \"second\")";
+
+        assert_eq!(eval(&engine, source).await.unwrap(), "second");
+    }
+
+    #[tokio::test]
+    async fn function_bodies_have_their_own_scope() {
+        let engine = Engine::new();
+
+        let source = "var name = \"outer\"\nfn get(name) do\n    var local = name\n    local\nend\nget(\"param\")";
+        assert_eq!(eval(&engine, source).await.unwrap(), "param");
+
+        let err = eval(&engine, "fn f() do\n    var local = 1\nend\nf()\nlocal")
+            .await
+            .unwrap_err();
+        assert_eq!(err.message, "The variable local does not exist");
+
+        // Only what was declared before the function can be used in it
+        let err = eval(&engine, "fn f() do\n    later\nend\nvar later = 1\nf()")
+            .await
+            .unwrap_err();
+        assert_eq!(err.message, "The variable later does not exist");
+    }
+
+    #[tokio::test]
+    async fn annotations_of_functions_are_checked() {
+        let engine = Engine::new();
+
+        let err = eval(&engine, "fn f(x: string) do\n    x\nend\nf(1)")
+            .await
+            .unwrap_err();
+        assert_eq!(
+            err.message,
+            "Invalid type for argument 0 (x), expected string but found number"
+        );
+        assert_eq!(err.stack.len(), 0);
+
+        let err = eval(&engine, "fn f() -> number do\n    \"one\"\nend\nf()")
+            .await
+            .unwrap_err();
+        assert_eq!(err.message, "Expected number but found string");
+        assert_eq!(err.stack[0].function, "f");
+
+        let err = eval(&engine, "fn f(x) do\nend\nf()").await.unwrap_err();
+        assert_eq!(err.message, "Missing argument 0 (x) of f");
+    }
+
+    #[tokio::test]
+    async fn recursion_is_capped() {
+        let engine = Engine::new();
+
+        for source in [
+            "fn forever(n) do\n    forever(n)\nend\nforever(1)",
+            "fn forever(n) do\n    if n do\n        if n == n do\n            var next = forever(n)\n        end\n    end\nend\nforever(1)",
+        ] {
+            let err = eval(&engine, source).await.unwrap_err();
+            assert_eq!(err.message, "Too many nested calls when calling forever");
+            assert!(err.stack.iter().all(|x| x.function == "forever"));
+        }
+    }
+
+    #[tokio::test]
+    async fn recursion_is_only_capped_by_the_number_of_calls() {
+        let mut engine = Engine::new();
+        engine.register_fn("decrement", |args| match &args[0] {
+            RuntimeValue::Number(x) => Ok(values::Number::make(x.value - 1.0)),
+            _ => Ok(values::Null::make()),
+        });
+
+        let source = "fn down(n) do\n    if n != 0 do\n        if n == n do\n            var next = down(decrement(n))\n        end\n    end\n    n\nend\ndown(50)";
+        assert_eq!(eval(&engine, source).await.unwrap(), "50");
+    }
+
+    #[tokio::test]
+    async fn library_functions_use_the_library_variables() {
+        let mut engine = Engine::new();
+        engine
+            .register_module(
+                "greetings",
+                "var greeting = \"Hello \"\nfn greet(name: string) -> string do\n    greeting\nend",
+            )
+            .unwrap();
+
+        let result = eval(
+            &engine,
+            "var greetings = import \"greetings\"\ngreetings.greet(\"you\")",
+        )
+        .await;
+        assert_eq!(result.unwrap(), "Hello ");
+    }
+}
@@ -0,0 +1,309 @@
+use crate::errors::MakerError;
+
+use super::{
+    edition::Edition,
+    lexer::{lex_with_comments, Comment, LogicalOperator},
+    nodes::{Block, Expression},
+    parser::Parser,
+};
+
+const INDENT: &str = "    ";
+
+/// Formats source code into the canonical style, keeping its comments
+pub fn format(source: &str, edition: Edition) -> Result<String, MakerError> {
+    let (tokens, comments) = lex_with_comments(source, "format".to_string(), edition)?;
+    let block = Parser::new(&tokens, edition).parse()?;
+
+    Ok(format_block(&block, &comments))
+}
+
+/// Pretty-prints a parsed program, putting the comments back in between its statements
+pub fn format_block(block: &Block, comments: &[Comment]) -> String {
+    let mut formatter = Formatter {
+        output: String::new(),
+        comments,
+        next_comment: 0,
+        last_line: None,
+    };
+
+    formatter.body(&block.nodes, 0, usize::MAX);
+    formatter.output
+}
+
+struct Formatter<'a> {
+    output: String,
+    comments: &'a [Comment<'a>],
+    next_comment: usize,
+    /// The last line of the source which was written, used to keep blank lines
+    last_line: Option<u32>,
+}
+
+impl<'a> Formatter<'a> {
+    fn indent(&mut self, indent: usize) {
+        self.output.push_str(&INDENT.repeat(indent));
+    }
+
+    /// Keeps (at most one) blank line if there was one in the source
+    fn blank_line_before(&mut self, line: u32) {
+        if let Some(last_line) = self.last_line {
+            if line > last_line + 1 {
+                self.output.push('\n');
+            }
+        }
+    }
+
+    fn next_comment(&self) -> Option<&'a Comment<'a>> {
+        self.comments.get(self.next_comment)
+    }
+
+    /// Writes every comment which comes before `position` on its own line
+    fn comments_before(&mut self, position: usize, indent: usize) {
+        while let Some(comment) = self.next_comment() {
+            if comment.location.start >= position {
+                break;
+            }
+
+            self.blank_line_before(comment.location.line);
+            self.indent(indent);
+            self.output.push_str(&format!("//{}\n", comment.value));
+            self.last_line = Some(comment.location.line);
+            self.next_comment += 1;
+        }
+    }
+
+    /// Writes a comment which sits at the end of `line`, after some code, as long as
+    /// it comes before `end` (so it is not after the `end` of the current block)
+    fn trailing_comment(&mut self, line: u32, end: usize) {
+        if let Some(comment) = self.next_comment() {
+            if comment.location.line == line && comment.location.start < end {
+                self.output.push_str(&format!(" //{}", comment.value));
+                self.next_comment += 1;
+            }
+        }
+    }
+
+    fn has_comment_before(&self, position: usize) -> bool {
+        self.next_comment()
+            .is_some_and(|x| x.location.start < position)
+    }
+
+    /// Puts every argument on its own line, so the comments in between them stay
+    /// next to the argument they were written by
+    fn multiline_args(&mut self, args: &[Expression], indent: usize, end: usize) {
+        self.output.push('\n');
+
+        for (i, arg) in args.iter().enumerate() {
+            let location = arg.get_location();
+
+            // Blank lines are only kept between statements
+            self.last_line = None;
+            self.comments_before(location.start, indent + 1);
+            self.indent(indent + 1);
+            self.expression(arg, indent + 1);
+            // A comment on the same line as the next argument belongs to that one
+            let next = match args.get(i + 1) {
+                Some(next) => {
+                    self.output.push(',');
+                    next.get_location().start
+                }
+                None => end,
+            };
+            self.trailing_comment(location.end_line, next);
+            self.output.push('\n');
+        }
+
+        self.last_line = None;
+        self.comments_before(end, indent + 1);
+        self.indent(indent);
+    }
+
+    fn body(&mut self, nodes: &[Expression], indent: usize, end: usize) {
+        for node in nodes {
+            let location = node.get_location();
+
+            self.comments_before(location.start, indent);
+            self.blank_line_before(location.line);
+            self.indent(indent);
+            self.expression(node, indent);
+            self.last_line = Some(location.end_line);
+            self.trailing_comment(location.end_line, end);
+            self.output.push('\n');
+        }
+
+        self.comments_before(end, indent);
+    }
+
+    fn block(&mut self, block: &Block, indent: usize) {
+        self.output.push_str("do\n");
+        self.last_line = Some(block.location.line);
+
+        self.body(&block.nodes, indent + 1, block.location.end);
+
+        self.indent(indent);
+        self.output.push_str("end");
+    }
+
+    fn expression(&mut self, expression: &Expression, indent: usize) {
+        match expression {
+            // ----- Special -----
+            Expression::Block(block) => self.block(block, indent),
+            // ----- Statements -----
+            Expression::VariableDeclaration(dec) => {
+                self.output.push_str(&format!("var {}", dec.name.name));
+                if let Some(annotation) = &dec.type_annotation {
+                    self.output.push_str(&format!(": {}", annotation.name));
+                }
+                self.output.push_str(" = ");
+                self.expression(&dec.value, indent);
+            }
+            Expression::FunctionDeclaration(dec) => {
+                let params: Vec<String> = dec
+                    .params
+                    .iter()
+                    .map(|param| match &param.type_annotation {
+                        Some(annotation) => format!("{}: {}", param.name.name, annotation.name),
+                        None => param.name.name.clone(),
+                    })
+                    .collect();
+                self.output
+                    .push_str(&format!("fn {}({})", dec.name.name, params.join(", ")));
+                if let Some(annotation) = &dec.return_annotation {
+                    self.output.push_str(&format!(" -> {}", annotation.name));
+                }
+                self.output.push(' ');
+                self.block(&dec.body, indent);
+            }
+            Expression::IfBlock(block) => {
+                self.output.push_str("if ");
+                self.expression(&block.test, indent);
+                self.output.push(' ');
+                self.block(&block.success, indent);
+
+                if let Some(alternate) = &block.alternate {
+                    self.output.push_str(" else ");
+                    self.expression(alternate, indent);
+                }
+            }
+            // ----- Expressions -----
+            Expression::Call(call) => {
+                self.expression(&call.callee, indent);
+                self.output.push('(');
+
+                if self.has_comment_before(call.location.end) {
+                    self.multiline_args(&call.args, indent, call.location.end);
+                    self.output.push(')');
+                    return;
+                }
+
+                for (i, arg) in call.args.iter().enumerate() {
+                    if i != 0 {
+                        self.output.push_str(", ");
+                    }
+                    self.expression(arg, indent);
+                }
+
+                self.output.push(')');
+            }
+            Expression::Member(member) => {
+                self.expression(&member.left, indent);
+                self.output.push('.');
+                self.expression(&member.right, indent);
+            }
+            Expression::Logical(logical) => {
+                self.expression(&logical.left, indent);
+                self.output.push_str(match logical.operator {
+                    LogicalOperator::Eq => " == ",
+                    LogicalOperator::Neq => " != ",
+                });
+                self.expression(&logical.right, indent);
+            }
+            // ----- Literals -----
+            Expression::Identifier(ident) => self.output.push_str(&ident.name),
+            Expression::Number(number) => self.output.push_str(&number.value.to_string()),
+            Expression::StringNode(string) => self.output.push_str(&quote(&string.value)),
+            Expression::Import(import) => {
+                self.output.push_str("import ");
+                self.output.push_str(&quote(&import.name.value));
+            }
+        }
+    }
+}
+
+/// Strings use double quotes, unless they contain one themselves
+fn quote(value: &str) -> String {
+    if value.contains('"') {
+        format!("'{}'", value)
+    } else {
+        format!("\"{}\"", value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLES: &[&str] = &[
+        "var x = 'a'\nmessage.reply(x)",
+        "// greet\nif message.content == \"!hi\" do // the command\n\n    message.reply(\"hi\")\n    // done\nend else if a != b do\nend",
+        "message.reply(\n    // what to say\n    \"hi\", // first\n    \"there\"\n    // nothing else\n)",
+        "log.info(time.format(\n    time.now(), // now\n    'YYYY'\n), 'x')\nprint(\n// nothing\n)",
+        "var t: string = import \"greetings\"\n\n\n// spaced out\nprint(t.hello) // hello",
+        "fn greet(name: string,who)->string do // greets\n// the name\nname\nend",
+    ];
+
+    fn comments(source: &str) -> Vec<&str> {
+        lex_with_comments(source, "test".to_string(), Edition::LATEST)
+            .unwrap()
+            .1
+            .iter()
+            .map(|x| x.value)
+            .collect()
+    }
+
+    #[test]
+    fn formatting_is_idempotent() {
+        for sample in SAMPLES {
+            let once = format(sample, Edition::LATEST).unwrap();
+            let twice = format(&once, Edition::LATEST).unwrap();
+
+            assert_eq!(once, twice, "formatting {:?} again changed it", sample);
+        }
+    }
+
+    #[test]
+    fn every_comment_is_kept_in_order() {
+        for sample in SAMPLES {
+            let formatted = format(sample, Edition::LATEST).unwrap();
+
+            assert_eq!(comments(sample), comments(&formatted), "{}", formatted);
+        }
+    }
+
+    #[test]
+    fn comments_stay_inside_calls() {
+        let formatted = format(
+            "message.reply(// what to say\n\"hi\", 'there' // second\n)\nprint(1)",
+            Edition::LATEST,
+        )
+        .unwrap();
+
+        assert_eq!(
+            formatted,
+            "message.reply(\n    // what to say\n    \"hi\",\n    \"there\" // second\n)\nprint(1)\n"
+        );
+    }
+
+    #[test]
+    fn calls_without_comments_stay_on_one_line() {
+        let formatted = format("message.reply(\n    \"hi\",\n    1\n)", Edition::LATEST).unwrap();
+
+        assert_eq!(formatted, "message.reply(\"hi\", 1)\n");
+    }
+
+    #[test]
+    fn functions_are_formatted() {
+        let formatted = format("fn f( a:string,b )->number do\n1\nend", Edition::LATEST).unwrap();
+
+        assert_eq!(formatted, "fn f(a: string, b) -> number do\n    1\nend\n");
+    }
+}
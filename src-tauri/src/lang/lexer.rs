@@ -1,5 +1,12 @@
+use std::sync::{Arc, OnceLock};
+
 use crate::errors::{MakerError, MakerErrorType};
 
+use super::edition::Edition;
+
+/// The context of `Location::no_location`, made once as it is used a lot
+static UNKNOWN_CONTEXT: OnceLock<Arc<str>> = OnceLock::new();
+
 #[derive(Debug, Copy, Clone)]
 pub enum TokenType {
     // ----- Literals -----
@@ -10,6 +17,8 @@ pub enum TokenType {
     // ----- Symbols -----
     Dot,
     Comma,
+    Colon,
+    Arrow,
     Assign,
     OpenBrace,
     CloseBrace,
@@ -25,31 +34,50 @@ pub enum TokenType {
     End,
     If,
     Else,
+    Import,
+    Fn,
 
     // ----- Special -----
     EOF,
     None,
 }
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, serde::Serialize, serde::Deserialize)]
 pub enum LogicalOperator {
     Eq,
     Neq,
 }
 
 #[derive(Debug, Clone)]
-pub struct Token {
-    pub value: String,
+pub struct Token<'a> {
+    pub value: &'a str,
     pub token_type: TokenType,
     pub location: Location,
 }
 
-#[derive(Debug, Clone, serde::Serialize)]
+/// Where something is in the source code
+///
+/// `start` and `end` are byte offsets into the source, `line` and `column`
+/// (and their `end_` counterparts) are 1-based and count characters
+///
+/// It defaults to `no_location`, for nodes which were not parsed from source code
+///
+/// `context` is shared by every location of a code piece, as there is one per token
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 pub struct Location {
-    pub start: u16,
-    pub end: u16,
-    pub line: u16,
-    pub context: String,
+    pub start: usize,
+    pub end: usize,
+    pub line: u32,
+    pub column: u32,
+    pub end_line: u32,
+    pub end_column: u32,
+    pub context: Arc<str>,
+}
+
+impl Default for Location {
+    fn default() -> Self {
+        Self::no_location()
+    }
 }
 
 impl Location {
@@ -57,146 +85,222 @@ impl Location {
         Location {
             start: 0,
             end: 0,
-            line: 0,
-            context: "<unknown>".to_string(),
+            line: 1,
+            column: 1,
+            end_line: 1,
+            end_column: 1,
+            context: UNKNOWN_CONTEXT
+                .get_or_init(|| Arc::from("<unknown>"))
+                .clone(),
+        }
+    }
+
+    /// Creates a location spanning from the start of this one to the end of `other`
+    pub fn to(&self, other: &Location) -> Location {
+        Location {
+            start: self.start,
+            end: other.end.max(self.start),
+            line: self.line,
+            column: self.column,
+            end_line: other.end_line,
+            end_column: other.end_column,
+            context: self.context.clone(),
         }
     }
 }
 
-macro_rules! eat {
-    ($a:ident, $b:ident) => {{
-        $a += 1;
-        $b.remove(0)
-    }};
+/// Walks over the source by byte index, so no characters are ever shifted around
+struct Cursor<'a> {
+    source: &'a str,
+    position: usize,
+    line: u32,
+    column: u32,
 }
 
-pub fn lex(contents: String, context: String) -> Result<Vec<Token>, MakerError> {
-    let mut chars: Vec<char> = contents.chars().collect();
-    let mut tokens: Vec<Token> = vec![];
+impl<'a> Cursor<'a> {
+    fn peek(&self) -> Option<char> {
+        self.source[self.position..].chars().next()
+    }
+
+    fn peek_second(&self) -> Option<char> {
+        let mut chars = self.source[self.position..].chars();
+        chars.next();
+        chars.next()
+    }
+
+    fn eat(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.position += c.len_utf8();
+
+        if c == '\n' {
+            self.line = self.line.saturating_add(1);
+            self.column = 1;
+        } else {
+            self.column = self.column.saturating_add(1);
+        }
+
+        Some(c)
+    }
 
-    let mut current_char: u16 = 0;
-    let mut current_line: u16 = 0;
+    fn eat_while<F: Fn(char) -> bool>(&mut self, predicate: F) {
+        while let Some(c) = self.peek() {
+            if !predicate(c) {
+                break;
+            }
+            self.eat();
+        }
+    }
 
-    while !chars.is_empty() {
-        let mut location = Location {
-            start: current_char,
-            end: current_char,
-            line: current_line,
+    fn slice(&self, start: usize) -> &'a str {
+        &self.source[start..self.position]
+    }
+
+    fn location(&self, context: &Arc<str>) -> Location {
+        Location {
+            start: self.position,
+            end: self.position,
+            line: self.line,
+            column: self.column,
+            end_line: self.line,
+            end_column: self.column,
             context: context.clone(),
-        };
+        }
+    }
 
-        let mut token_value: Option<String> = None;
-        let mut token_type: Option<TokenType> = None;
+    /// Finishes a location which was started with `location`
+    fn close(&self, location: &mut Location) {
+        location.end = self.position;
+        location.end_line = self.line;
+        location.end_column = self.column;
+    }
+}
 
-        let mut set_token = |value, t| {
-            token_value = Some(value);
-            token_type = Some(t);
-        };
+/// A `// comment`, which the parser never sees but the formatter keeps
+#[derive(Debug, Clone)]
+pub struct Comment<'a> {
+    pub value: &'a str,
+    pub location: Location,
+}
 
-        match chars[0] {
-            // Whitespace
-            ' ' | '\t' | '\r' => {
-                eat!(current_char, chars);
+pub fn lex<'a>(
+    contents: &'a str,
+    context: String,
+    edition: Edition,
+) -> Result<Vec<Token<'a>>, MakerError> {
+    Ok(lex_with_comments(contents, context, edition)?.0)
+}
+
+pub fn lex_with_comments<'a>(
+    contents: &'a str,
+    context: String,
+    edition: Edition,
+) -> Result<(Vec<Token<'a>>, Vec<Comment<'a>>), MakerError> {
+    let context: Arc<str> = context.into();
+    let mut cursor = Cursor {
+        source: contents,
+        position: 0,
+        line: 1,
+        column: 1,
+    };
+    let mut tokens: Vec<Token<'a>> = vec![];
+    let mut comments: Vec<Comment<'a>> = vec![];
+
+    while let Some(c) = cursor.peek() {
+        let mut location = cursor.location(&context);
+
+        let start = cursor.position;
+
+        let (token_value, token_type): (&'a str, TokenType) = match c {
+            // Whitespace & newlines
+            ' ' | '\t' | '\r' | '\n' => {
+                cursor.eat();
                 continue;
             }
-            // Newlines
-            '\n' => {
-                chars.remove(0);
-                current_char = 0;
-                current_line += 1;
+            // Comments
+            '/' if cursor.peek_second() == Some('/') => {
+                cursor.eat_while(|c| c != '\n');
+                cursor.close(&mut location);
+
+                comments.push(Comment {
+                    value: cursor.slice(start + 2).trim_end(),
+                    location,
+                });
                 continue;
             }
             // Identifiers
-            _ if chars[0].is_alphabetic() || chars[0] == '_' => {
-                let mut value = eat!(current_char, chars).to_string();
+            _ if c.is_alphabetic() || c == '_' => {
+                cursor.eat();
 
                 // Repeat until not alphanumeric
-                while !chars.is_empty() && (chars[0].is_alphanumeric() || chars[0] == '_') {
-                    value.push(eat!(current_char, chars));
-                }
+                cursor.eat_while(|c| c.is_alphanumeric() || c == '_');
+                let value = cursor.slice(start);
 
                 // Check if it is a keyword
-                let kw: Option<TokenType> = match value.as_str() {
-                    "var" => Some(TokenType::Var),
-                    "do" => Some(TokenType::Do),
-                    "end" => Some(TokenType::End),
-                    "if" => Some(TokenType::If),
-                    "else" => Some(TokenType::Else),
-                    _ => None,
+                let token_type = match value {
+                    "var" => TokenType::Var,
+                    "do" => TokenType::Do,
+                    "end" => TokenType::End,
+                    "if" => TokenType::If,
+                    "else" => TokenType::Else,
+                    "import" if edition >= Edition::V2 => TokenType::Import,
+                    "fn" if edition >= Edition::V3 => TokenType::Fn,
+                    _ => TokenType::Identifier,
                 };
 
-                // Add it
-                if let Some(kw) = kw {
-                    set_token(value, kw);
-                } else {
-                    set_token(value, TokenType::Identifier);
-                }
+                (value, token_type)
             }
             // Numbers
-            _ if chars[0].is_numeric() => {
-                let mut value = eat!(current_char, chars).to_string();
-
+            _ if c.is_numeric() => {
                 // Repeat until not numeric
-                while !chars.is_empty() && chars[0].is_numeric() {
-                    value.push(eat!(current_char, chars));
-                }
+                cursor.eat_while(|c| c.is_numeric());
 
-                set_token(value, TokenType::Number);
+                (cursor.slice(start), TokenType::Number)
             }
             // Strings
-            '"' => {
-                eat!(current_char, chars);
-                let mut value = String::new();
-
-                // Repeat until a "
-                while !chars.is_empty() && chars[0] != '"' {
-                    value.push(eat!(current_char, chars));
-                }
-
-                // Make sure there was a "
-                if chars.is_empty() || chars[0] != '"' {
+            '"' | '\'' => {
+                cursor.eat();
+                let mut opening = location.clone();
+                cursor.close(&mut opening);
+                let value_start = cursor.position;
+
+                // Repeat until the same quote
+                cursor.eat_while(|x| x != c);
+                let value = cursor.slice(value_start);
+
+                // Make sure there was a closing quote
+                if cursor.eat().is_none() {
                     return Err(MakerError::lang(
                         "Expected end of string",
-                        location,
-                        MakerErrorType::RuntimeError,
-                    ));
+                        cursor.location(&context),
+                        MakerErrorType::LexerError,
+                    )
+                    .with_label(opening, "the string starts here")
+                    .with_note(format!("strings must be closed with a {}", c)));
                 }
 
-                eat!(current_char, chars);
-
-                // Set it
-                set_token(value, TokenType::String)
+                (value, TokenType::String)
             }
             // Others
             _ => {
-                let mut symbol_type: TokenType = TokenType::None;
-
                 // Check 2 character long tokens
-                if chars.len() >= 2 {
-                    symbol_type = match chars[0] {
-                        '!' => match chars[1] {
-                            '=' => TokenType::Logical(LogicalOperator::Neq),
-                            _ => TokenType::None,
-                        },
-                        '=' => match chars[1] {
-                            '=' => TokenType::Logical(LogicalOperator::Eq),
-                            _ => TokenType::None,
-                        },
-                        _ => TokenType::None,
-                    };
-                    if !matches!(symbol_type, TokenType::None) {
-                        eat!(current_char, chars);
-                    }
-                }
+                let mut symbol_type = match (c, cursor.peek_second()) {
+                    ('!', Some('=')) => TokenType::Logical(LogicalOperator::Neq),
+                    ('=', Some('=')) => TokenType::Logical(LogicalOperator::Eq),
+                    ('-', Some('>')) => TokenType::Arrow,
+                    _ => TokenType::None,
+                };
 
-                if matches!(symbol_type, TokenType::None) {
-                    symbol_type = match chars[0] {
+                if !matches!(symbol_type, TokenType::None) {
+                    cursor.eat();
+                } else {
+                    symbol_type = match c {
                         '(' => TokenType::OpenBrace,
                         ')' => TokenType::CloseBrace,
                         '{' => TokenType::OpenCurly,
                         '}' => TokenType::CloseCurly,
                         '.' => TokenType::Dot,
                         ',' => TokenType::Comma,
+                        ':' => TokenType::Colon,
                         '=' => TokenType::Assign,
                         _ => TokenType::None,
                     };
@@ -204,47 +308,62 @@ pub fn lex(contents: String, context: String) -> Result<Vec<Token>, MakerError>
 
                 // Check if nothing was found
                 if matches!(symbol_type, TokenType::None) {
+                    cursor.eat();
+                    cursor.close(&mut location);
                     return Err(MakerError::lang(
-                        format!("Unexpected character: {}", chars[0]),
+                        format!("Unexpected character: {}", c),
                         location,
                         MakerErrorType::LexerError,
                     ));
                 }
 
-                // Add it
-                set_token(eat!(current_char, chars).to_string(), symbol_type);
+                cursor.eat();
+                (cursor.slice(start), symbol_type)
             }
-        }
-
-        // Add token
-        if matches!(token_value, None) || matches!(token_type, None) {
-            return Err(MakerError::lang(
-                "An unknown error occurred".to_string(),
-                location,
-                MakerErrorType::LexerError,
-            ));
-        }
+        };
 
-        location.end = current_char;
+        cursor.close(&mut location);
 
         tokens.push(Token {
-            value: token_value.unwrap(),
-            token_type: token_type.unwrap(),
+            value: token_value,
+            token_type,
             location,
         });
     }
 
     // Add EOF
     tokens.push(Token {
-        value: "".to_string(),
+        value: "",
         token_type: TokenType::EOF,
-        location: Location {
-            start: current_char,
-            end: current_char,
-            line: current_line,
-            context,
-        },
+        location: cursor.location(&context),
     });
 
-    Ok(tokens)
+    Ok((tokens, comments))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn locations_share_their_context() {
+        let tokens = lex("var x = \"a\"\nx", "piece".to_string(), Edition::LATEST).unwrap();
+
+        assert_eq!(&*tokens[0].location.context, "piece");
+        assert!(tokens
+            .iter()
+            .all(|x| Arc::ptr_eq(&x.location.context, &tokens[0].location.context)));
+    }
+
+    #[test]
+    fn token_values_borrow_the_source() {
+        let source = "var name = 'é'";
+        let tokens = lex(source, "piece".to_string(), Edition::LATEST).unwrap();
+        let values: Vec<&str> = tokens.iter().map(|x| x.value).collect();
+
+        assert_eq!(values, vec!["var", "name", "=", "é", ""]);
+        assert_eq!(tokens[1].value.as_ptr(), source[4..].as_ptr());
+        assert_eq!(tokens[3].location.column, 12);
+        assert_eq!(tokens[3].location.end_column, 15);
+    }
 }
@@ -0,0 +1,172 @@
+use std::collections::HashSet;
+
+use crate::errors::MakerError;
+
+use super::{
+    edition::Edition,
+    lexer::{lex, Location, LogicalOperator, Token, TokenType},
+};
+
+/// Something the migration assistant rewrote, and why
+#[derive(serde::Serialize, Debug, Clone)]
+pub struct MigrationChange {
+    pub location: Location,
+    pub message: String,
+}
+
+#[derive(serde::Serialize, Debug, Clone)]
+pub struct Migration {
+    pub source: String,
+    pub from: u8,
+    pub to: u8,
+    pub changes: Vec<MigrationChange>,
+}
+
+/// A replacement of the bytes `start..end` of the source
+struct Edit {
+    start: usize,
+    end: usize,
+    text: String,
+}
+
+/// Rewrites source code written in `from` so that it does the same thing in the latest edition
+pub fn migrate(source: &str, from: Edition) -> Result<Migration, MakerError> {
+    let mut edits: Vec<Edit> = vec![];
+    let mut changes: Vec<MigrationChange> = vec![];
+
+    if from < Edition::V2 {
+        to_v2(source, &mut edits, &mut changes)?;
+    }
+    if from < Edition::V3 {
+        to_v3(source, &mut edits, &mut changes)?;
+    }
+
+    // Apply from the back, so the earlier offsets stay correct
+    let mut migrated = source.to_string();
+    edits.sort_by_key(|x| x.start);
+    for edit in edits.iter().rev() {
+        migrated.replace_range(edit.start..edit.end, &edit.text);
+    }
+
+    Ok(Migration {
+        source: migrated,
+        from: from.number(),
+        to: Edition::LATEST.number(),
+        changes,
+    })
+}
+
+fn to_v2(
+    source: &str,
+    edits: &mut Vec<Edit>,
+    changes: &mut Vec<MigrationChange>,
+) -> Result<(), MakerError> {
+    let tokens = lex(source, "migrate".to_string(), Edition::V1)?;
+
+    // `import` became a keyword, so variables called that need a new name
+    let rename = unused_name(&tokens, "import");
+
+    for token in &tokens {
+        match token.token_type {
+            TokenType::Logical(LogicalOperator::Neq) => {
+                edits.push(Edit {
+                    start: token.location.start,
+                    end: token.location.end,
+                    text: "==".to_string(),
+                });
+                changes.push(MigrationChange {
+                    location: token.location.clone(),
+                    message: "!= used to compare for equality, so it was replaced with =="
+                        .to_string(),
+                });
+            }
+            TokenType::Identifier if token.value == "import" => {
+                edits.push(Edit {
+                    start: token.location.start,
+                    end: token.location.end,
+                    text: rename.clone(),
+                });
+                changes.push(MigrationChange {
+                    location: token.location.clone(),
+                    message: format!("import is now a keyword, so it was renamed to {}", rename),
+                });
+            }
+            _ => {}
+        }
+    }
+
+    Ok(())
+}
+
+fn to_v3(
+    source: &str,
+    edits: &mut Vec<Edit>,
+    changes: &mut Vec<MigrationChange>,
+) -> Result<(), MakerError> {
+    // Lexing as edition 2 only changes what `import` is, which does not matter here
+    let tokens = lex(source, "migrate".to_string(), Edition::V2)?;
+
+    // `fn` became a keyword, so variables called that need a new name
+    let rename = unused_name(&tokens, "fn");
+
+    for token in &tokens {
+        if matches!(token.token_type, TokenType::Identifier) && token.value == "fn" {
+            edits.push(Edit {
+                start: token.location.start,
+                end: token.location.end,
+                text: rename.clone(),
+            });
+            changes.push(MigrationChange {
+                location: token.location.clone(),
+                message: format!("fn is now a keyword, so it was renamed to {}", rename),
+            });
+        }
+    }
+
+    Ok(())
+}
+
+/// A name for variables called `keyword` which no other variable has
+fn unused_name(tokens: &[Token], keyword: &str) -> String {
+    let names: HashSet<&str> = tokens
+        .iter()
+        .filter(|x| matches!(x.token_type, TokenType::Identifier))
+        .map(|x| x.value)
+        .collect();
+
+    let mut rename = format!("{}_", keyword);
+    while names.contains(rename.as_str()) {
+        rename.push('_');
+    }
+    rename
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keywords_used_as_variables_are_renamed() {
+        let migration = migrate("var fn = 1\nvar fn_ = fn", Edition::V2).unwrap();
+
+        assert_eq!(migration.source, "var fn__ = 1\nvar fn_ = fn__");
+        assert_eq!(migration.changes.len(), 2);
+        assert_eq!((migration.from, migration.to), (2, 3));
+    }
+
+    #[test]
+    fn the_first_edition_is_migrated_all_the_way() {
+        let migration = migrate("var import = 1\nvar fn = import != 1", Edition::V1).unwrap();
+
+        assert_eq!(migration.source, "var import_ = 1\nvar fn_ = import_ == 1");
+        assert_eq!(migration.changes.len(), 4);
+    }
+
+    #[test]
+    fn the_latest_edition_is_left_alone() {
+        let migration = migrate("fn f() do\nend", Edition::LATEST).unwrap();
+
+        assert_eq!(migration.source, "fn f() do\nend");
+        assert!(migration.changes.is_empty());
+    }
+}
@@ -1,6 +1,23 @@
+pub mod assertions;
+pub mod ast;
+pub mod builtins;
+pub mod debugger;
+pub mod diagnostics;
+pub mod edition;
+pub mod engine;
+pub mod formatter;
 pub mod lexer;
+pub mod migrate;
+pub mod natives;
 pub mod nodes;
+pub mod optimizer;
 pub mod parser;
+pub mod profiler;
 pub mod runtime;
 pub mod scope;
+pub mod semantic;
+pub mod symbols;
+pub mod testing;
+pub mod time;
+pub mod types;
 pub mod values;
@@ -0,0 +1,345 @@
+use std::{collections::HashMap, fmt, future::Future, sync::Arc};
+
+use crate::errors::{MakerError, MakerErrorType};
+
+use super::{
+    builtins,
+    lexer::Location,
+    types::Type,
+    values::{self, NativeFunction, NativeFuture, RuntimeValue},
+};
+
+/// A value which can be written in a declaration, used for defaults
+#[derive(Debug, PartialEq)]
+pub enum Literal {
+    Null,
+    Number(f64),
+    String(&'static str),
+    Boolean(bool),
+}
+
+impl Literal {
+    pub fn value(&self) -> RuntimeValue {
+        match self {
+            Literal::Null => values::Null::make(),
+            Literal::Number(number) => values::Number::make(*number),
+            Literal::String(string) => values::StringValue::make(string.to_string()),
+            Literal::Boolean(boolean) => values::Boolean::make(*boolean),
+        }
+    }
+}
+
+impl fmt::Display for Literal {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Literal::Null => write!(f, "null"),
+            Literal::Number(number) => write!(f, "{}", number),
+            Literal::String(string) => write!(f, "{:?}", string),
+            Literal::Boolean(boolean) => write!(f, "{}", boolean),
+        }
+    }
+}
+
+/// What a parameter is when the argument is left out
+#[derive(Debug, PartialEq)]
+pub enum ParamDefault {
+    /// It can not be left out
+    Required,
+    /// It is null
+    Optional,
+    Value(Literal),
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Param {
+    pub name: &'static str,
+    pub ty: Type,
+    pub default: ParamDefault,
+}
+
+impl fmt::Display for Param {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.default {
+            ParamDefault::Required => write!(f, "{}: {}", self.name, self.ty),
+            ParamDefault::Optional => write!(f, "{}?: {}", self.name, self.ty),
+            ParamDefault::Value(value) => write!(f, "{}: {} = {}", self.name, self.ty, value),
+        }
+    }
+}
+
+/// The declared parameters and return type of a native function
+#[derive(Debug, PartialEq)]
+pub struct Signature {
+    pub params: &'static [Param],
+    pub returns: Type,
+}
+
+impl Signature {
+    /// How the function is shown in the editor, e.g. `message.reply(content: string) -> null`
+    pub fn describe(&self, name: &str) -> String {
+        let params: Vec<String> = self.params.iter().map(|x| x.to_string()).collect();
+        format!("{}({}) -> {}", name, params.join(", "), self.returns)
+    }
+
+    /// Checks the arguments of a call to the function `name` and fills in the ones which
+    /// were left out, `locations` are where each argument was written
+    pub fn check_args(
+        &self,
+        name: &str,
+        args: Vec<RuntimeValue>,
+        locations: &[Location],
+        call: &Location,
+    ) -> Result<Vec<RuntimeValue>, MakerError> {
+        let note = format!("the function is {}", self.describe(name));
+
+        if args.len() > self.params.len() {
+            return Err(MakerError::lang(
+                format!(
+                    "{} takes {} argument(s) but {} were given",
+                    name,
+                    self.params.len(),
+                    args.len()
+                ),
+                locations[self.params.len()].clone(),
+                MakerErrorType::RuntimeError,
+            )
+            .with_note(note));
+        }
+
+        let mut args = args.into_iter();
+        let mut checked = vec![];
+
+        for (i, param) in self.params.iter().enumerate() {
+            let value = match (args.next(), &param.default) {
+                (Some(RuntimeValue::Null(_)), ParamDefault::Optional) => values::Null::make(),
+                (Some(value), _) if param.ty.matches(&value) => value,
+                (Some(value), _) => {
+                    return Err(MakerError::lang(
+                        format!(
+                            "Invalid type for argument {} ({}), expected {} but found {}",
+                            i,
+                            param.name,
+                            param.ty,
+                            value.type_name()
+                        ),
+                        locations[i].clone(),
+                        MakerErrorType::RuntimeError,
+                    )
+                    .with_note(note));
+                }
+                (None, ParamDefault::Required) => {
+                    return Err(MakerError::lang(
+                        format!("Missing argument {} ({}) of {}", i, param.name, name),
+                        call.clone(),
+                        MakerErrorType::RuntimeError,
+                    )
+                    .with_note(note));
+                }
+                (None, ParamDefault::Optional) => values::Null::make(),
+                (None, ParamDefault::Value(value)) => value.value(),
+            };
+
+            checked.push(value);
+        }
+
+        Ok(checked)
+    }
+}
+
+type Implementation<C> =
+    Arc<dyn Fn(Arc<C>, Vec<RuntimeValue>, Option<Location>) -> NativeFuture + Send + Sync>;
+
+/// The implementations of the native functions declared in `BUILTINS`, `C` is
+/// whatever they need to run, e.g. the Discord message which ran the command
+///
+/// The arguments are checked against the declared signature before an
+/// implementation is called, so it gets exactly one value per parameter
+pub struct NativeRegistry<C> {
+    natives: Vec<(&'static str, &'static Signature, Implementation<C>)>,
+}
+
+impl<C: Send + Sync + 'static> Default for NativeRegistry<C> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<C: Send + Sync + 'static> NativeRegistry<C> {
+    pub fn new() -> Self {
+        Self { natives: vec![] }
+    }
+
+    /// Implements the native function at `path`, e.g. `message.reply`, it is also
+    /// given where it was called from
+    ///
+    /// Panics if it is not declared as a function in `BUILTINS`, as the
+    /// editor and type checker would not know about it
+    pub fn register<F, R>(mut self, path: &'static str, func: F) -> Self
+    where
+        F: Fn(Arc<C>, Vec<RuntimeValue>, Option<Location>) -> R + Send + Sync + 'static,
+        R: Future<Output = Result<RuntimeValue, MakerError>> + Send + 'static,
+    {
+        let signature = match builtins::find_path(path).map(|x| x.ty) {
+            Some(Type::Function(Some(signature))) => signature,
+            _ => panic!("{} is not declared as a native function", path),
+        };
+
+        self.natives.push((
+            path,
+            signature,
+            Arc::new(move |context, args, location| Box::pin(func(context, args, location))),
+        ));
+        self
+    }
+
+    /// The native function at `path`, with `context` given to it
+    pub fn get(&self, path: &str, context: Arc<C>) -> Option<RuntimeValue> {
+        let (_, signature, implementation) = self.natives.iter().find(|x| x.0 == path)?;
+        Some(bind(signature, implementation.clone(), context))
+    }
+
+    /// The natives which are members of `object`, with `context` given to them
+    pub fn bind(&self, object: &str, context: Arc<C>) -> HashMap<String, RuntimeValue> {
+        self.natives
+            .iter()
+            .filter_map(|(path, signature, implementation)| {
+                let member = path.strip_prefix(object)?.strip_prefix('.')?;
+                Some((
+                    member.to_string(),
+                    bind(signature, implementation.clone(), context.clone()),
+                ))
+            })
+            .collect()
+    }
+}
+
+fn bind<C: Send + Sync + 'static>(
+    signature: &'static Signature,
+    implementation: Implementation<C>,
+    context: Arc<C>,
+) -> RuntimeValue {
+    RuntimeValue::NativeFunction(NativeFunction {
+        func: Arc::new(move |args, location| {
+            implementation(context.clone(), args, location.cloned())
+        }),
+        signature: Some(signature),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn signature(path: &str) -> &'static Signature {
+        match builtins::find_path(path).unwrap().ty {
+            Type::Function(Some(signature)) => signature,
+            _ => panic!("{} has no signature", path),
+        }
+    }
+
+    /// Calls a native function the way the interpreter does, its arguments checked first
+    async fn call(function: RuntimeValue, args: Vec<RuntimeValue>) -> RuntimeValue {
+        let RuntimeValue::NativeFunction(function) = function else {
+            panic!("not a native function");
+        };
+        let locations = vec![Location::no_location(); args.len()];
+        let args = function
+            .signature
+            .unwrap()
+            .check_args("test", args, &locations, &Location::no_location())
+            .unwrap();
+
+        (function.func)(args, None).await.unwrap()
+    }
+
+    #[test]
+    fn left_out_arguments_get_their_default() {
+        let format = signature("datetime.format");
+
+        assert_eq!(
+            format.describe("datetime.format"),
+            "datetime.format(pattern: string = \"%Y-%m-%d %H:%M\", timezone: string = \"UTC\") -> string"
+        );
+
+        let args = format
+            .check_args(
+                "datetime.format",
+                vec![values::StringValue::make("%H".to_string())],
+                &[Location::no_location()],
+                &Location::no_location(),
+            )
+            .unwrap();
+        let args: Vec<String> = args.iter().map(|x| x.to_string()).collect();
+        assert_eq!(args, vec!["%H", "UTC"]);
+    }
+
+    #[test]
+    fn arguments_are_checked_against_the_signature() {
+        let format = signature("datetime.format");
+        let check = |args: Vec<RuntimeValue>| {
+            let locations = vec![Location::no_location(); args.len()];
+            let err = format
+                .check_args(
+                    "datetime.format",
+                    args,
+                    &locations,
+                    &Location::no_location(),
+                )
+                .unwrap_err();
+            (err.message.clone(), err.notes.clone())
+        };
+
+        let (message, notes) = check(vec![values::Number::make(1.0)]);
+        assert_eq!(
+            message,
+            "Invalid type for argument 0 (pattern), expected string but found number"
+        );
+        assert_eq!(
+            notes,
+            vec![format!(
+                "the function is {}",
+                format.describe("datetime.format")
+            )]
+        );
+
+        let (message, _) = check(vec![values::Null::make(); 3]);
+        assert_eq!(
+            message,
+            "datetime.format takes 2 argument(s) but 3 were given"
+        );
+    }
+
+    #[tokio::test]
+    async fn natives_are_bound_to_their_context() {
+        let registry = NativeRegistry::<String>::new()
+            .register("log.info", |context, args, _| async move {
+                Ok(values::StringValue::make(format!(
+                    "{} {}",
+                    context, args[0]
+                )))
+            })
+            .register("print", |_, _, _| async { Ok(values::Null::make()) });
+        let context = Arc::new("[info]".to_string());
+
+        let log = registry.bind("log", context.clone());
+        assert_eq!(log.keys().collect::<Vec<_>>(), vec!["info"]);
+        let info = log["info"].clone();
+        assert_eq!(
+            call(info, vec![values::StringValue::make("hi".to_string())])
+                .await
+                .to_string(),
+            "[info] hi"
+        );
+
+        assert!(registry.get("print", context.clone()).is_some());
+        assert!(registry.get("log.warn", context).is_none());
+    }
+
+    #[test]
+    #[should_panic(expected = "message.content is not declared as a native function")]
+    fn only_declared_functions_can_be_registered() {
+        NativeRegistry::<()>::new().register("message.content", |_, _, _| async {
+            Ok(values::Null::make())
+        });
+    }
+}
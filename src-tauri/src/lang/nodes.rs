@@ -1,6 +1,8 @@
 use super::lexer::{Location, LogicalOperator};
 
-#[derive(Debug, Clone)]
+/// Serialized with a `type` field naming the variant, e.g. `{"type": "Number", "value": 1, ...}`
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(tag = "type")]
 pub enum Expression {
     Identifier(Identifier),
     Block(Block),
@@ -11,86 +13,130 @@ pub enum Expression {
     Logical(Logical),
     IfBlock(IfBlock),
     VariableDeclaration(VariableDeclaration),
+    FunctionDeclaration(FunctionDeclaration),
+    Import(Import),
 }
 
 impl Expression {
     pub fn get_location(&self) -> Location {
-        match self.clone() {
-            Expression::Identifier(v) => v.location,
-            Expression::Number(v) => v.location,
-            Expression::Block(v) => v.location,
-            Expression::Call(v) => v.location,
-            Expression::Logical(v) => v.location,
-            Expression::Member(v) => v.location,
-            Expression::StringNode(v) => v.location,
-            Expression::IfBlock(v) => v.location,
-            Expression::VariableDeclaration(v) => v.location,
+        match self {
+            Expression::Identifier(v) => v.location.clone(),
+            Expression::Number(v) => v.location.clone(),
+            Expression::Block(v) => v.location.clone(),
+            Expression::Call(v) => v.location.clone(),
+            Expression::Logical(v) => v.location.clone(),
+            Expression::Member(v) => v.location.clone(),
+            Expression::StringNode(v) => v.location.clone(),
+            Expression::IfBlock(v) => v.location.clone(),
+            Expression::VariableDeclaration(v) => v.location.clone(),
+            Expression::FunctionDeclaration(v) => v.location.clone(),
+            Expression::Import(v) => v.location.clone(),
         }
     }
 }
 
 // ----- Specials -----
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 pub struct Block {
     pub nodes: Vec<Expression>,
+    #[serde(default)]
     pub location: Location,
 }
 
 // ----- Statements -----
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 pub struct VariableDeclaration {
     pub name: Identifier,
+    /// The name of the type after the colon, e.g. `number` in `var n: number = 1`
+    pub type_annotation: Option<Identifier>,
     pub value: Box<Expression>,
+    #[serde(default)]
+    pub location: Location,
+}
+
+/// `fn name(param: type) -> type do ... end`, both kinds of annotation are optional
+///
+/// The body sees the variables which were declared before the function, and
+/// evaluates to what the function returns
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct FunctionDeclaration {
+    pub name: Identifier,
+    pub params: Vec<Parameter>,
+    /// The name of the type after the arrow, e.g. `number` in `fn f() -> number`
+    pub return_annotation: Option<Identifier>,
+    pub body: Block,
+    #[serde(default)]
+    pub location: Location,
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct Parameter {
+    pub name: Identifier,
+    pub type_annotation: Option<Identifier>,
+}
+
+/// `import "name"`, evaluates to an object of the library's top level variables
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct Import {
+    pub name: StringNode,
+    #[serde(default)]
     pub location: Location,
 }
 
 // ----- Expressions -----
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 pub struct Call {
     pub callee: Box<Expression>,
     pub args: Vec<Expression>,
+    #[serde(default)]
     pub location: Location,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 pub struct Member {
     pub left: Box<Expression>,
     pub right: Box<Expression>,
+    #[serde(default)]
     pub location: Location,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 pub struct Logical {
     pub left: Box<Expression>,
     pub right: Box<Expression>,
     pub operator: LogicalOperator,
+    #[serde(default)]
     pub location: Location,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 pub struct IfBlock {
     pub test: Box<Expression>,
     pub success: Block,
     pub alternate: Option<Box<Expression>>,
+    #[serde(default)]
     pub location: Location,
 }
 
 // ----- Literals -----
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 pub struct Identifier {
     pub name: String,
+    #[serde(default)]
     pub location: Location,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 pub struct Number {
     pub value: f64,
+    #[serde(default)]
     pub location: Location,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 pub struct StringNode {
     pub value: String,
+    #[serde(default)]
     pub location: Location,
 }
@@ -0,0 +1,579 @@
+use std::collections::HashMap;
+
+use super::{
+    edition::Edition,
+    lexer::Location,
+    nodes::{Block, Expression, Identifier, Number, StringNode},
+    values::{self, RuntimeValue},
+};
+
+/// Simplifies a program without changing what it does when run in `edition`,
+/// `globals` are the names which are declared before the script runs and
+/// `library` is whether the program is imported rather than run
+///
+/// - Comparisons between literals are folded into `true` or `false`
+/// - `if` blocks with a literal test are replaced by the branch which would run
+/// - `var`s of literals which are used exactly once are inlined, except in libraries
+pub fn optimize(block: Block, globals: &[&str], edition: Edition, library: bool) -> Block {
+    let folder = Folder { edition };
+    let block = folder.block(block);
+
+    // Importing a library gives every variable it declared, so they all have to stay
+    if library {
+        return block;
+    }
+
+    // Inlining can make more tests constant, so fold again after
+    let mut usages = Usages::default();
+    usages.block(&block, false);
+    let inlinable = usages.inlinable(globals);
+
+    if inlinable.is_empty() {
+        return block;
+    }
+
+    let mut inliner = Inliner {
+        inlinable,
+        values: HashMap::new(),
+    };
+    folder.block(inliner.block(block))
+}
+
+/// The value of an expression if it is known without running anything
+fn constant(expression: &Expression) -> Option<RuntimeValue> {
+    match expression {
+        Expression::Number(number) => Some(values::Number::make(number.value)),
+        Expression::StringNode(string) => Some(values::StringValue::make(string.value.clone())),
+        // These are in every scope and can not be redeclared
+        Expression::Identifier(ident) if ident.name == "true" => Some(values::Boolean::make(true)),
+        Expression::Identifier(ident) if ident.name == "false" => {
+            Some(values::Boolean::make(false))
+        }
+        _ => None,
+    }
+}
+
+/// An expression which evaluates to `value`
+fn literal(value: RuntimeValue, location: Location) -> Expression {
+    match value {
+        RuntimeValue::Number(number) => Expression::Number(Number {
+            value: number.value,
+            location,
+        }),
+        RuntimeValue::StringValue(string) => Expression::StringNode(StringNode {
+            value: string.value,
+            location,
+        }),
+        value => Expression::Identifier(Identifier {
+            name: value.to_string(),
+            location,
+        }),
+    }
+}
+
+/// Evaluates to null, like a `var` or an `if` which did not run
+fn empty(location: Location) -> Expression {
+    Expression::Block(Block {
+        nodes: vec![],
+        location,
+    })
+}
+
+fn is_empty(expression: &Expression) -> bool {
+    matches!(expression, Expression::Block(block) if block.nodes.is_empty())
+}
+
+/// Folds constants, which depends on the edition for `!=`
+struct Folder {
+    edition: Edition,
+}
+
+impl Folder {
+    fn block(&self, block: Block) -> Block {
+        let mut nodes: Vec<Expression> = block
+            .nodes
+            .into_iter()
+            .map(|x| self.expression(x))
+            .collect();
+
+        // Empty blocks do nothing, but the last node is still the value of the block
+        let last = nodes.len().saturating_sub(1);
+        let mut index = 0;
+        nodes.retain(|node| {
+            index += 1;
+            index - 1 == last || !is_empty(node)
+        });
+
+        Block {
+            nodes,
+            location: block.location,
+        }
+    }
+
+    fn expression(&self, expression: Expression) -> Expression {
+        match expression {
+            Expression::Block(block) => Expression::Block(self.block(block)),
+            Expression::VariableDeclaration(mut dec) => {
+                dec.value = Box::new(self.expression(*dec.value));
+                Expression::VariableDeclaration(dec)
+            }
+            Expression::FunctionDeclaration(mut dec) => {
+                dec.body = self.block(dec.body);
+                Expression::FunctionDeclaration(dec)
+            }
+            Expression::Call(mut call) => {
+                call.callee = Box::new(self.expression(*call.callee));
+                call.args = call.args.into_iter().map(|x| self.expression(x)).collect();
+                Expression::Call(call)
+            }
+            Expression::Member(mut member) => {
+                // The right side is a key, so there is nothing to fold
+                member.left = Box::new(self.expression(*member.left));
+                Expression::Member(member)
+            }
+            Expression::Logical(mut logical) => {
+                logical.left = Box::new(self.expression(*logical.left));
+                logical.right = Box::new(self.expression(*logical.right));
+
+                match (constant(&logical.left), constant(&logical.right)) {
+                    (Some(left), Some(right)) => {
+                        let result = self.edition.compare(logical.operator, left.equals(&right));
+                        literal(values::Boolean::make(result), logical.location)
+                    }
+                    _ => Expression::Logical(logical),
+                }
+            }
+            Expression::IfBlock(mut block) => {
+                block.test = Box::new(self.expression(*block.test));
+                block.success = self.block(block.success);
+                block.alternate = block.alternate.map(|x| Box::new(self.expression(*x)));
+
+                match constant(&block.test) {
+                    Some(test) if test.is_truthy() => Expression::Block(block.success),
+                    Some(_) => match block.alternate {
+                        Some(alternate) => *alternate,
+                        None => empty(block.location),
+                    },
+                    None => Expression::IfBlock(block),
+                }
+            }
+            expression => expression,
+        }
+    }
+}
+
+/// Where a variable is declared and used, in the order they are evaluated
+#[derive(Default)]
+struct Usages {
+    order: usize,
+    /// (order, whether it might not run, whether the value is a literal)
+    declarations: HashMap<String, Vec<(usize, bool, bool)>>,
+    references: HashMap<String, Vec<usize>>,
+}
+
+impl Usages {
+    fn next(&mut self) -> usize {
+        self.order += 1;
+        self.order
+    }
+
+    fn block(&mut self, block: &Block, conditional: bool) {
+        for node in &block.nodes {
+            self.expression(node, conditional);
+        }
+    }
+
+    fn expression(&mut self, expression: &Expression, conditional: bool) {
+        match expression {
+            Expression::Block(block) => self.block(block, conditional),
+            Expression::VariableDeclaration(dec) => {
+                // The value is evaluated before the variable exists
+                self.expression(&dec.value, conditional);
+
+                let order = self.next();
+                // Annotated values are checked when declared, so they have to stay
+                let is_literal = constant(&dec.value).is_some() && dec.type_annotation.is_none();
+                self.declarations
+                    .entry(dec.name.name.clone())
+                    .or_default()
+                    .push((order, conditional, is_literal));
+            }
+            Expression::FunctionDeclaration(dec) => {
+                let order = self.next();
+                self.declarations
+                    .entry(dec.name.name.clone())
+                    .or_default()
+                    .push((order, conditional, false));
+
+                // The body might run any number of times, and its parameters hide
+                // the variables of the same name, so those are never inlined
+                for param in &dec.params {
+                    self.declarations
+                        .entry(param.name.name.clone())
+                        .or_default()
+                        .push((order, true, false));
+                }
+                self.block(&dec.body, true);
+            }
+            Expression::Call(call) => {
+                self.expression(&call.callee, conditional);
+                for arg in &call.args {
+                    self.expression(arg, conditional);
+                }
+            }
+            Expression::Member(member) => self.expression(&member.left, conditional),
+            Expression::Logical(logical) => {
+                self.expression(&logical.left, conditional);
+                self.expression(&logical.right, conditional);
+            }
+            Expression::IfBlock(block) => {
+                self.expression(&block.test, conditional);
+                self.block(&block.success, true);
+                if let Some(alternate) = &block.alternate {
+                    self.expression(alternate, true);
+                }
+            }
+            Expression::Identifier(ident) => {
+                let order = self.next();
+                self.references
+                    .entry(ident.name.clone())
+                    .or_default()
+                    .push(order);
+            }
+            Expression::Number(_) | Expression::StringNode(_) | Expression::Import(_) => {}
+        }
+    }
+
+    /// The variables which are always declared as a literal before their only use
+    fn inlinable(&self, globals: &[&str]) -> Vec<String> {
+        self.declarations
+            .iter()
+            .filter(|(name, declarations)| {
+                let references = match self.references.get(*name) {
+                    Some(references) => references,
+                    None => return false,
+                };
+
+                // Redeclaring is an error which has to stay
+                match (declarations.as_slice(), references.as_slice()) {
+                    ([(declared, false, true)], [used]) => {
+                        declared < used && !globals.contains(&name.as_str())
+                    }
+                    _ => false,
+                }
+            })
+            .map(|(name, _)| name.clone())
+            .collect()
+    }
+}
+
+struct Inliner {
+    inlinable: Vec<String>,
+    values: HashMap<String, RuntimeValue>,
+}
+
+impl Inliner {
+    fn block(&mut self, block: Block) -> Block {
+        Block {
+            nodes: block
+                .nodes
+                .into_iter()
+                .map(|x| self.expression(x))
+                .collect(),
+            location: block.location,
+        }
+    }
+
+    fn expression(&mut self, expression: Expression) -> Expression {
+        match expression {
+            Expression::Block(block) => Expression::Block(self.block(block)),
+            Expression::VariableDeclaration(mut dec) => {
+                if self.inlinable.contains(&dec.name.name) {
+                    if let Some(value) = constant(&dec.value) {
+                        self.values.insert(dec.name.name, value);
+                        return empty(dec.location);
+                    }
+                }
+
+                dec.value = Box::new(self.expression(*dec.value));
+                Expression::VariableDeclaration(dec)
+            }
+            Expression::FunctionDeclaration(mut dec) => {
+                dec.body = self.block(dec.body);
+                Expression::FunctionDeclaration(dec)
+            }
+            Expression::Call(mut call) => {
+                call.callee = Box::new(self.expression(*call.callee));
+                call.args = call.args.into_iter().map(|x| self.expression(x)).collect();
+                Expression::Call(call)
+            }
+            Expression::Member(mut member) => {
+                member.left = Box::new(self.expression(*member.left));
+                Expression::Member(member)
+            }
+            Expression::Logical(mut logical) => {
+                logical.left = Box::new(self.expression(*logical.left));
+                logical.right = Box::new(self.expression(*logical.right));
+                Expression::Logical(logical)
+            }
+            Expression::IfBlock(mut block) => {
+                block.test = Box::new(self.expression(*block.test));
+                block.success = self.block(block.success);
+                block.alternate = block.alternate.map(|x| Box::new(self.expression(*x)));
+                Expression::IfBlock(block)
+            }
+            Expression::Identifier(ident) => match self.values.remove(&ident.name) {
+                Some(value) => literal(value, ident.location),
+                None => Expression::Identifier(ident),
+            },
+            expression => expression,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+    use crate::lang::{engine::Engine, lexer::lex, parser::Parser, runtime::Program, scope::Scope};
+
+    /// A small xorshift generator, so that failures can be reproduced from their seed
+    struct Random(u64);
+
+    impl Random {
+        fn below(&mut self, max: usize) -> usize {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            (self.0 % max as u64) as usize
+        }
+    }
+
+    /// A random program which mostly parses, using everything the optimizer looks at
+    fn generate(random: &mut Random, depth: usize) -> String {
+        let mut code = String::new();
+
+        for _ in 0..random.below(6) + 1 {
+            let line = match random.below(if depth < 2 { 6 } else { 4 }) {
+                0 | 1 => format!("var v{} = {}", random.below(4), value(random)),
+                2 => format!("var v{}: string = {}", random.below(4), value(random)),
+                3 => format!("emit({})", value(random)),
+                // The parameter hides a variable, which must not be inlined into the body
+                5 => format!(
+                    "fn f{}(v{}) do\n{}\nend",
+                    random.below(2),
+                    random.below(4),
+                    generate(random, depth + 1)
+                ),
+                _ => {
+                    let mut block = format!(
+                        "if {} do\n{}\nend",
+                        value(random),
+                        generate(random, depth + 1)
+                    );
+                    if random.below(2) == 0 {
+                        block.push_str(&format!(" else do\n{}\nend", generate(random, depth + 1)));
+                    }
+                    block
+                }
+            };
+            code.push_str(&line);
+            code.push('\n');
+        }
+
+        code
+    }
+
+    fn value(random: &mut Random) -> String {
+        let operand = |random: &mut Random| match random.below(6) {
+            0 => random.below(3).to_string(),
+            1 => ["\"a\"", "\"b\""][random.below(2)].to_string(),
+            2 => ["true", "false"][random.below(2)].to_string(),
+            _ => format!("v{}", random.below(4)),
+        };
+
+        match random.below(4) {
+            0 => format!(
+                "{} {} {}",
+                operand(random),
+                ["==", "!="][random.below(2)],
+                operand(random)
+            ),
+            1 => format!("f{}({})", random.below(2), operand(random)),
+            _ => operand(random),
+        }
+    }
+
+    fn parse(code: &str, edition: Edition) -> Option<Block> {
+        let tokens = lex(code, "test".to_string(), edition).ok()?;
+        Parser::new(&tokens, edition).parse().ok()
+    }
+
+    fn globals() -> Vec<String> {
+        let mut globals: Vec<String> = Scope::new().variables.into_keys().collect();
+        globals.push("emit".to_string());
+        globals
+    }
+
+    /// Runs a program, returning its result and everything it emitted
+    async fn run(block: Block, edition: Edition) -> (Result<String, String>, Vec<String>) {
+        let emitted = Arc::new(Mutex::new(vec![]));
+        let log = emitted.clone();
+        let mut engine = Engine::new();
+        engine.register_fn("emit", move |args| {
+            log.lock().unwrap().push(args[0].to_string());
+            Ok(values::Null::make())
+        });
+
+        let program = Program {
+            block: Arc::new(block),
+            edition,
+        };
+        let result = engine
+            .eval(&program, HashMap::new())
+            .await
+            .map(|x| format!("{}: {}", x.type_name(), x))
+            .map_err(|x| x.message.clone());
+
+        let emitted = emitted.lock().unwrap().clone();
+        (result, emitted)
+    }
+
+    /// The variables importing a library gives, as text
+    async fn import(block: Block, edition: Edition) -> Result<Vec<String>, String> {
+        let mut engine = Engine::new();
+        engine.register_fn("emit", |_| Ok(values::Null::make()));
+        engine.register_program(
+            "library",
+            Program {
+                block: Arc::new(block),
+                edition,
+            },
+        );
+
+        let program = engine
+            .compile("import \"library\"")
+            .map_err(|x| x.message.clone())?;
+        let imported = engine
+            .eval(&program, HashMap::new())
+            .await
+            .map_err(|x| x.message.clone())?;
+        let RuntimeValue::Object(object) = imported else {
+            return Err("a library is not an object".to_string());
+        };
+
+        let mut variables: Vec<String> = object
+            .items
+            .iter()
+            .map(|(name, value)| format!("{} = {}: {}", name, value.type_name(), value))
+            .collect();
+        variables.sort();
+        Ok(variables)
+    }
+
+    #[tokio::test]
+    async fn optimizing_never_changes_what_programs_do() {
+        let globals = globals();
+        let globals: Vec<&str> = globals.iter().map(|x| x.as_str()).collect();
+        let mut optimized_any = false;
+
+        for seed in 1..1500 {
+            let code = generate(&mut Random(seed), 0);
+
+            for edition in [Edition::V1, Edition::V2, Edition::V3] {
+                let Some(block) = parse(&code, edition) else {
+                    continue;
+                };
+                let optimized = optimize(block.clone(), &globals, edition, false);
+                optimized_any |= format!("{:?}", optimized) != format!("{:?}", block);
+
+                assert_eq!(
+                    run(optimized, edition).await,
+                    run(block, edition).await,
+                    "seed {} changed in {:?}:\n{}",
+                    seed,
+                    edition,
+                    code
+                );
+            }
+        }
+
+        assert!(
+            optimized_any,
+            "nothing was optimized, so nothing was tested"
+        );
+    }
+
+    #[tokio::test]
+    async fn optimizing_never_changes_what_libraries_export() {
+        let globals = globals();
+        let globals: Vec<&str> = globals.iter().map(|x| x.as_str()).collect();
+
+        for seed in 1..1500 {
+            let code = generate(&mut Random(seed), 0);
+
+            for edition in [Edition::V1, Edition::V2, Edition::V3] {
+                let Some(block) = parse(&code, edition) else {
+                    continue;
+                };
+                let optimized = optimize(block.clone(), &globals, edition, true);
+
+                assert_eq!(
+                    import(optimized, edition).await,
+                    import(block, edition).await,
+                    "seed {} changed in {:?}:\n{}",
+                    seed,
+                    edition,
+                    code
+                );
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn libraries_keep_variables_used_once() {
+        let mut engine = Engine::new();
+        engine
+            .register_module("greetings", "var hello = \"Hello\"\nvar shout = hello")
+            .unwrap();
+
+        let program = engine
+            .compile("var greetings = import \"greetings\"\ngreetings.hello")
+            .unwrap();
+        let result = engine.eval(&program, HashMap::new()).await.unwrap();
+        assert_eq!(result.to_string(), "Hello");
+    }
+
+    #[test]
+    fn scripts_inline_variables_used_once() {
+        let block = parse("var a = \"x\"\na == \"x\"", Edition::LATEST).unwrap();
+        let optimized = optimize(block, &[], Edition::LATEST, false);
+
+        assert!(matches!(
+            optimized.nodes.as_slice(),
+            [Expression::Identifier(ident)] if ident.name == "true"
+        ));
+    }
+
+    #[tokio::test]
+    async fn parameters_are_not_inlined_into() {
+        let code = "var a = 1\nfn f(a) do\n    emit(a)\nend\nf(2)";
+        let block = parse(code, Edition::LATEST).unwrap();
+        let optimized = optimize(block, &["emit"], Edition::LATEST, false);
+
+        let (result, emitted) = run(optimized, Edition::LATEST).await;
+        assert_eq!(result, Ok("null: null".to_string()));
+        assert_eq!(emitted, vec!["2"]);
+    }
+
+    #[tokio::test]
+    async fn variables_used_in_functions_are_inlined() {
+        let code = "var a = 1\nfn f() do\n    emit(a)\nend\nf()\nf()";
+        let block = parse(code, Edition::LATEST).unwrap();
+        let optimized = optimize(block, &["emit"], Edition::LATEST, false);
+
+        assert!(!format!("{:?}", optimized).contains("VariableDeclaration"));
+        assert_eq!(run(optimized, Edition::LATEST).await.1, vec!["1", "1"]);
+    }
+}
@@ -1,41 +1,66 @@
-use std::mem::{discriminant, Discriminant};
+use std::{
+    mem::{discriminant, Discriminant},
+    sync::OnceLock,
+};
 
 use crate::errors::{MakerError, MakerErrorType};
 
 use super::{
-    lexer::{Token, TokenType},
+    edition::Edition,
+    lexer::{Location, Token, TokenType},
     nodes::{self, Block, Expression},
 };
 
-pub struct Parser {
-    tokens: Vec<Token>,
+/// How deep expressions may nest before the parser gives up, so that
+/// something like `a(a(a(...)))` cannot overflow the stack
+const MAX_DEPTH: usize = 128;
+
+/// Handed out by `at` once the tokens run out
+static EOF_TOKEN: OnceLock<Token<'static>> = OnceLock::new();
+
+pub struct Parser<'a> {
+    tokens: &'a [Token<'a>],
+    position: usize,
+    depth: usize,
+    edition: Edition,
 }
 
 type E = Result<Expression, MakerError>;
 
-impl Parser {
-    fn at(&mut self) -> Token {
-        self.tokens.get(0).unwrap().clone()
+impl<'a> Parser<'a> {
+    fn at(&self) -> &'a Token<'a> {
+        self.tokens.get(self.position).unwrap_or_else(|| {
+            EOF_TOKEN.get_or_init(|| Token {
+                value: "",
+                token_type: TokenType::EOF,
+                location: Location::no_location(),
+            })
+        })
+    }
+
+    fn eat(&mut self) -> &'a Token<'a> {
+        let token = self.at();
+        self.position += 1;
+        token
     }
 
-    fn eat(&mut self) -> Token {
-        println!(" eated {:?}", self.at());
-        self.tokens.remove(0)
+    fn is_empty(&self) -> bool {
+        self.position >= self.tokens.len()
     }
 
     fn expect<S: Into<String>>(
         &mut self,
         what: Discriminant<TokenType>,
         message: S,
-    ) -> Result<Token, MakerError> {
-        if discriminant(&mut self.at().token_type) == what {
-            return Ok(self.eat());
+    ) -> Result<&'a Token<'a>, MakerError> {
+        if discriminant(&self.at().token_type) == what {
+            Ok(self.eat())
         } else {
-            return Err(MakerError::lang(
+            Err(MakerError::lang(
                 message.into(),
-                self.at().location,
+                self.at().location.clone(),
                 MakerErrorType::ParserError,
-            ));
+            ))
         }
     }
 
@@ -46,34 +71,60 @@ impl Parser {
         )?;
         match token.token_type {
             TokenType::Identifier => Ok(nodes::Identifier {
-                name: token.value,
-                location: token.location,
+                name: token.value.to_string(),
+                location: token.location.clone(),
             }),
             _ => unreachable!(),
         }
     }
 
-    pub fn new(tokens: Vec<Token>) -> Parser {
-        Parser { tokens }
+    pub fn new(tokens: &'a [Token<'a>], edition: Edition) -> Parser<'a> {
+        Parser {
+            tokens,
+            position: 0,
+            depth: 0,
+            edition,
+        }
     }
 
     pub fn parse(&mut self) -> Result<Block, MakerError> {
         let mut block = nodes::Block {
             nodes: vec![],
-            location: self.at().location,
+            location: self.at().location.clone(),
         };
 
         // Repeat until EOF
-        while !self.tokens.is_empty() && !matches!(self.at().token_type, TokenType::EOF) {
+        while !self.is_empty() && !matches!(self.at().token_type, TokenType::EOF) {
             block.nodes.push(self.parse_statement()?);
         }
 
         Ok(block)
     }
 
+    fn enter(&mut self) -> Result<(), MakerError> {
+        if self.depth >= MAX_DEPTH {
+            return Err(MakerError::lang(
+                "Code is nested too deeply",
+                self.at().location.clone(),
+                MakerErrorType::ParserError,
+            ));
+        }
+
+        self.depth += 1;
+        Ok(())
+    }
+
     fn parse_statement(&mut self) -> E {
+        self.enter()?;
+        let result = self.parse_statement_inner();
+        self.depth -= 1;
+        result
+    }
+
+    fn parse_statement_inner(&mut self) -> E {
         match self.at().token_type {
             TokenType::Var => self.parse_variable_declaration(),
+            TokenType::Fn => self.parse_function_declaration(),
 
             TokenType::If => {
                 let token = self.eat();
@@ -88,7 +139,7 @@ impl Parser {
                     } else {
                         return Err(MakerError::lang(
                             "Expected if or do",
-                            self.at().location,
+                            self.at().location.clone(),
                             MakerErrorType::ParserError,
                         ));
                     }
@@ -96,11 +147,17 @@ impl Parser {
                     None
                 };
 
+                // Span the whole if, up to the end of the last branch
+                let last = match &alternate {
+                    Some(alternate) => alternate.get_location(),
+                    None => block.location.clone(),
+                };
+
                 Ok(Expression::IfBlock(nodes::IfBlock {
                     test: Box::from(test),
                     success: block,
                     alternate,
-                    location: token.location,
+                    location: token.location.to(&last),
                 }))
             }
             _ => self.parse_expression(),
@@ -110,35 +167,97 @@ impl Parser {
     fn parse_variable_declaration(&mut self) -> E {
         let token = self.eat();
         let identifier = self.get_identifier()?;
+
+        // Types are optional
+        let type_annotation = self.parse_type_annotation()?;
+
         self.expect(discriminant(&TokenType::Assign), "Expected =")?;
         let value = self.parse_expression()?;
 
         Ok(Expression::VariableDeclaration(
             nodes::VariableDeclaration {
                 name: identifier,
+                type_annotation,
+                location: token.location.to(&value.get_location()),
                 value: Box::from(value),
-                location: token.location,
             },
         ))
     }
 
-    fn parse_expression(&mut self) -> E {
-        match self.at().token_type {
-            _ => self.parse_logical_expression(),
+    /// The `: type` after a name, if there is one
+    fn parse_type_annotation(&mut self) -> Result<Option<nodes::Identifier>, MakerError> {
+        if matches!(self.at().token_type, TokenType::Colon) {
+            self.eat();
+            Ok(Some(self.get_identifier()?))
+        } else {
+            Ok(None)
         }
     }
 
+    fn parse_function_declaration(&mut self) -> E {
+        let token = self.eat();
+        let name = self.get_identifier()?;
+        self.expect(
+            discriminant(&TokenType::OpenBrace),
+            "Expected ( after the name of the function",
+        )?;
+
+        let mut params: Vec<nodes::Parameter> = vec![];
+        while !self.is_empty() && !matches!(self.at().token_type, TokenType::CloseBrace) {
+            params.push(nodes::Parameter {
+                name: self.get_identifier()?,
+                type_annotation: self.parse_type_annotation()?,
+            });
+            if matches!(self.at().token_type, TokenType::Comma) {
+                self.eat();
+            } else {
+                break;
+            }
+        }
+
+        self.expect(
+            discriminant(&TokenType::CloseBrace),
+            "Expected closing of parameters",
+        )?;
+
+        let return_annotation = if matches!(self.at().token_type, TokenType::Arrow) {
+            self.eat();
+            Some(self.get_identifier()?)
+        } else {
+            None
+        };
+
+        let body = self.parse_block()?;
+
+        Ok(Expression::FunctionDeclaration(
+            nodes::FunctionDeclaration {
+                location: token.location.to(&body.location),
+                name,
+                params,
+                return_annotation,
+                body,
+            },
+        ))
+    }
+
+    fn parse_expression(&mut self) -> E {
+        self.enter()?;
+        let result = self.parse_logical_expression();
+        self.depth -= 1;
+        result
+    }
+
     fn parse_logical_expression(&mut self) -> E {
         let left = self.parse_call_expression()?;
 
         if let TokenType::Logical(logical) = self.at().token_type {
-            let operator = self.eat();
+            self.eat();
             let right = self.parse_call_expression()?;
 
             return Ok(Expression::Logical(nodes::Logical {
+                location: left.get_location().to(&right.get_location()),
                 left: Box::from(left),
                 right: Box::from(right),
-                location: operator.location,
                 operator: logical,
             }));
         }
@@ -150,12 +269,11 @@ impl Parser {
         let left = self.parse_member_expression()?;
 
         if matches!(self.at().token_type, TokenType::OpenBrace) {
-            let start = self.eat();
+            self.eat();
             let mut args: Vec<Expression> = vec![];
 
             // Get arguments
-            while !self.tokens.is_empty() && !matches!(self.at().token_type, TokenType::CloseBrace)
-            {
+            while !self.is_empty() && !matches!(self.at().token_type, TokenType::CloseBrace) {
                 args.push(self.parse_expression()?);
                 if matches!(self.at().token_type, TokenType::Comma) {
                     self.eat();
@@ -165,15 +283,15 @@ impl Parser {
             }
 
             // Check for ending (
-            self.expect(
+            let end = self.expect(
                 discriminant(&TokenType::CloseBrace),
                 "Expected closing of arguments",
             )?;
 
             // Done
             return Ok(Expression::Call(nodes::Call {
+                location: left.get_location().to(&end.location),
                 callee: Box::from(left),
-                location: start.location,
                 args,
             }));
         }
@@ -185,13 +303,13 @@ impl Parser {
         let left = self.parse_literal()?;
 
         if matches!(self.at().token_type, TokenType::Dot) {
-            let start = self.eat();
+            self.eat();
             let key = self.parse_literal()?;
 
             return Ok(Expression::Member(nodes::Member {
+                location: left.get_location().to(&key.get_location()),
                 left: Box::from(left),
                 right: Box::from(key),
-                location: start.location,
             }));
         }
 
@@ -201,20 +319,35 @@ impl Parser {
     fn parse_block(&mut self) -> Result<Block, MakerError> {
         let mut block = nodes::Block {
             nodes: vec![],
-            location: self.at().location,
+            location: self.at().location.clone(),
         };
 
         // Expect a {
         self.expect(discriminant(&TokenType::Do), "Expected do")?;
 
         // Repeat until {
-        while !self.tokens.is_empty() && !matches!(self.at().token_type, TokenType::End) {
-            block.nodes.push(self.parse_expression()?);
-            println!("{:?}", self.at());
+        while !self.is_empty() && !matches!(self.at().token_type, TokenType::End | TokenType::EOF) {
+            // Statements were only allowed at the top level before edition 2
+            let node = if self.edition >= Edition::V2 {
+                self.parse_statement()?
+            } else {
+                self.parse_expression()?
+            };
+            block.nodes.push(node);
         }
 
         // Expect a )
-        self.expect(discriminant(&TokenType::End), "Expected end")?;
+        if !matches!(self.at().token_type, TokenType::End) {
+            return Err(MakerError::lang(
+                "Expected end",
+                self.at().location.clone(),
+                MakerErrorType::ParserError,
+            )
+            .with_label(block.location.clone(), "the block starts here")
+            .with_note("every do needs a matching end"));
+        }
+        let end = self.eat();
+        block.location = block.location.to(&end.location);
 
         Ok(block)
     }
@@ -225,33 +358,173 @@ impl Parser {
                 let value = self.eat();
 
                 Expression::Number(nodes::Number {
-                    value: value.value.parse::<f64>().unwrap(),
-                    location: value.location,
+                    value: value.value.parse::<f64>().map_err(|_| {
+                        MakerError::lang(
+                            format!("Invalid number: {}", value.value),
+                            value.location.clone(),
+                            MakerErrorType::ParserError,
+                        )
+                    })?,
+                    location: value.location.clone(),
                 })
             }
             TokenType::Identifier => {
                 let value = self.eat();
 
                 Expression::Identifier(nodes::Identifier {
-                    name: value.value,
-                    location: value.location,
+                    name: value.value.to_string(),
+                    location: value.location.clone(),
                 })
             }
             TokenType::String => {
                 let value = self.eat();
 
                 Expression::StringNode(nodes::StringNode {
-                    value: value.value,
-                    location: value.location,
+                    value: value.value.to_string(),
+                    location: value.location.clone(),
+                })
+            }
+            TokenType::Import => {
+                let token = self.eat();
+                let name = self.expect(
+                    discriminant(&TokenType::String),
+                    "Expected the name of a library",
+                )?;
+
+                Expression::Import(nodes::Import {
+                    name: nodes::StringNode {
+                        value: name.value.to_string(),
+                        location: name.location.clone(),
+                    },
+                    location: token.location.to(&name.location),
                 })
             }
             _ => {
                 return Err(MakerError::lang(
-                    format!("Cannot handle this: {:?}", self.at()),
-                    self.at().location,
+                    format!("Unexpected token: {:?}", self.at().token_type),
+                    self.at().location.clone(),
                     MakerErrorType::ParserError,
                 ))
             }
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lang::lexer::lex;
+
+    const EDITIONS: [Edition; 3] = [Edition::V1, Edition::V2, Edition::V3];
+
+    /// Pieces of code which random programs are stitched together from
+    const FRAGMENTS: [&str; 28] = [
+        "var", "x", "y", " = ", "\"a\"", "'b'", "1", "2.5", "(", ")", ",", ".", "if", "do", "end",
+        "else", "import", "==", "!=", ":", "string", "\n", " ", "//", "\"", "é", "fn", "->",
+    ];
+
+    /// A small xorshift generator, so that failures can be reproduced from their seed
+    struct Random(u64);
+
+    impl Random {
+        fn next(&mut self) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0
+        }
+
+        fn below(&mut self, max: usize) -> usize {
+            (self.next() % max as u64) as usize
+        }
+    }
+
+    fn parse(code: &str, edition: Edition) -> Result<Block, MakerError> {
+        let tokens = lex(code, "test".to_string(), edition)?;
+        Parser::new(&tokens, edition).parse()
+    }
+
+    fn random_code(random: &mut Random) -> String {
+        let mut code = String::new();
+        for _ in 0..random.below(64) {
+            if random.below(8) == 0 {
+                code.push(char::from_u32(random.below(0x3000) as u32).unwrap_or('?'));
+            } else {
+                code.push_str(FRAGMENTS[random.below(FRAGMENTS.len())]);
+            }
+        }
+        code
+    }
+
+    #[test]
+    fn parsing_never_panics() {
+        for seed in 1..5000 {
+            let mut random = Random(seed);
+            let code = random_code(&mut random);
+
+            for edition in EDITIONS {
+                // Only a panic fails this, any error is fine
+                let _ = parse(&code, edition);
+            }
+        }
+    }
+
+    #[test]
+    fn nesting_is_capped() {
+        let mut random = Random(42);
+
+        for _ in 0..200 {
+            let depth = random.below(MAX_DEPTH * 8);
+            let code = format!("{}x{}", "f(".repeat(depth), ")".repeat(depth));
+
+            for edition in EDITIONS {
+                match parse(&code, edition) {
+                    Ok(_) => assert!(depth < MAX_DEPTH, "{} calls deep should not parse", depth),
+                    Err(err) => {
+                        assert!(depth + 2 >= MAX_DEPTH, "{} calls deep should parse", depth);
+                        assert_eq!(err.message, "Code is nested too deeply");
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn nested_blocks_are_capped() {
+        let code = format!(
+            "{}x{}",
+            "if x do ".repeat(MAX_DEPTH * 4),
+            " end".repeat(MAX_DEPTH * 4)
+        );
+        let err = parse(&code, Edition::V2).unwrap_err();
+
+        assert_eq!(err.message, "Code is nested too deeply");
+    }
+
+    #[test]
+    fn functions_are_parsed_with_their_annotations() {
+        let block = parse("fn f(a: string, b) -> number do\n    1\nend", Edition::V3).unwrap();
+        let Expression::FunctionDeclaration(function) = &block.nodes[0] else {
+            panic!("expected a function");
+        };
+
+        let params: Vec<(&str, Option<&str>)> = function
+            .params
+            .iter()
+            .map(|x| {
+                let annotation = x.type_annotation.as_ref().map(|x| x.name.as_str());
+                (x.name.name.as_str(), annotation)
+            })
+            .collect();
+        assert_eq!(params, vec![("a", Some("string")), ("b", None)]);
+        assert_eq!(function.return_annotation.as_ref().unwrap().name, "number");
+        assert_eq!(function.body.nodes.len(), 1);
+        assert_eq!(function.location.end, function.body.location.end);
+    }
+
+    #[test]
+    fn fn_is_only_a_keyword_from_edition_3() {
+        assert!(parse("var fn = 1\nfn", Edition::V2).is_ok());
+        assert!(parse("var fn = 1", Edition::V3).is_err());
+    }
+}
@@ -0,0 +1,371 @@
+use std::{
+    collections::HashMap,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use super::{lexer::Location, nodes::Expression, runtime::function_name};
+
+/// The time spent in a node over every time it was evaluated, in microseconds
+#[derive(serde::Serialize, Debug, Clone)]
+pub struct NodeProfile {
+    pub location: Location,
+    pub label: String,
+    pub calls: u64,
+    /// Including the nodes inside of it
+    pub total: u64,
+    /// Excluding the nodes inside of it
+    pub own: u64,
+}
+
+/// The time spent awaiting the futures a native function returned, in microseconds
+#[derive(serde::Serialize, Debug, Clone)]
+pub struct NativeProfile {
+    pub name: String,
+    pub calls: u64,
+    pub awaited: u64,
+}
+
+/// The results of profiling one or more executions of a code piece
+#[derive(serde::Serialize, Debug, Clone)]
+pub struct Profile {
+    pub name: String,
+    pub executions: u64,
+    pub total: u64,
+    /// Sorted by the time spent in the node itself, slowest first
+    pub nodes: Vec<NodeProfile>,
+    pub natives: Vec<NativeProfile>,
+    /// The time spent in each stack, keyed by its frames joined with `;`
+    #[serde(skip)]
+    stacks: HashMap<String, u64>,
+}
+
+impl Profile {
+    /// Adds the results of another execution of the same code piece
+    pub fn merge(&mut self, other: Profile) {
+        self.executions += other.executions;
+        self.total += other.total;
+
+        let mut nodes: HashMap<NodeKey, NodeProfile> = self
+            .nodes
+            .drain(..)
+            .map(|x| (node_key(&x.location), x))
+            .collect();
+        for node in other.nodes {
+            match nodes.get_mut(&node_key(&node.location)) {
+                Some(existing) => {
+                    existing.calls += node.calls;
+                    existing.total += node.total;
+                    existing.own += node.own;
+                }
+                None => {
+                    nodes.insert(node_key(&node.location), node);
+                }
+            }
+        }
+        self.nodes = nodes.into_values().collect();
+
+        for native in other.natives {
+            match self.natives.iter_mut().find(|x| x.name == native.name) {
+                Some(existing) => {
+                    existing.calls += native.calls;
+                    existing.awaited += native.awaited;
+                }
+                None => self.natives.push(native),
+            }
+        }
+
+        for (stack, time) in other.stacks {
+            *self.stacks.entry(stack).or_default() += time;
+        }
+
+        self.sort();
+    }
+
+    fn sort(&mut self) {
+        self.nodes.sort_by_key(|x| std::cmp::Reverse(x.own));
+        self.natives.sort_by_key(|x| std::cmp::Reverse(x.awaited));
+    }
+
+    /// The folded stack format which flamegraph tools like `inferno` and
+    /// `flamegraph.pl` read, one `frame;frame;frame microseconds` per line
+    pub fn to_folded(&self) -> String {
+        let mut stacks: Vec<(&String, &u64)> = self.stacks.iter().collect();
+        stacks.sort();
+
+        stacks
+            .into_iter()
+            .filter(|(_, time)| **time > 0)
+            .map(|(stack, time)| format!("{} {}\n", stack, time))
+            .collect()
+    }
+}
+
+/// Nodes are told apart by the code piece they are in and where in it they are
+type NodeKey = (Arc<str>, usize, usize);
+
+fn node_key(location: &Location) -> NodeKey {
+    (location.context.clone(), location.start, location.end)
+}
+
+struct Frame {
+    label: String,
+    location: Option<Location>,
+    started: Instant,
+    /// The time spent in the frames above this one
+    children: Duration,
+}
+
+/// Records how long each node takes while the interpreter runs
+pub struct Profiler {
+    /// The first frame is the whole execution
+    stack: Vec<Frame>,
+    nodes: HashMap<NodeKey, NodeProfile>,
+    natives: HashMap<String, NativeProfile>,
+    stacks: HashMap<String, Duration>,
+}
+
+impl Profiler {
+    /// Starts profiling, `name` is the bottom frame of every stack
+    pub fn new(name: impl Into<String>) -> Profiler {
+        Profiler {
+            stack: vec![Frame {
+                label: sanitize(name.into()),
+                location: None,
+                started: Instant::now(),
+                children: Duration::ZERO,
+            }],
+            nodes: HashMap::new(),
+            natives: HashMap::new(),
+            stacks: HashMap::new(),
+        }
+    }
+
+    fn path(&self) -> String {
+        self.stack
+            .iter()
+            .map(|x| x.label.as_str())
+            .collect::<Vec<&str>>()
+            .join(";")
+    }
+
+    /// Called right before a node is evaluated
+    pub fn enter(&mut self, expression: &Expression) {
+        let location = expression.get_location();
+
+        self.stack.push(Frame {
+            label: sanitize(format!(
+                "{} ({}:{}:{})",
+                describe(expression),
+                location.context,
+                location.line,
+                location.column
+            )),
+            location: Some(location),
+            started: Instant::now(),
+            children: Duration::ZERO,
+        });
+    }
+
+    /// Called right after the node which was last entered has been evaluated
+    pub fn exit(&mut self) {
+        // The bottom frame is only popped once finished
+        if self.stack.len() <= 1 {
+            return;
+        }
+
+        let path = self.path();
+        let frame = self.stack.pop().unwrap();
+        let elapsed = frame.started.elapsed();
+        let own = elapsed.saturating_sub(frame.children);
+
+        *self.stacks.entry(path).or_default() += own;
+
+        if let Some(parent) = self.stack.last_mut() {
+            parent.children += elapsed;
+        }
+
+        let location = frame.location.unwrap();
+        let node = self
+            .nodes
+            .entry(node_key(&location))
+            .or_insert_with(|| NodeProfile {
+                location,
+                label: frame.label,
+                calls: 0,
+                total: 0,
+                own: 0,
+            });
+        node.calls += 1;
+        node.total += elapsed.as_micros() as u64;
+        node.own += own.as_micros() as u64;
+    }
+
+    /// Records the time spent awaiting a future returned by the native function `name`,
+    /// it shows up as a frame above the node which called it
+    pub fn native(&mut self, name: &str, awaited: Duration) {
+        let path = format!("{};{}", self.path(), sanitize(format!("native {}", name)));
+        *self.stacks.entry(path).or_default() += awaited;
+
+        if let Some(frame) = self.stack.last_mut() {
+            frame.children += awaited;
+        }
+
+        let native = self
+            .natives
+            .entry(name.to_string())
+            .or_insert_with(|| NativeProfile {
+                name: name.to_string(),
+                calls: 0,
+                awaited: 0,
+            });
+        native.calls += 1;
+        native.awaited += awaited.as_micros() as u64;
+    }
+
+    pub fn finish(mut self) -> Profile {
+        // The execution might have been stopped halfway through a node
+        while self.stack.len() > 1 {
+            self.exit();
+        }
+
+        let path = self.path();
+        let root = self.stack.pop().unwrap();
+        let total = root.started.elapsed();
+        *self.stacks.entry(path).or_default() += total.saturating_sub(root.children);
+
+        let mut profile = Profile {
+            name: root.label,
+            executions: 1,
+            total: total.as_micros() as u64,
+            nodes: self.nodes.into_values().collect(),
+            natives: self.natives.into_values().collect(),
+            stacks: self
+                .stacks
+                .into_iter()
+                .map(|(stack, time)| (stack, time.as_micros() as u64))
+                .collect(),
+        };
+        profile.sort();
+        profile
+    }
+}
+
+/// `;` separates frames in the folded format, so it can not be in a label
+fn sanitize(label: String) -> String {
+    label.replace(';', ",")
+}
+
+/// A short readable name for a node, e.g. `call message.reply`
+fn describe(expression: &Expression) -> String {
+    match expression {
+        Expression::Block(_) => "block".to_string(),
+        Expression::VariableDeclaration(dec) => format!("var {}", dec.name.name),
+        Expression::FunctionDeclaration(dec) => format!("fn {}", dec.name.name),
+        Expression::Call(call) => format!("call {}", function_name(&call.callee)),
+        Expression::Member(_) => format!("member {}", function_name(expression)),
+        Expression::Logical(_) => "comparison".to_string(),
+        Expression::IfBlock(_) => "if".to_string(),
+        Expression::Identifier(ident) => ident.name.clone(),
+        Expression::Number(_) => "number".to_string(),
+        Expression::StringNode(_) => "string".to_string(),
+        Expression::Import(import) => format!("import \"{}\"", import.name.value),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lang::{edition::Edition, engine::Engine, lexer::lex, parser::Parser, values::Null};
+
+    const SOURCE: &str = "fn f() do\n    wait()\nend\nf()\nf()";
+
+    /// Profiles one execution of `SOURCE`, where `wait` takes a couple of milliseconds
+    async fn profile() -> Profile {
+        let mut engine = Engine::new();
+        engine.register_fn("wait", |_| {
+            std::thread::sleep(Duration::from_millis(2));
+            Ok(Null::make())
+        });
+        let program = engine.compile(SOURCE).unwrap();
+        let mut interpreter = engine.interpreter(HashMap::new()).unwrap();
+
+        interpreter.set_profiler(Profiler::new("command;1"));
+        interpreter.evaluate_block(&program.block).await.unwrap();
+        interpreter.take_profile().unwrap()
+    }
+
+    /// The microseconds of the folded stack `stack`
+    fn folded(profile: &Profile, stack: &str) -> u64 {
+        profile
+            .to_folded()
+            .lines()
+            .find_map(|x| x.strip_prefix(stack)?.strip_prefix(' '))
+            .unwrap_or_else(|| panic!("{} is not in the profile", stack))
+            .parse()
+            .unwrap()
+    }
+
+    fn node<'a>(profile: &'a Profile, label: &str) -> &'a NodeProfile {
+        profile.nodes.iter().find(|x| x.label == label).unwrap()
+    }
+
+    #[tokio::test]
+    async fn natives_show_up_above_the_nodes_calling_them() {
+        let profile = profile().await;
+
+        assert_eq!(profile.name, "command,1");
+        assert_eq!(profile.natives.len(), 1);
+        assert_eq!(profile.natives[0].calls, 2);
+        assert!(profile.natives[0].awaited >= 4000);
+
+        let call = node(&profile, "call wait (script:2:5)");
+        assert_eq!(call.calls, 2);
+        // The time spent in `wait` is not its own
+        assert!(call.total >= 4000 && call.own * 2 < call.total);
+        assert_eq!(node(&profile, "call f (script:4:1)").calls, 1);
+
+        for line in ["4:1", "5:1"] {
+            let stack = format!(
+                "command,1;call f (script:{});call wait (script:2:5);native wait",
+                line
+            );
+            assert!(folded(&profile, &stack) >= 2000);
+        }
+    }
+
+    #[tokio::test]
+    async fn executions_are_merged() {
+        let mut merged = profile().await;
+        merged.merge(profile().await);
+
+        assert_eq!(merged.executions, 2);
+        assert_eq!(node(&merged, "call wait (script:2:5)").calls, 4);
+        assert_eq!(node(&merged, "call f (script:4:1)").calls, 2);
+        assert_eq!(merged.natives[0].calls, 4);
+        assert!(
+            folded(
+                &merged,
+                "command,1;call f (script:4:1);call wait (script:2:5);native wait"
+            ) >= 4000
+        );
+    }
+
+    #[test]
+    fn stopped_executions_finish_the_nodes_they_were_in() {
+        let tokens = lex("f(1)", "script".to_string(), Edition::LATEST).unwrap();
+        let block = Parser::new(&tokens, Edition::LATEST).parse().unwrap();
+        let Expression::Call(call) = &block.nodes[0] else {
+            panic!("f(1) is a call");
+        };
+
+        let mut profiler = Profiler::new("command");
+        profiler.enter(&block.nodes[0]);
+        profiler.enter(&call.args[0]);
+        let profile = profiler.finish();
+
+        let mut labels: Vec<&str> = profile.nodes.iter().map(|x| x.label.as_str()).collect();
+        labels.sort();
+        assert_eq!(labels, vec!["call f (script:1:1)", "number (script:1:3)"]);
+    }
+}
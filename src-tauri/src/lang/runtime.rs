@@ -1,152 +1,584 @@
-use crate::errors::{MakerError, MakerErrorType};
+use std::{
+    collections::{HashMap, HashSet},
+    future::Future,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+    time::Instant,
+};
+
+use crate::errors::{MakerError, MakerErrorType, StackFrame};
 
 use super::{
-    nodes::Expression,
+    debugger::Debugger,
+    edition::Edition,
+    lexer::Location,
+    nodes::{Block, Call, Expression, Identifier, Import},
+    profiler::{Profile, Profiler},
     scope::Scope,
+    time,
+    types::Type,
     values::{self, Boolean, Null, RuntimeValue},
 };
 
 type E = Result<RuntimeValue, MakerError>;
 
+/// How many calls can be on the stack before calling a function fails, so a function
+/// which calls itself forever stops with an error
+const MAX_CALLS: usize = 256;
+
+/// How deeply nodes can be evaluated inside each other, a function body nests a few levels
+/// per call, so this is well above what `MAX_CALLS` allows and only bounds the memory used
+const MAX_DEPTH: usize = 2048;
+
+/// When less stack than this is left, nested nodes are evaluated on a new piece of stack
+const RED_ZONE: usize = 256 * 1024;
+const STACK_SIZE: usize = 2 * 1024 * 1024;
+
+/// A parsed code piece, with the edition it has to be run in
+#[derive(Debug, Clone)]
+pub struct Program {
+    pub block: Arc<Block>,
+    pub edition: Edition,
+}
+
+/// The compiled libraries which can be imported, by name
+pub type Modules = HashMap<String, Program>;
+
 pub struct Interpreter {
     scope: Scope,
+    call_stack: Vec<StackFrame>,
+    debugger: Option<Debugger>,
+    profiler: Option<Profiler>,
+    depth: usize,
+    edition: Edition,
+    modules: Arc<Modules>,
+    /// Declared in the scope of every library, as they do not share the script's scope
+    globals: Arc<HashMap<String, RuntimeValue>>,
+    /// Every module is only evaluated once per execution
+    imported: HashMap<String, RuntimeValue>,
+    /// The modules which are being evaluated right now, to find cycles
+    importing: Vec<String>,
 }
 
 macro_rules! evaluate {
     ($self:ident, $what:expr) => {
-        Box::pin(async { $self.evaluate($what).await }).await
+        GrowStack(Box::pin(async { $self.evaluate($what).await })).await
     };
 }
 
+/// Polls a nested evaluation, moving onto a new piece of stack when the current one runs
+/// low, so how deeply code can nest does not depend on the thread it is run on
+struct GrowStack<F>(Pin<Box<F>>);
+
+impl<F: Future> Future for GrowStack<F> {
+    type Output = F::Output;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<F::Output> {
+        stacker::maybe_grow(RED_ZONE, STACK_SIZE, || self.0.as_mut().poll(cx))
+    }
+}
+
 impl Interpreter {
     pub fn new(scope: Scope) -> Self {
-        Self { scope }
+        Self {
+            scope,
+            call_stack: vec![],
+            debugger: None,
+            profiler: None,
+            depth: 0,
+            edition: Edition::LATEST,
+            modules: Arc::new(HashMap::new()),
+            globals: Arc::new(HashMap::new()),
+            imported: HashMap::new(),
+            importing: vec![],
+        }
+    }
+
+    /// Makes the interpreter behave like the given edition of the language
+    pub fn set_edition(&mut self, edition: Edition) {
+        self.edition = edition;
+    }
+
+    /// Sets the libraries which `import` can find
+    pub fn set_modules(&mut self, modules: Arc<Modules>) {
+        self.modules = modules;
+    }
+
+    /// Sets the values which libraries can use, like the natives of an `Engine`
+    pub fn set_globals(&mut self, globals: Arc<HashMap<String, RuntimeValue>>) {
+        self.globals = globals;
+    }
+
+    /// Makes the interpreter pause for the debugger before evaluating nodes
+    pub fn set_debugger(&mut self, debugger: Debugger) {
+        self.debugger = Some(debugger);
+    }
+
+    /// Makes the interpreter record how long each node takes
+    pub fn set_profiler(&mut self, profiler: Profiler) {
+        self.profiler = Some(profiler);
+    }
+
+    /// Stops profiling, returning what was recorded
+    pub fn take_profile(&mut self) -> Option<Profile> {
+        self.profiler.take().map(|x| x.finish())
+    }
+
+    /// Runs a call with a frame pushed, so that errors know where they came from
+    async fn with_frame<F>(&mut self, frame: StackFrame, call: F) -> E
+    where
+        F: std::future::Future<Output = E>,
+    {
+        self.call_stack.push(frame);
+        let result = call.await;
+        self.pop_frame(result)
+    }
+
+    fn pop_frame(&mut self, result: E) -> E {
+        // Only the innermost call takes the snapshot, as it sees the whole stack
+        let result = result.map_err(|mut err| {
+            if err.stack.is_empty() {
+                err.stack = self.call_stack.iter().rev().cloned().collect();
+            }
+            err
+        });
+
+        self.call_stack.pop();
+        result
     }
 
-    pub async fn evaluate(&mut self, expression: Expression) -> E {
+    async fn import(&mut self, import: &Import) -> E {
+        let name = &import.name.value;
+
+        if let Some(value) = self.imported.get(name) {
+            return Ok(value.clone());
+        }
+
+        if let Some(start) = self.importing.iter().position(|x| x == name) {
+            let mut cycle = self.importing[start..].to_vec();
+            cycle.push(name.clone());
+
+            return Err(MakerError::lang(
+                format!("Import cycle: {}", cycle.join(" -> ")),
+                import.location.clone(),
+                MakerErrorType::RuntimeError,
+            )
+            .with_note(
+                "libraries can not import each other, move the shared code into another library",
+            ));
+        }
+
+        let module = match self.modules.get(name) {
+            Some(module) => module.clone(),
+            None => {
+                return Err(MakerError::lang(
+                    format!("There is no library called {}", name),
+                    import.name.location.clone(),
+                    MakerErrorType::RuntimeError,
+                ))
+            }
+        };
+
+        // Libraries get their own scope, only their top level variables are shared
+        let mut module_scope = Scope::new();
+        for (name, value) in self.globals.iter() {
+            // The globals come from the embedder, so clashing with a builtin is their mistake
+            let _ = module_scope.declare(name, value.clone());
+        }
+
+        let scope = std::mem::replace(&mut self.scope, module_scope);
+        let builtins: HashSet<String> = self.scope.variables.keys().cloned().collect();
+
+        self.importing.push(name.clone());
+        self.call_stack.push(StackFrame {
+            function: format!("import \"{}\"", name),
+            location: import.location.clone(),
+            native: false,
+        });
+
+        // The library might be written in another edition
+        let edition = std::mem::replace(&mut self.edition, module.edition);
+        let result = Box::pin(self.evaluate_block(&module.block)).await;
+        let result = self.pop_frame(result);
+
+        self.edition = edition;
+        self.importing.pop();
+        let module_scope = std::mem::replace(&mut self.scope, scope);
+        result?;
+
+        // Builtins the library declared over are its own variables
+        let shadowed: HashSet<String> = builtins
+            .iter()
+            .filter(|x| module_scope.is_shadowed(x))
+            .cloned()
+            .collect();
+
+        let value = values::Object::make(
+            module_scope
+                .variables
+                .into_iter()
+                .filter(|(name, _)| !builtins.contains(name) || shadowed.contains(name))
+                .collect(),
+        );
+        self.imported.insert(name.clone(), value.clone());
+
+        Ok(value)
+    }
+
+    async fn call_function(
+        &mut self,
+        function: values::Function,
+        args: Vec<RuntimeValue>,
+        call: &Call,
+    ) -> E {
+        let declaration = function.declaration.clone();
+        let name = function_name(&call.callee);
+
+        if self.call_stack.len() >= MAX_CALLS {
+            return Err(MakerError::lang(
+                format!("Too many nested calls when calling {}", name),
+                call.location.clone(),
+                MakerErrorType::RuntimeError,
+            )
+            .with_note("a function which calls itself has to stop doing so at some point"));
+        }
+
+        let params = &declaration.params;
+        if args.len() > params.len() {
+            return Err(MakerError::lang(
+                format!(
+                    "{} takes {} argument(s) but {} were given",
+                    name,
+                    params.len(),
+                    args.len()
+                ),
+                call.args[params.len()].get_location(),
+                MakerErrorType::RuntimeError,
+            ));
+        }
+        if let Some(param) = params.get(args.len()) {
+            return Err(MakerError::lang(
+                format!(
+                    "Missing argument {} ({}) of {}",
+                    args.len(),
+                    param.name.name,
+                    name
+                ),
+                call.location.clone(),
+                MakerErrorType::RuntimeError,
+            ));
+        }
+
+        // The body can call the function itself, besides what was declared before it
+        let mut scope = (*function.scope).clone();
+        scope.set(
+            &declaration.name.name,
+            RuntimeValue::Function(function.clone()),
+        );
+
+        for (i, (param, value)) in params.iter().zip(args).enumerate() {
+            if let Some(annotation) = &param.type_annotation {
+                let expected = annotation_type(annotation)?;
+
+                if !expected.matches(&value) {
+                    return Err(MakerError::lang(
+                        format!(
+                            "Invalid type for argument {} ({}), expected {} but found {}",
+                            i,
+                            param.name.name,
+                            expected,
+                            value.type_name()
+                        ),
+                        call.args[i].get_location(),
+                        MakerErrorType::TypeError,
+                    )
+                    .with_label(
+                        annotation.location.clone(),
+                        "expected because of this annotation",
+                    ));
+                }
+            }
+
+            // Parameters hide the variables of the same name
+            scope.set(&param.name.name, value);
+        }
+
+        let scope = std::mem::replace(&mut self.scope, scope);
+        let edition = std::mem::replace(&mut self.edition, function.edition);
+        self.call_stack.push(StackFrame {
+            function: name,
+            location: call.location.clone(),
+            native: false,
+        });
+
+        let result = Box::pin(self.evaluate_block(&declaration.body))
+            .await
+            .and_then(|value| {
+                let Some(annotation) = &declaration.return_annotation else {
+                    return Ok(value);
+                };
+                let expected = annotation_type(annotation)?;
+
+                if expected.matches(&value) {
+                    Ok(value)
+                } else {
+                    let location = match declaration.body.nodes.last() {
+                        Some(last) => last.get_location(),
+                        None => declaration.body.location.clone(),
+                    };
+
+                    Err(MakerError::lang(
+                        format!("Expected {} but found {}", expected, value.type_name()),
+                        location,
+                        MakerErrorType::TypeError,
+                    )
+                    .with_label(
+                        annotation.location.clone(),
+                        "expected because of this annotation",
+                    ))
+                }
+            });
+        let result = self.pop_frame(result);
+
+        self.edition = edition;
+        self.scope = scope;
+        result
+    }
+
+    pub async fn evaluate_block(&mut self, block: &Block) -> E {
+        let mut last: RuntimeValue = Null::make();
+
+        for node in &block.nodes {
+            last = evaluate!(self, node)?;
+        }
+
+        Ok(last)
+    }
+
+    pub async fn evaluate(&mut self, expression: &Expression) -> E {
+        if let Some(debugger) = &mut self.debugger {
+            debugger
+                .before(&expression.get_location(), self.depth, &self.scope)
+                .await?;
+        }
+
+        if self.depth >= MAX_DEPTH {
+            return Err(MakerError::lang(
+                "Code is evaluated too deeply",
+                expression.get_location(),
+                MakerErrorType::RuntimeError,
+            )
+            .with_note("a function which calls itself has to stop doing so at some point"));
+        }
+
+        if let Some(profiler) = &mut self.profiler {
+            profiler.enter(expression);
+        }
+
+        self.depth += 1;
+        let result = self.evaluate_node(expression).await;
+        self.depth -= 1;
+
+        if let Some(profiler) = &mut self.profiler {
+            profiler.exit();
+        }
+
+        result
+    }
+
+    async fn evaluate_node(&mut self, expression: &Expression) -> E {
         match expression {
             // ----- Special -----
-            Expression::Block(block) => {
-                let mut last: RuntimeValue = Null::make();
+            Expression::Block(block) => self.evaluate_block(block).await,
+            // ----- Expressions -----
+            Expression::VariableDeclaration(dec) => {
+                let value = evaluate!(self, &dec.value)?;
 
-                for node in block.nodes {
-                    last = evaluate!(self, node)?;
+                // Not everything can be checked beforehand, e.g. whatever a native function returns
+                if let Some(annotation) = &dec.type_annotation {
+                    let expected = annotation_type(annotation)?;
+
+                    if !expected.matches(&value) {
+                        return Err(MakerError::lang(
+                            format!("Expected {} but found {}", expected, value.type_name()),
+                            dec.value.get_location(),
+                            MakerErrorType::TypeError,
+                        )
+                        .with_label(
+                            annotation.location.clone(),
+                            "expected because of this annotation",
+                        ));
+                    }
                 }
 
-                Ok(last)
+                self.scope
+                    .declare(&dec.name.name, value)
+                    .map_err(|err| err.with_location(dec.name.location.clone()))?;
+
+                Ok(values::Null::make())
             }
-            // ----- Expressions -----
-            Expression::VariableDeclaration(dec) => {
-                let value = evaluate!(self, *dec.value)?;
-                self.scope.declare(&dec.name.name, value)?;
+            Expression::FunctionDeclaration(dec) => {
+                let function =
+                    values::Function::make(dec.clone(), self.scope.clone(), self.edition);
+
+                self.scope
+                    .declare(&dec.name.name, function)
+                    .map_err(|err| err.with_location(dec.name.location.clone()))?;
 
                 Ok(values::Null::make())
             }
             Expression::Member(expr) => {
-                let left = evaluate!(self, *expr.left.clone())?;
+                let left = evaluate!(self, &expr.left)?;
 
                 match left {
                     RuntimeValue::Object(obj) => {
                         if let Expression::Identifier(ref ident) = *expr.right {
                             // Check if the object has it
-                            if !obj.items.contains_key(&ident.name) {
-                                return Err(MakerError::lang(
+                            match obj.items.get(&ident.name) {
+                                Some(value) => Ok(value.clone()),
+                                None => Err(MakerError::lang(
                                     format!("Object does not have key {}", ident.name),
                                     expr.right.get_location(),
                                     MakerErrorType::RuntimeError,
-                                ));
+                                )),
                             }
-
-                            Ok(obj.items.get(&ident.name).unwrap().clone())
                         } else {
-                            return Err(MakerError::lang(
+                            Err(MakerError::lang(
                                 "Can only index an object with an identifier!",
                                 expr.right.get_location(),
                                 MakerErrorType::RuntimeError,
-                            ));
+                            ))
                         }
                     }
-                    _ => {
-                        return Err(MakerError::lang(
-                            format!("Cannot index a {}", left.type_name()),
-                            expr.left.get_location(),
-                            MakerErrorType::RuntimeError,
-                        ));
+                    RuntimeValue::Snowflake(_)
+                    | RuntimeValue::DateTime(_)
+                    | RuntimeValue::Duration(_) => {
+                        let Expression::Identifier(ref ident) = *expr.right else {
+                            return Err(MakerError::lang(
+                                "Can only index a value with an identifier!",
+                                expr.right.get_location(),
+                                MakerErrorType::RuntimeError,
+                            ));
+                        };
+
+                        time::member(&left, &ident.name).ok_or_else(|| {
+                            MakerError::lang(
+                                format!("A {} does not have key {}", left.type_name(), ident.name),
+                                expr.right.get_location(),
+                                MakerErrorType::RuntimeError,
+                            )
+                        })
                     }
+                    _ => Err(MakerError::lang(
+                        format!("Cannot index a {}", left.type_name()),
+                        expr.left.get_location(),
+                        MakerErrorType::RuntimeError,
+                    )),
                 }
             }
             Expression::Call(call) => {
-                let callee = evaluate!(self, *call.callee)?;
+                let callee = evaluate!(self, &call.callee)?;
                 let mut args: Vec<RuntimeValue> = vec![];
 
                 // Collect args
-                for arg in call.args {
+                for arg in &call.args {
                     args.push(evaluate!(self, arg)?);
                 }
 
                 // Check the type of the caller
                 match callee {
                     RuntimeValue::NativeFunction(func) => {
-                        let value = (func.func)(args);
+                        let name = function_name(&call.callee);
 
-                        // Check if it is a future, if so, automatically await it
-                        match value {
-                            Ok(RuntimeValue::Future(future)) => {
-                                let v2 = future.value.clone();
-                                let mut value = v2.lock().await;
+                        if let Some(signature) = func.signature {
+                            let locations: Vec<Location> =
+                                call.args.iter().map(|x| x.get_location()).collect();
+                            args = signature.check_args(&name, args, &locations, &call.location)?;
+                        }
 
-                                let v = value.as_mut().await;
-                                Ok(v?)
-                            }
-                            v => Ok(v?),
+                        let frame = StackFrame {
+                            function: name.clone(),
+                            location: call.location.clone(),
+                            native: true,
+                        };
+                        let mut awaited = None;
+
+                        let result = self
+                            .with_frame(frame, async {
+                                let started = Instant::now();
+                                let result = (func.func)(args, Some(&call.location)).await;
+                                awaited = Some(started.elapsed());
+                                result
+                            })
+                            .await;
+
+                        if let (Some(profiler), Some(awaited)) = (&mut self.profiler, awaited) {
+                            profiler.native(&name, awaited);
                         }
+
+                        result.map_err(|err| err.with_location(call.location.clone()))
                     }
-                    c => {
-                        return Err(MakerError::lang(
-                            format!("Cannot call a {}", c.type_name()),
-                            call.location,
-                            MakerErrorType::RuntimeError,
-                        ))
+                    RuntimeValue::Function(function) => {
+                        self.call_function(function, args, call).await
                     }
+                    c => Err(MakerError::lang(
+                        format!("Cannot call a {}", c.type_name()),
+                        call.location.clone(),
+                        MakerErrorType::RuntimeError,
+                    )),
                 }
             }
             Expression::Logical(expr) => {
-                let left = evaluate!(self, *expr.left)?;
-                let right = evaluate!(self, *expr.right)?;
-
-                // Check if types are the same
-                if left.type_name() != right.type_name() {
-                    return Ok(Boolean::make(false));
-                }
-
-                let result = match (left, right) {
-                    (RuntimeValue::Boolean(l), RuntimeValue::Boolean(r)) => l.value == r.value,
-                    (RuntimeValue::StringValue(l), RuntimeValue::StringValue(r)) => {
-                        l.value == r.value
-                    }
-                    (RuntimeValue::Number(l), RuntimeValue::Number(r)) => l.value == r.value,
-                    (RuntimeValue::Null(_), _) => true,
-                    _ => false,
-                };
+                let left = evaluate!(self, &expr.left)?;
+                let right = evaluate!(self, &expr.right)?;
 
+                let result = self.edition.compare(expr.operator, left.equals(&right));
                 Ok(Boolean::make(result))
             }
             Expression::IfBlock(block) => {
-                let test = evaluate!(self, *block.test)?.is_truthy();
-                println!("{}", test);
+                let test = evaluate!(self, &block.test)?.is_truthy();
 
                 if test {
-                    evaluate!(self, Expression::Block(block.success))
-                } else if let Some(alternate) = block.alternate {
-                    evaluate!(self, *alternate)
+                    self.evaluate_block(&block.success).await
+                } else if let Some(alternate) = &block.alternate {
+                    evaluate!(self, alternate)
                 } else {
                     Ok(values::Null::make())
                 }
             }
             // ----- Literals -----
-            Expression::Identifier(ident) => Ok(self.scope.get(&ident.name)?),
+            Expression::Identifier(ident) => self
+                .scope
+                .get(&ident.name)
+                .map_err(|err| err.with_location(ident.location.clone())),
             Expression::Number(value) => Ok(values::Number::make(value.value)),
-            Expression::StringNode(string) => Ok(values::StringValue::make(string.value)),
+            Expression::StringNode(string) => Ok(values::StringValue::make(string.value.clone())),
+            Expression::Import(import) => self.import(import).await,
         }
     }
 }
+
+/// The type an annotation names, which is only known to exist once it is used
+fn annotation_type(annotation: &Identifier) -> Result<Type, MakerError> {
+    Type::from_name(&annotation.name).ok_or_else(|| {
+        MakerError::lang(
+            format!("Unknown type {}", annotation.name),
+            annotation.location.clone(),
+            MakerErrorType::TypeError,
+        )
+    })
+}
+
+/// Gets a readable name for whatever is being called, e.g. `message.reply`
+pub(super) fn function_name(callee: &Expression) -> String {
+    match callee {
+        Expression::Identifier(ident) => ident.name.clone(),
+        Expression::Member(member) => format!(
+            "{}.{}",
+            function_name(&member.left),
+            function_name(&member.right)
+        ),
+        _ => "<anonymous>".to_string(),
+    }
+}
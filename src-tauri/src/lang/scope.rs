@@ -1,53 +1,107 @@
 use crate::errors::{MakerError, MakerErrorType};
 
 use super::{
-    lexer::Location,
+    assertions,
+    builtins::SHADOWABLE,
+    time,
     values::{self, RuntimeValue},
 };
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
+#[derive(Clone)]
 pub struct Scope {
     pub variables: HashMap<String, RuntimeValue>,
+    /// The builtins of `SHADOWABLE` which have not been declared over yet
+    shadowable: HashSet<String>,
+}
+
+impl Default for Scope {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Scope {
     pub fn new() -> Scope {
+        let mut variables = HashMap::from([
+            ("true".to_string(), values::Boolean::make(true)),
+            ("false".to_string(), values::Boolean::make(false)),
+        ]);
+        variables.extend(assertions::natives());
+        variables.extend(time::natives());
+        let shadowable = SHADOWABLE
+            .iter()
+            .filter(|x| variables.contains_key(**x))
+            .map(|x| x.to_string())
+            .collect();
+
         Scope {
-            variables: HashMap::from([
-                ("true".to_string(), values::Boolean::make(true)),
-                ("false".to_string(), values::Boolean::make(false)),
-            ]),
+            variables,
+            shadowable,
         }
     }
 
     pub fn get(&self, name: &str) -> Result<RuntimeValue, MakerError> {
-        self.exists_current(name)?;
-        Ok(self.variables.get(name).unwrap().clone())
-    }
-
-    fn exists_current(&self, name: &str) -> Result<(), MakerError> {
-        if !self.variables.contains_key(name) {
-            return Err(MakerError::lang(
+        match self.variables.get(name) {
+            Some(value) => Ok(value.clone()),
+            None => Err(MakerError::new(
                 format!("The variable {} does not exist", name),
-                Location::no_location(),
                 MakerErrorType::RuntimeError,
-            ));
+            )
+            .with_note("variables have to be declared with var before they are used")),
         }
-
-        Ok(())
     }
 
     pub fn declare(&mut self, name: &str, value: RuntimeValue) -> Result<RuntimeValue, MakerError> {
-        if self.variables.contains_key(name) {
-            return Err(MakerError::lang(
+        if self.variables.contains_key(name) && !self.shadowable.remove(name) {
+            return Err(MakerError::new(
                 format!("The variable {} already exists", name),
-                Location::no_location(),
                 MakerErrorType::RuntimeError,
-            ));
+            )
+            .with_note("variables can only be declared once, try using a different name"));
         }
 
         // Declare it
         self.variables.insert(name.to_string(), value.clone());
         Ok(value)
     }
+
+    /// Sets a variable whether it exists or not, for what a function is called with
+    pub fn set(&mut self, name: &str, value: RuntimeValue) {
+        self.shadowable.remove(name);
+        self.variables.insert(name.to_string(), value);
+    }
+
+    /// Whether `name` is a builtin of `SHADOWABLE` which was declared over
+    pub fn is_shadowed(&self, name: &str) -> bool {
+        SHADOWABLE.contains(&name) && !self.shadowable.contains(name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shadowable_builtins_can_be_declared_over_once() {
+        let mut scope = Scope::new();
+        assert!(!scope.is_shadowed("assert"));
+
+        scope.declare("assert", values::Number::make(1.0)).unwrap();
+        assert!(scope.is_shadowed("assert"));
+        assert_eq!(scope.get("assert").unwrap().to_string(), "1");
+
+        let err = scope
+            .declare("assert", values::Number::make(2.0))
+            .unwrap_err();
+        assert_eq!(err.message, "The variable assert already exists");
+    }
+
+    #[test]
+    fn other_builtins_can_not_be_declared_over() {
+        let mut scope = Scope::new();
+
+        assert!(scope.declare("true", values::Number::make(1.0)).is_err());
+        assert!(!scope.is_shadowed("true"));
+    }
 }
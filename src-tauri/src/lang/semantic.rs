@@ -0,0 +1,299 @@
+use std::collections::HashMap;
+
+use crate::errors::{MakerError, MakerErrorType};
+
+use super::{
+    builtins::SHADOWABLE,
+    lexer::Location,
+    nodes::{Block, Expression, Identifier},
+};
+
+/// A variable declared with `var` (or a function or one of its parameters),
+/// `location` is where its name is
+#[derive(Debug, Clone)]
+pub struct Definition {
+    pub name: String,
+    pub location: Location,
+    /// The library it holds, if it was declared as `var x = import "library"`
+    pub library: Option<String>,
+}
+
+/// A use of a variable, `definition` is an index into `Analysis::definitions`
+/// and is `None` for globals (and variables which do not exist)
+#[derive(Debug, Clone)]
+pub struct Reference {
+    pub name: String,
+    pub location: Location,
+    pub definition: Option<usize>,
+}
+
+/// An `object.key` access where the object is a plain variable, or an `import`
+/// in which case `object` is the name of the library
+#[derive(Debug, Clone)]
+pub struct MemberAccess {
+    pub object: String,
+    pub name: String,
+    pub location: Location,
+    /// The library the object was imported from, if it was
+    pub library: Option<String>,
+}
+
+/// What is known about a program without running it
+#[derive(Debug, Default)]
+pub struct Analysis {
+    pub definitions: Vec<Definition>,
+    pub references: Vec<Reference>,
+    pub members: Vec<MemberAccess>,
+    pub errors: Vec<MakerError>,
+}
+
+impl Analysis {
+    /// Finds the definition which the name at `offset` points to, whether the
+    /// offset is on a use of it or on the definition itself
+    pub fn definition_at(&self, offset: usize) -> Option<&Definition> {
+        if let Some(definition) = self
+            .definitions
+            .iter()
+            .find(|x| contains(&x.location, offset))
+        {
+            return Some(definition);
+        }
+
+        let reference = self
+            .references
+            .iter()
+            .find(|x| contains(&x.location, offset))?;
+        self.definitions.get(reference.definition?)
+    }
+}
+
+fn contains(location: &Location, offset: usize) -> bool {
+    location.start <= offset && offset <= location.end
+}
+
+/// Walks over a program, resolving variables like the interpreter would,
+/// `globals` are the names which are declared before the script runs
+pub fn analyze(block: &Block, globals: &[&str]) -> Analysis {
+    let mut analyzer = Analyzer {
+        analysis: Analysis::default(),
+        scope: HashMap::new(),
+        globals,
+    };
+
+    analyzer.block(block);
+    analyzer.analysis
+}
+
+struct Analyzer<'a> {
+    analysis: Analysis,
+    scope: HashMap<String, usize>,
+    globals: &'a [&'a str],
+}
+
+impl<'a> Analyzer<'a> {
+    fn declare(&mut self, identifier: &Identifier, library: Option<String>) {
+        let name = &identifier.name;
+        if let Some(previous) = self.scope.get(name) {
+            let previous = &self.analysis.definitions[*previous];
+            self.analysis.errors.push(
+                MakerError::lang(
+                    format!("The variable {} already exists", name),
+                    identifier.location.clone(),
+                    MakerErrorType::RuntimeError,
+                )
+                .with_label(previous.location.clone(), "it was first declared here"),
+            );
+            return;
+        }
+
+        if self.globals.contains(&name.as_str()) && !SHADOWABLE.contains(&name.as_str()) {
+            self.analysis.errors.push(MakerError::lang(
+                format!("The variable {} already exists", name),
+                identifier.location.clone(),
+                MakerErrorType::RuntimeError,
+            ));
+            return;
+        }
+
+        self.scope
+            .insert(name.clone(), self.analysis.definitions.len());
+        self.analysis.definitions.push(Definition {
+            name: name.clone(),
+            location: identifier.location.clone(),
+            library,
+        });
+    }
+
+    fn block(&mut self, block: &Block) {
+        for node in &block.nodes {
+            self.expression(node);
+        }
+    }
+
+    fn expression(&mut self, expression: &Expression) {
+        match expression {
+            Expression::Block(block) => self.block(block),
+            Expression::VariableDeclaration(dec) => {
+                self.expression(&dec.value);
+
+                let library = match &*dec.value {
+                    Expression::Import(import) => Some(import.name.value.clone()),
+                    _ => None,
+                };
+                self.declare(&dec.name, library);
+            }
+            Expression::FunctionDeclaration(dec) => {
+                self.declare(&dec.name, None);
+
+                // The body sees what was declared before it (and the function itself),
+                // but what it declares is gone once it returns
+                let scope = self.scope.clone();
+                for param in &dec.params {
+                    self.scope
+                        .insert(param.name.name.clone(), self.analysis.definitions.len());
+                    self.analysis.definitions.push(Definition {
+                        name: param.name.name.clone(),
+                        location: param.name.location.clone(),
+                        library: None,
+                    });
+                }
+
+                self.block(&dec.body);
+                self.scope = scope;
+            }
+            Expression::IfBlock(block) => {
+                self.expression(&block.test);
+                self.block(&block.success);
+                if let Some(alternate) = &block.alternate {
+                    self.expression(alternate);
+                }
+            }
+            Expression::Call(call) => {
+                self.expression(&call.callee);
+                for arg in &call.args {
+                    self.expression(arg);
+                }
+            }
+            Expression::Member(member) => {
+                self.expression(&member.left);
+
+                // The right side is a key, not a variable
+                let Expression::Identifier(key) = &*member.right else {
+                    return;
+                };
+                let (object, library) = match &*member.left {
+                    Expression::Identifier(object) => (
+                        object.name.clone(),
+                        self.scope
+                            .get(&object.name)
+                            .and_then(|x| self.analysis.definitions[*x].library.clone()),
+                    ),
+                    Expression::Import(import) => {
+                        (import.name.value.clone(), Some(import.name.value.clone()))
+                    }
+                    _ => return,
+                };
+
+                self.analysis.members.push(MemberAccess {
+                    object,
+                    name: key.name.clone(),
+                    location: key.location.clone(),
+                    library,
+                });
+            }
+            Expression::Logical(logical) => {
+                self.expression(&logical.left);
+                self.expression(&logical.right);
+            }
+            Expression::Identifier(ident) => {
+                let definition = self.scope.get(&ident.name).copied();
+
+                if definition.is_none() && !self.globals.contains(&ident.name.as_str()) {
+                    self.analysis.errors.push(
+                        MakerError::lang(
+                            format!("The variable {} does not exist", ident.name),
+                            ident.location.clone(),
+                            MakerErrorType::RuntimeError,
+                        )
+                        .with_note("variables have to be declared with var before they are used"),
+                    );
+                }
+
+                self.analysis.references.push(Reference {
+                    name: ident.name.clone(),
+                    location: ident.location.clone(),
+                    definition,
+                });
+            }
+            Expression::Number(_) | Expression::StringNode(_) | Expression::Import(_) => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lang::{edition::Edition, lexer::lex, parser::Parser};
+
+    fn errors(code: &str, globals: &[&str]) -> Vec<String> {
+        let tokens = lex(code, "test".to_string(), Edition::LATEST).unwrap();
+        let block = Parser::new(&tokens, Edition::LATEST).parse().unwrap();
+        analyze(&block, globals)
+            .errors
+            .into_iter()
+            .map(|x| x.message.clone())
+            .collect()
+    }
+
+    #[test]
+    fn only_shadowable_globals_can_be_declared_over() {
+        let globals = ["message", "assert"];
+
+        assert!(errors("var assert = 1\nassert", &globals).is_empty());
+        assert_eq!(
+            errors("var message = 1", &globals),
+            vec!["The variable message already exists"]
+        );
+        assert_eq!(
+            errors("var assert = 1\nvar assert = 2", &globals),
+            vec!["The variable assert already exists"]
+        );
+    }
+
+    #[test]
+    fn uses_resolve_to_their_declaration() {
+        let tokens = lex(
+            "var a = 1\nif a do\n    a\nend",
+            "test".to_string(),
+            Edition::LATEST,
+        )
+        .unwrap();
+        let block = Parser::new(&tokens, Edition::LATEST).parse().unwrap();
+        let analysis = analyze(&block, &[]);
+
+        assert_eq!(analysis.definitions.len(), 1);
+        assert_eq!(analysis.references.len(), 2);
+        assert!(analysis.references.iter().all(|x| x.definition == Some(0)));
+        assert_eq!(
+            analysis
+                .definition_at(analysis.references[1].location.start)
+                .unwrap()
+                .name,
+            "a"
+        );
+    }
+
+    #[test]
+    fn functions_have_their_own_scope() {
+        assert!(errors("fn f(x) do\n    var y = x\nend\nvar y = 2", &[]).is_empty());
+        assert!(errors("fn f(n) do\n    f(n)\nend", &[]).is_empty());
+        assert_eq!(
+            errors("fn f() do\n    var y = 1\nend\ny", &[]),
+            vec!["The variable y does not exist"]
+        );
+        assert_eq!(
+            errors("fn f(x) do\n    var x = 1\nend", &[]),
+            vec!["The variable x already exists"]
+        );
+    }
+}
@@ -0,0 +1,515 @@
+use std::collections::HashMap;
+
+use crate::errors::{MakerError, MakerErrorType};
+
+use super::{
+    builtins::BUILTINS,
+    edition::Edition,
+    lexer::{lex, Location, TokenType},
+    parser::Parser,
+    semantic::{self, Analysis},
+};
+
+/// A variable which can be looked up across a bot's code pieces
+#[derive(Debug, Clone, PartialEq)]
+pub enum Symbol {
+    /// A variable of a command, which only its own code piece can see
+    Local { code_piece: u8, name: String },
+    /// A variable of a library, which every code piece importing it can see as `library.name`
+    Library { library: String, name: String },
+}
+
+impl Symbol {
+    pub fn name(&self) -> &str {
+        match self {
+            Symbol::Local { name, .. } | Symbol::Library { name, .. } => name,
+        }
+    }
+}
+
+/// Where a symbol is used in one code piece, in order
+#[derive(serde::Serialize, Debug, Clone)]
+pub struct References {
+    pub code_piece_id: u8,
+    pub locations: Vec<Location>,
+}
+
+#[derive(serde::Serialize, Debug, Clone)]
+pub struct TextEdit {
+    pub location: Location,
+    pub text: String,
+}
+
+/// What has to be replaced in one code piece, in order
+#[derive(serde::Serialize, Debug, Clone)]
+pub struct CodePieceEdits {
+    pub code_piece_id: u8,
+    pub edits: Vec<TextEdit>,
+}
+
+struct IndexedPiece {
+    /// The name of the code piece in errors, like when it is run
+    context: String,
+    /// The name it is imported by, if it is a library
+    library: Option<String>,
+    /// Code pieces which do not parse can not be searched, so this is why
+    analysis: Result<Analysis, MakerError>,
+}
+
+/// The variables of a bot's code pieces and everywhere they are used, so that they
+/// can be found and renamed without running anything
+#[derive(Default)]
+pub struct SymbolIndex {
+    pieces: HashMap<u8, IndexedPiece>,
+}
+
+impl SymbolIndex {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Indexes a code piece, replacing what was known about it before
+    ///
+    /// `library` is its name if it is a library, and `edition` the number of the edition it is written in
+    pub fn update(&mut self, id: u8, library: Option<String>, code: &str, edition: u8) {
+        let context = match &library {
+            Some(name) => name.clone(),
+            None => format!("code_piece_{}", id),
+        };
+        let analysis = analyze(code, context.clone(), edition);
+
+        self.pieces.insert(
+            id,
+            IndexedPiece {
+                context,
+                library,
+                analysis,
+            },
+        );
+    }
+
+    pub fn remove(&mut self, id: u8) {
+        self.pieces.remove(&id);
+    }
+
+    pub fn contains(&self, id: u8) -> bool {
+        self.pieces.contains_key(&id)
+    }
+
+    /// The IDs of every indexed code piece
+    pub fn code_pieces(&self) -> Vec<u8> {
+        self.pieces.keys().copied().collect()
+    }
+
+    /// Finds the symbol written at `line`:`column` of a code piece, either where it is
+    /// declared or where it is used
+    pub fn symbol_at(&self, id: u8, line: u32, column: u32) -> Result<Symbol, MakerError> {
+        let piece = self
+            .pieces
+            .get(&id)
+            .ok_or_else(|| error(format!("Code piece {} is not part of this bot", id)))?;
+        let analysis = piece.analysis.as_ref().map_err(|err| {
+            err.clone()
+                .with_note("the code has to parse before its variables can be found")
+        })?;
+        let at = |location: &Location| {
+            location.line == line && location.column <= column && column <= location.end_column
+        };
+        let symbol = |name: &str| match &piece.library {
+            Some(library) => Symbol::Library {
+                library: library.clone(),
+                name: name.to_string(),
+            },
+            None => Symbol::Local {
+                code_piece: id,
+                name: name.to_string(),
+            },
+        };
+
+        if let Some(definition) = analysis.definitions.iter().find(|x| at(&x.location)) {
+            return Ok(symbol(&definition.name));
+        }
+
+        if let Some(reference) = analysis.references.iter().find(|x| at(&x.location)) {
+            return match reference.definition {
+                Some(_) => Ok(symbol(&reference.name)),
+                None if is_builtin(&reference.name) => Err(error_at(
+                    format!("{} is a builtin, it can not be renamed", reference.name),
+                    &reference.location,
+                )),
+                None => Err(error_at(
+                    format!("The variable {} does not exist", reference.name),
+                    &reference.location,
+                )),
+            };
+        }
+
+        if let Some(member) = analysis.members.iter().find(|x| at(&x.location)) {
+            return match &member.library {
+                Some(library) => Ok(Symbol::Library {
+                    library: library.clone(),
+                    name: member.name.clone(),
+                }),
+                None => Err(error_at(
+                    format!(
+                        "{}.{} is not a variable of a library",
+                        member.object, member.name
+                    ),
+                    &member.location,
+                )
+                .with_note("only variables, and those of imported libraries, can be renamed")),
+            };
+        }
+
+        Err(error(format!(
+            "There is no variable at {}:{}",
+            line, column
+        )))
+    }
+
+    /// Everywhere `symbol` is declared and used, by code piece
+    pub fn references(&self, symbol: &Symbol) -> Vec<References> {
+        let name = symbol.name();
+        let mut found = vec![];
+
+        for (id, piece) in &self.pieces {
+            let Ok(analysis) = &piece.analysis else {
+                continue;
+            };
+            let mut locations: Vec<Location> = vec![];
+
+            if self.declares(*id, piece, symbol) {
+                locations.extend(
+                    analysis
+                        .definitions
+                        .iter()
+                        .filter(|x| x.name == name)
+                        .map(|x| x.location.clone()),
+                );
+                locations.extend(
+                    analysis
+                        .references
+                        .iter()
+                        .filter(|x| x.name == name && x.definition.is_some())
+                        .map(|x| x.location.clone()),
+                );
+            }
+            if let Symbol::Library { library, .. } = symbol {
+                locations.extend(
+                    analysis
+                        .members
+                        .iter()
+                        .filter(|x| x.library.as_ref() == Some(library) && x.name == name)
+                        .map(|x| x.location.clone()),
+                );
+            }
+
+            if !locations.is_empty() {
+                locations.sort_by_key(|x| x.start);
+                found.push(References {
+                    code_piece_id: *id,
+                    locations,
+                });
+            }
+        }
+
+        found.sort_by_key(|x| x.code_piece_id);
+        found
+    }
+
+    /// Everywhere the symbol at `line`:`column` of a code piece is used
+    pub fn find_references(
+        &self,
+        id: u8,
+        line: u32,
+        column: u32,
+    ) -> Result<Vec<References>, MakerError> {
+        Ok(self.references(&self.symbol_at(id, line, column)?))
+    }
+
+    /// The edits which rename the symbol at `line`:`column` of a code piece to `new_name`,
+    /// refusing if it would then clash with another variable
+    ///
+    /// `globals` are the names the bot has besides the builtins, like its plugins
+    pub fn rename(
+        &self,
+        id: u8,
+        line: u32,
+        column: u32,
+        new_name: &str,
+        globals: &[&str],
+    ) -> Result<Vec<CodePieceEdits>, MakerError> {
+        let symbol = self.symbol_at(id, line, column)?;
+
+        if !is_identifier(new_name) {
+            return Err(error(format!("{} is not a valid variable name", new_name))
+                .with_note("names are made of letters, digits and _, and can not be keywords"));
+        }
+        if new_name == symbol.name() {
+            return Ok(vec![]);
+        }
+        if is_builtin(new_name) {
+            return Err(error(format!("{} is already a builtin", new_name)));
+        }
+        if globals.contains(&new_name) {
+            return Err(
+                error(format!("{} is already a global of the bot", new_name))
+                    .with_note("plugins are globals named after them"),
+            );
+        }
+
+        self.check_conflicts(&symbol, new_name)?;
+
+        Ok(self
+            .references(&symbol)
+            .into_iter()
+            .map(|references| CodePieceEdits {
+                code_piece_id: references.code_piece_id,
+                edits: references
+                    .locations
+                    .into_iter()
+                    .map(|location| TextEdit {
+                        location,
+                        text: new_name.to_string(),
+                    })
+                    .collect(),
+            })
+            .collect())
+    }
+
+    /// Whether `symbol` is declared in the code piece, rather than only used
+    fn declares(&self, id: u8, piece: &IndexedPiece, symbol: &Symbol) -> bool {
+        match symbol {
+            Symbol::Local { code_piece, .. } => *code_piece == id,
+            Symbol::Library { library, .. } => piece.library.as_ref() == Some(library),
+        }
+    }
+
+    fn check_conflicts(&self, symbol: &Symbol, new_name: &str) -> Result<(), MakerError> {
+        if let Symbol::Library { library, .. } = symbol {
+            if !self
+                .pieces
+                .values()
+                .any(|x| x.library.as_ref() == Some(library))
+            {
+                return Err(error(format!("There is no library called {}", library)));
+            }
+        }
+
+        for (id, piece) in &self.pieces {
+            let declares = self.declares(*id, piece, symbol);
+            let analysis = match &piece.analysis {
+                Ok(analysis) => analysis,
+                // Any code piece could import a library, so all of them have to be known
+                Err(_) if declares || matches!(symbol, Symbol::Library { .. }) => {
+                    return Err(error(format!(
+                        "{} does not parse, so it is not known where it uses {}",
+                        piece.context,
+                        symbol.name()
+                    ))
+                    .with_note("fix its errors before renaming"))
+                }
+                Err(_) => continue,
+            };
+
+            if declares {
+                if let Some(definition) = analysis.definitions.iter().find(|x| x.name == new_name) {
+                    return Err(error_at(
+                        format!(
+                            "{} already has a variable called {}",
+                            piece.context, new_name
+                        ),
+                        &definition.location,
+                    ));
+                }
+                // A use of a builtin, or of a variable which does not exist yet
+                if let Some(reference) = analysis.references.iter().find(|x| x.name == new_name) {
+                    return Err(error_at(
+                        format!("{} already uses {}", piece.context, new_name),
+                        &reference.location,
+                    ));
+                }
+            }
+
+            // This would find the renamed variable, rather than failing like it does now
+            if let Symbol::Library { library, .. } = symbol {
+                if let Some(member) = analysis
+                    .members
+                    .iter()
+                    .find(|x| x.library.as_ref() == Some(library) && x.name == new_name)
+                {
+                    return Err(error_at(
+                        format!(
+                            "{} already uses {}.{}",
+                            piece.context, member.object, new_name
+                        ),
+                        &member.location,
+                    ));
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn analyze(code: &str, context: String, edition: u8) -> Result<Analysis, MakerError> {
+    let edition = Edition::from_number(edition)
+        .ok_or_else(|| error(format!("{} uses unknown edition {}", context, edition)))?;
+    let globals: Vec<&str> = BUILTINS.iter().map(|x| x.name).collect();
+
+    let tokens = lex(code, context, edition)?;
+    let block = Parser::new(&tokens, edition).parse()?;
+    Ok(semantic::analyze(&block, &globals))
+}
+
+fn is_identifier(name: &str) -> bool {
+    match lex(name, String::new(), Edition::LATEST) {
+        Ok(tokens) => {
+            tokens.len() == 2
+                && matches!(tokens[0].token_type, TokenType::Identifier)
+                && tokens[0].value == name
+        }
+        Err(_) => false,
+    }
+}
+
+fn is_builtin(name: &str) -> bool {
+    BUILTINS.iter().any(|x| x.name == name)
+}
+
+fn error(message: impl Into<String>) -> MakerError {
+    MakerError::new(message, MakerErrorType::SymbolError)
+}
+
+fn error_at(message: impl Into<String>, location: &Location) -> MakerError {
+    MakerError::lang(message, location.clone(), MakerErrorType::SymbolError)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LIBRARY: &str = "var greeting = \"hi\"\nprint(greeting)";
+    const COMMAND: &str =
+        "var utils = import \"utils\"\nvar greeting = utils.greeting\nmessage.reply(greeting)";
+
+    fn index() -> SymbolIndex {
+        let mut index = SymbolIndex::new();
+        index.update(
+            1,
+            Some("utils".to_string()),
+            LIBRARY,
+            Edition::LATEST.number(),
+        );
+        index.update(2, None, COMMAND, Edition::LATEST.number());
+        index
+    }
+
+    /// The code of each code piece after the edits, by ID
+    fn apply(edits: Vec<CodePieceEdits>) -> Vec<(u8, String)> {
+        edits
+            .into_iter()
+            .map(|piece| {
+                let mut code = if piece.code_piece_id == 1 {
+                    LIBRARY
+                } else {
+                    COMMAND
+                }
+                .to_string();
+                for edit in piece.edits.iter().rev() {
+                    code.replace_range(edit.location.start..edit.location.end, &edit.text);
+                }
+                (piece.code_piece_id, code)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn library_variables_are_renamed_where_they_are_imported() {
+        let edits = index().rename(1, 1, 5, "welcome", &[]).unwrap();
+
+        assert_eq!(
+            apply(edits),
+            vec![
+                (1, "var welcome = \"hi\"\nprint(welcome)".to_string()),
+                (
+                    2,
+                    "var utils = import \"utils\"\nvar greeting = utils.welcome\nmessage.reply(greeting)"
+                        .to_string()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn local_variables_are_only_renamed_in_their_code_piece() {
+        let index = index();
+        assert_eq!(
+            index.symbol_at(2, 3, 15).unwrap(),
+            Symbol::Local {
+                code_piece: 2,
+                name: "greeting".to_string()
+            }
+        );
+
+        let edits = index.rename(2, 3, 15, "reply", &[]).unwrap();
+
+        assert_eq!(
+            apply(edits),
+            vec![(
+                2,
+                "var utils = import \"utils\"\nvar reply = utils.greeting\nmessage.reply(reply)"
+                    .to_string()
+            )]
+        );
+    }
+
+    #[test]
+    fn renames_which_would_clash_are_refused() {
+        let index = index();
+        let rename = |line, column, new_name| {
+            index
+                .rename(2, line, column, new_name, &["plugin"])
+                .unwrap_err()
+                .message
+                .clone()
+        };
+
+        assert_eq!(
+            rename(2, 5, "utils"),
+            "code_piece_2 already has a variable called utils"
+        );
+        assert_eq!(rename(2, 5, "message"), "message is already a builtin");
+        assert_eq!(
+            rename(2, 5, "plugin"),
+            "plugin is already a global of the bot"
+        );
+        assert_eq!(rename(2, 5, "var"), "var is not a valid variable name");
+        assert_eq!(
+            rename(3, 1, "msg"),
+            "message is a builtin, it can not be renamed"
+        );
+        assert_eq!(
+            rename(3, 9, "send"),
+            "message.reply is not a variable of a library"
+        );
+    }
+
+    #[test]
+    fn code_pieces_which_do_not_parse_are_not_renamed_in() {
+        let mut index = index();
+        index.update(3, None, "var = ", Edition::LATEST.number());
+
+        // It might import the library
+        let err = index.rename(1, 1, 5, "welcome", &[]).unwrap_err();
+        assert_eq!(
+            err.message,
+            "code_piece_3 does not parse, so it is not known where it uses greeting"
+        );
+
+        // But it can not see the variables of another command
+        assert!(index.rename(2, 2, 5, "welcome", &[]).is_ok());
+        assert!(index.symbol_at(3, 1, 1).is_err());
+    }
+}
@@ -0,0 +1,292 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex, OnceLock},
+};
+
+use crate::errors::MakerError;
+
+use super::{
+    engine::Engine,
+    natives::NativeRegistry,
+    runtime::Program,
+    values::{self, RuntimeValue},
+};
+
+/// A message to run a command with, and the replies it should give to it
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
+pub struct TestCase {
+    pub name: String,
+    pub content: String,
+    /// The name of who sent the message
+    pub author: String,
+    /// The ID of the channel it was sent in
+    pub channel: String,
+    pub expected_replies: Vec<String>,
+}
+
+#[derive(serde::Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum DiffKind {
+    /// Expected and given
+    Same,
+    /// Expected but not given
+    Missing,
+    /// Given but not expected
+    Unexpected,
+}
+
+#[derive(serde::Serialize, Debug, Clone)]
+pub struct DiffLine {
+    pub kind: DiffKind,
+    pub reply: String,
+}
+
+#[derive(serde::Serialize, Debug, Clone)]
+pub struct TestResult {
+    pub name: String,
+    pub passed: bool,
+    pub replies: Vec<String>,
+    /// The replies compared to the expected ones, in order
+    pub diff: Vec<DiffLine>,
+    /// What the command printed or logged
+    pub logs: Vec<String>,
+    /// What the command stopped with, e.g. a failed assertion
+    pub error: Option<MakerError>,
+}
+
+impl TestResult {
+    /// The result as text, for the command line
+    pub fn render(&self) -> String {
+        let mut rendered = format!(
+            "{} {}\n",
+            if self.passed { "PASS" } else { "FAIL" },
+            self.name
+        );
+        if self.passed {
+            return rendered;
+        }
+
+        for line in &self.diff {
+            let sign = match line.kind {
+                DiffKind::Same => ' ',
+                DiffKind::Missing => '-',
+                DiffKind::Unexpected => '+',
+            };
+            rendered.push_str(&format!("    {} {:?}\n", sign, line.reply));
+        }
+        for log in &self.logs {
+            rendered.push_str(&format!("    log: {}\n", log));
+        }
+        if let Some(error) = &self.error {
+            rendered.push_str(&format!("    error: {}", error.message));
+            if let Some(location) = &error.location {
+                rendered.push_str(&format!(
+                    " at {}:{}:{}",
+                    location.context, location.line, location.column
+                ));
+            }
+            rendered.push('\n');
+        }
+
+        rendered
+    }
+}
+
+/// What a command did to the simulated message
+#[derive(Default)]
+struct Simulation {
+    replies: Mutex<Vec<String>>,
+    logs: Mutex<Vec<String>>,
+}
+
+impl Simulation {
+    fn log(&self, level: &str, args: Vec<RuntimeValue>) -> Result<RuntimeValue, MakerError> {
+        if let Ok(mut logs) = self.logs.lock() {
+            logs.push(format!("{}: {}", level, args[0]));
+        }
+        Ok(values::Null::make())
+    }
+}
+
+/// The natives the app gives commands, without Discord
+fn registry() -> &'static NativeRegistry<Simulation> {
+    static REGISTRY: OnceLock<NativeRegistry<Simulation>> = OnceLock::new();
+
+    REGISTRY.get_or_init(|| {
+        NativeRegistry::<Simulation>::new()
+            .register("message.reply", |simulation, args, _| async move {
+                if let Ok(mut replies) = simulation.replies.lock() {
+                    replies.push(args[0].to_string());
+                }
+                Ok(values::Null::make())
+            })
+            .register("print", |simulation, args, _| async move {
+                simulation.log("print", args)
+            })
+            .register("log.info", |simulation, args, _| async move {
+                simulation.log("info", args)
+            })
+            .register("log.warn", |simulation, args, _| async move {
+                simulation.log("warn", args)
+            })
+    })
+}
+
+fn bindings(test: &TestCase, simulation: Arc<Simulation>) -> HashMap<String, RuntimeValue> {
+    let mut message = registry().bind("message", simulation.clone());
+    for (name, value) in [("content", &test.content), ("author", &test.author)] {
+        message.insert(name.to_string(), values::StringValue::make(value.clone()));
+    }
+    for name in ["id", "author_id"] {
+        message.insert(name.to_string(), values::Snowflake::make(0));
+    }
+    // Kept as it was written when it is not an ID, rather than failing the test
+    let channel = match test.channel.trim().parse() {
+        Ok(id) => values::Snowflake::make(id),
+        Err(_) => values::StringValue::make(test.channel.clone()),
+    };
+    message.insert("channel".to_string(), channel);
+
+    let mut bindings = HashMap::from([
+        ("message".to_string(), values::Object::make(message)),
+        (
+            "log".to_string(),
+            values::Object::make(registry().bind("log", simulation.clone())),
+        ),
+    ]);
+    if let Some(print) = registry().get("print", simulation) {
+        bindings.insert("print".to_string(), print);
+    }
+    bindings
+}
+
+/// Runs a command's program with the message of `test`, `engine` has the bot's libraries
+/// and `globals` is what the command gets besides the message, e.g. plugins
+pub async fn run(
+    engine: &Engine,
+    program: &Program,
+    test: &TestCase,
+    mut globals: HashMap<String, RuntimeValue>,
+) -> TestResult {
+    let simulation = Arc::new(Simulation::default());
+    globals.extend(bindings(test, simulation.clone()));
+    let result = engine.eval(program, globals).await;
+
+    let replies = simulation
+        .replies
+        .lock()
+        .map(|x| x.clone())
+        .unwrap_or_default();
+    let logs = simulation
+        .logs
+        .lock()
+        .map(|x| x.clone())
+        .unwrap_or_default();
+    let diff = diff(&test.expected_replies, &replies);
+
+    TestResult {
+        name: test.name.clone(),
+        passed: result.is_ok() && replies == test.expected_replies,
+        replies,
+        diff,
+        logs,
+        error: result.err(),
+    }
+}
+
+/// A failed result for a test which could not run at all, e.g. as the command does not compile
+pub fn failed(test: &TestCase, error: MakerError) -> TestResult {
+    TestResult {
+        name: test.name.clone(),
+        passed: false,
+        replies: vec![],
+        diff: diff(&test.expected_replies, &[]),
+        logs: vec![],
+        error: Some(error),
+    }
+}
+
+/// Lines up the replies with the expected ones, by their longest common subsequence
+fn diff(expected: &[String], replies: &[String]) -> Vec<DiffLine> {
+    // lengths[i][j] is the length of the common subsequence of expected[i..] and replies[j..]
+    let mut lengths = vec![vec![0; replies.len() + 1]; expected.len() + 1];
+    for i in (0..expected.len()).rev() {
+        for j in (0..replies.len()).rev() {
+            lengths[i][j] = if expected[i] == replies[j] {
+                lengths[i + 1][j + 1] + 1
+            } else {
+                lengths[i + 1][j].max(lengths[i][j + 1])
+            };
+        }
+    }
+
+    let line = |kind, reply: &String| DiffLine {
+        kind,
+        reply: reply.clone(),
+    };
+    let (mut i, mut j) = (0, 0);
+    let mut lines = vec![];
+
+    while i < expected.len() && j < replies.len() {
+        if expected[i] == replies[j] {
+            lines.push(line(DiffKind::Same, &expected[i]));
+            i += 1;
+            j += 1;
+        } else if lengths[i + 1][j] >= lengths[i][j + 1] {
+            lines.push(line(DiffKind::Missing, &expected[i]));
+            i += 1;
+        } else {
+            lines.push(line(DiffKind::Unexpected, &replies[j]));
+            j += 1;
+        }
+    }
+    lines.extend(expected[i..].iter().map(|x| line(DiffKind::Missing, x)));
+    lines.extend(replies[j..].iter().map(|x| line(DiffKind::Unexpected, x)));
+
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn case(content: &str, expected_replies: &[&str]) -> TestCase {
+        TestCase {
+            name: "test".to_string(),
+            content: content.to_string(),
+            author: "someone".to_string(),
+            channel: "1".to_string(),
+            expected_replies: expected_replies.iter().map(|x| x.to_string()).collect(),
+        }
+    }
+
+    async fn run_code(code: &str, test: &TestCase) -> TestResult {
+        let engine = Engine::new();
+        let program = engine.compile(code).unwrap();
+        run(&engine, &program, test, HashMap::new()).await
+    }
+
+    #[tokio::test]
+    async fn replies_are_compared_in_order() {
+        let code = "message.reply(message.content)\nmessage.reply(\"done\")";
+
+        let result = run_code(code, &case("hi", &["hi", "done"])).await;
+        assert!(result.passed, "{}", result.render());
+
+        let result = run_code(code, &case("hi", &["done", "hi"])).await;
+        assert!(!result.passed);
+        let kinds: Vec<DiffKind> = result.diff.iter().map(|x| x.kind).collect();
+        assert_eq!(
+            kinds,
+            vec![DiffKind::Missing, DiffKind::Same, DiffKind::Unexpected]
+        );
+    }
+
+    #[tokio::test]
+    async fn anything_can_be_replied() {
+        let code = "message.reply(1)\nmessage.reply(true)\nmessage.reply(message.id)";
+        let result = run_code(code, &case("hi", &["1", "true", "0"])).await;
+
+        assert!(result.passed, "{}", result.render());
+    }
+}
@@ -0,0 +1,294 @@
+use std::{collections::HashMap, fmt::Write, sync::Arc, sync::OnceLock};
+
+use chrono::{
+    format::{Item, StrftimeItems},
+    DateTime, TimeDelta, Utc,
+};
+use chrono_tz::Tz;
+
+use crate::errors::{MakerError, MakerErrorType};
+
+use super::{
+    natives::NativeRegistry,
+    values::{self, RuntimeValue},
+};
+
+/// `time` and `to_snowflake`, which every script has like `assert`
+fn registry() -> &'static NativeRegistry<()> {
+    static REGISTRY: OnceLock<NativeRegistry<()>> = OnceLock::new();
+
+    REGISTRY.get_or_init(|| {
+        NativeRegistry::<()>::new()
+            .register("time.now", |_, _, _| async move {
+                Ok(values::DateTime::make(Utc::now()))
+            })
+            .register("time.from_timestamp", |_, args, _| async move {
+                let seconds = number(&args[0])?;
+                DateTime::from_timestamp_millis((seconds * 1000.0).round() as i64)
+                    .map(values::DateTime::make)
+                    .ok_or_else(|| error(format!("The timestamp {} is out of range", seconds)))
+            })
+            .register("time.parse", |_, args, _| async move {
+                let text = args[0].to_string();
+                DateTime::parse_from_rfc3339(text.trim())
+                    .map(|x| values::DateTime::make(x.with_timezone(&Utc)))
+                    .map_err(|err| {
+                        error(format!("Could not read the time {:?}: {}", text, err))
+                            .with_note("times are written like 2024-05-01T12:00:00Z or 2024-05-01T14:00:00+02:00")
+                    })
+            })
+            .register("time.seconds", |_, args, _| async move {
+                duration(number(&args[0])?, 1000.0)
+            })
+            .register("time.minutes", |_, args, _| async move {
+                duration(number(&args[0])?, 60_000.0)
+            })
+            .register("time.hours", |_, args, _| async move {
+                duration(number(&args[0])?, 3_600_000.0)
+            })
+            .register("time.days", |_, args, _| async move {
+                duration(number(&args[0])?, 86_400_000.0)
+            })
+            .register("to_snowflake", |_, args, _| async move {
+                let id = args[0].to_string();
+                id.trim()
+                    .parse()
+                    .map(values::Snowflake::make)
+                    .map_err(|_| error(format!("{:?} is not a Discord ID", id)))
+            })
+    })
+}
+
+/// The members of a datetime which are functions, given the datetime itself
+fn datetime_registry() -> &'static NativeRegistry<DateTime<Utc>> {
+    static REGISTRY: OnceLock<NativeRegistry<DateTime<Utc>>> = OnceLock::new();
+
+    REGISTRY.get_or_init(|| {
+        NativeRegistry::<DateTime<Utc>>::new()
+            .register("datetime.add", |time, args, _| async move {
+                time.checked_add_signed(delta(&args[0])?)
+                    .map(values::DateTime::make)
+                    .ok_or_else(|| error("The time is out of range"))
+            })
+            .register("datetime.sub", |time, args, _| async move {
+                time.checked_sub_signed(delta(&args[0])?)
+                    .map(values::DateTime::make)
+                    .ok_or_else(|| error("The time is out of range"))
+            })
+            .register("datetime.since", |time, args, _| async move {
+                Ok(values::Duration::make(
+                    time.signed_duration_since(datetime(&args[0])?),
+                ))
+            })
+            .register("datetime.is_before", |time, args, _| async move {
+                Ok(values::Boolean::make(*time < datetime(&args[0])?))
+            })
+            .register("datetime.is_after", |time, args, _| async move {
+                Ok(values::Boolean::make(*time > datetime(&args[0])?))
+            })
+            .register("datetime.format", |time, args, _| async move {
+                format(&time, &args[0].to_string(), &args[1].to_string())
+                    .map(values::StringValue::make)
+            })
+            .register("datetime.relative", |time, _, _| async move {
+                Ok(values::StringValue::make(relative(&time, &Utc::now())))
+            })
+    })
+}
+
+/// The members of a duration which are functions, given the duration itself
+fn duration_registry() -> &'static NativeRegistry<TimeDelta> {
+    static REGISTRY: OnceLock<NativeRegistry<TimeDelta>> = OnceLock::new();
+
+    REGISTRY.get_or_init(|| {
+        NativeRegistry::<TimeDelta>::new()
+            .register("duration.add", |duration, args, _| async move {
+                duration
+                    .checked_add(&delta(&args[0])?)
+                    .map(values::Duration::make)
+                    .ok_or_else(|| error("The duration is out of range"))
+            })
+            .register("duration.sub", |duration, args, _| async move {
+                duration
+                    .checked_sub(&delta(&args[0])?)
+                    .map(values::Duration::make)
+                    .ok_or_else(|| error("The duration is out of range"))
+            })
+            .register("duration.is_longer", |duration, args, _| async move {
+                Ok(values::Boolean::make(*duration > delta(&args[0])?))
+            })
+            .register("duration.is_shorter", |duration, args, _| async move {
+                Ok(values::Boolean::make(*duration < delta(&args[0])?))
+            })
+    })
+}
+
+pub fn natives() -> HashMap<String, RuntimeValue> {
+    let mut natives = HashMap::from([(
+        "time".to_string(),
+        values::Object::make(registry().bind("time", Arc::new(()))),
+    )]);
+    if let Some(to_snowflake) = registry().get("to_snowflake", Arc::new(())) {
+        natives.insert("to_snowflake".to_string(), to_snowflake);
+    }
+    natives
+}
+
+/// The member `name` of a snowflake, datetime or duration, `None` if it has no such member
+pub fn member(value: &RuntimeValue, name: &str) -> Option<RuntimeValue> {
+    match value {
+        RuntimeValue::Snowflake(snowflake) => match name {
+            "created_at" => Some(values::DateTime::make(snowflake.created_at())),
+            _ => None,
+        },
+        RuntimeValue::DateTime(time) => match name {
+            "timestamp" => Some(values::Number::make(
+                time.value.timestamp_millis() as f64 / 1000.0,
+            )),
+            name => datetime_registry().get(&format!("datetime.{}", name), Arc::new(time.value)),
+        },
+        RuntimeValue::Duration(duration) => {
+            let millis = duration.value.num_milliseconds() as f64;
+            match name {
+                "seconds" => Some(values::Number::make(millis / 1000.0)),
+                "minutes" => Some(values::Number::make(millis / 60_000.0)),
+                "hours" => Some(values::Number::make(millis / 3_600_000.0)),
+                "days" => Some(values::Number::make(millis / 86_400_000.0)),
+                name => {
+                    duration_registry().get(&format!("duration.{}", name), Arc::new(duration.value))
+                }
+            }
+        }
+        _ => None,
+    }
+}
+
+/// Writes `time` in the IANA time zone `timezone` with a strftime `pattern`
+fn format(time: &DateTime<Utc>, pattern: &str, timezone: &str) -> Result<String, MakerError> {
+    let timezone: Tz = timezone.parse().map_err(|_| {
+        error(format!("Unknown time zone {}", timezone))
+            .with_note("time zones are named like UTC, Europe/London or America/New_York")
+    })?;
+
+    // chrono panics on invalid patterns when writing them, rather than failing
+    let items: Vec<Item> = StrftimeItems::new(pattern).collect();
+    if items.contains(&Item::Error) {
+        return Err(error(format!("Invalid time format {:?}", pattern))
+            .with_note("the formats are those of strftime, e.g. %Y-%m-%d %H:%M"));
+    }
+
+    let mut formatted = String::new();
+    write!(
+        formatted,
+        "{}",
+        time.with_timezone(&timezone)
+            .format_with_items(items.iter())
+    )
+    .map_err(|_| error(format!("Could not format the time with {:?}", pattern)))?;
+
+    Ok(formatted)
+}
+
+/// e.g. `3 hours ago` or `in 2 days`, rounded down to the largest unit
+fn relative(time: &DateTime<Utc>, now: &DateTime<Utc>) -> String {
+    const UNITS: &[(i64, &str)] = &[
+        (365 * 24 * 60 * 60, "year"),
+        (30 * 24 * 60 * 60, "month"),
+        (24 * 60 * 60, "day"),
+        (60 * 60, "hour"),
+        (60, "minute"),
+    ];
+
+    let difference = now.signed_duration_since(time).num_seconds();
+    let Some((size, unit)) = UNITS.iter().find(|(size, _)| difference.abs() >= *size) else {
+        return "just now".to_string();
+    };
+
+    let amount = difference.abs() / size;
+    let plural = if amount == 1 { "" } else { "s" };
+    if difference > 0 {
+        format!("{} {}{} ago", amount, unit, plural)
+    } else {
+        format!("in {} {}{}", amount, unit, plural)
+    }
+}
+
+fn duration(amount: f64, unit_millis: f64) -> Result<RuntimeValue, MakerError> {
+    let millis = amount * unit_millis;
+    if !millis.is_finite() || millis.abs() >= i64::MAX as f64 {
+        return Err(error(format!("{} is too long for a duration", amount)));
+    }
+
+    TimeDelta::try_milliseconds(millis.round() as i64)
+        .map(values::Duration::make)
+        .ok_or_else(|| error(format!("{} is too long for a duration", amount)))
+}
+
+// The arguments are checked against the signatures, these only fail if a signature is wrong
+
+fn number(value: &RuntimeValue) -> Result<f64, MakerError> {
+    match value {
+        RuntimeValue::Number(number) => Ok(number.value),
+        other => Err(error(format!(
+            "Expected number but found {}",
+            other.type_name()
+        ))),
+    }
+}
+
+fn datetime(value: &RuntimeValue) -> Result<DateTime<Utc>, MakerError> {
+    match value {
+        RuntimeValue::DateTime(time) => Ok(time.value),
+        other => Err(error(format!(
+            "Expected datetime but found {}",
+            other.type_name()
+        ))),
+    }
+}
+
+fn delta(value: &RuntimeValue) -> Result<TimeDelta, MakerError> {
+    match value {
+        RuntimeValue::Duration(duration) => Ok(duration.value),
+        other => Err(error(format!(
+            "Expected duration but found {}",
+            other.type_name()
+        ))),
+    }
+}
+
+fn error(message: impl Into<String>) -> MakerError {
+    MakerError::new(message, MakerErrorType::RuntimeError)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use crate::lang::engine::Engine;
+
+    async fn eval(source: &str) -> String {
+        let engine = Engine::new();
+        let program = engine.compile(source).unwrap();
+        engine
+            .eval(&program, HashMap::new())
+            .await
+            .unwrap()
+            .to_string()
+    }
+
+    #[tokio::test]
+    async fn snowflakes_know_when_they_were_made() {
+        let code = "var id = to_snowflake(\"175928847299117063\")\nvar made = id.created_at\nmade.timestamp";
+
+        assert_eq!(eval(code).await, "1462015105.796");
+    }
+
+    #[tokio::test]
+    async fn durations_add_to_datetimes() {
+        let code = "var start = time.from_timestamp(0)\nvar later = start.add(time.hours(2))\nlater.timestamp";
+        assert_eq!(eval(code).await, "7200");
+
+        let code = "var day = time.days(1)\nday.hours";
+        assert_eq!(eval(code).await, "24");
+    }
+}
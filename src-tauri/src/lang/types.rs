@@ -0,0 +1,530 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+};
+
+use crate::errors::{MakerError, MakerErrorType};
+
+use super::{
+    builtins::{self, Builtin},
+    lexer::Location,
+    natives::{ParamDefault, Signature},
+    nodes::{Block, Call, Expression, FunctionDeclaration, Identifier},
+    runtime::function_name,
+    values::RuntimeValue,
+};
+
+/// What is known about a value before running the script
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Type {
+    /// Could be anything, nothing is checked
+    Any,
+    Null,
+    Number,
+    String,
+    Boolean,
+    /// An object, the members are only known for builtins
+    Object(&'static [Builtin]),
+    /// A function, the signature is only known for builtins
+    Function(Option<&'static Signature>),
+    /// A Discord ID, it can be used wherever a string can
+    Snowflake,
+    DateTime,
+    Duration,
+}
+
+impl Type {
+    /// The type an annotation like `var n: number` refers to
+    pub fn from_name(name: &str) -> Option<Type> {
+        Some(match name {
+            "any" => Type::Any,
+            "null" => Type::Null,
+            "number" => Type::Number,
+            "string" => Type::String,
+            "boolean" => Type::Boolean,
+            "object" => Type::Object(&[]),
+            "function" => Type::Function(None),
+            "snowflake" => Type::Snowflake,
+            "datetime" => Type::DateTime,
+            "duration" => Type::Duration,
+            _ => return None,
+        })
+    }
+
+    /// Whether a value of type `other` can be used where this type is expected
+    pub fn accepts(&self, other: &Type) -> bool {
+        match (self, other) {
+            (Type::Any, _) | (_, Type::Any) => true,
+            (Type::Object(_), Type::Object(_)) => true,
+            (Type::Function(_), Type::Function(_)) => true,
+            (Type::String, Type::Snowflake) => true,
+            (a, b) => a == b,
+        }
+    }
+
+    /// Used to check annotations while running, as not everything is known beforehand
+    pub fn matches(&self, value: &RuntimeValue) -> bool {
+        matches!(
+            (self, value),
+            (Type::Any, _)
+                | (Type::Null, RuntimeValue::Null(_))
+                | (Type::Number, RuntimeValue::Number(_))
+                | (Type::String, RuntimeValue::StringValue(_))
+                | (Type::Boolean, RuntimeValue::Boolean(_))
+                | (Type::Object(_), RuntimeValue::Object(_))
+                | (Type::Function(_), RuntimeValue::NativeFunction(_))
+                | (Type::Function(_), RuntimeValue::Function(_))
+                | (Type::String, RuntimeValue::Snowflake(_))
+                | (Type::Snowflake, RuntimeValue::Snowflake(_))
+                | (Type::DateTime, RuntimeValue::DateTime(_))
+                | (Type::Duration, RuntimeValue::Duration(_))
+        )
+    }
+
+    /// What can be accessed with `.` on a value of this type, if it is known
+    pub fn members(&self) -> Option<&'static [Builtin]> {
+        match self {
+            Type::Object(members) => Some(members),
+            ty => builtins::VALUE_TYPES
+                .iter()
+                .find(|x| x.ty == *ty)
+                .map(|x| x.members),
+        }
+    }
+}
+
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Type::Any => write!(f, "any"),
+            Type::Null => write!(f, "null"),
+            Type::Number => write!(f, "number"),
+            Type::String => write!(f, "string"),
+            Type::Boolean => write!(f, "boolean"),
+            Type::Object(_) => write!(f, "object"),
+            Type::Function(None) => write!(f, "function"),
+            Type::Function(Some(signature)) => write!(f, "{}", signature.describe("function")),
+            Type::Snowflake => write!(f, "snowflake"),
+            Type::DateTime => write!(f, "datetime"),
+            Type::Duration => write!(f, "duration"),
+        }
+    }
+}
+
+/// Infers the type of everything in the program and reports what would fail
+/// at runtime, `globals` are the builtins which are declared before the script runs
+pub fn check(block: &Block, globals: &[Builtin]) -> Vec<MakerError> {
+    let mut checker = Checker {
+        variables: globals.iter().map(|x| (x.name.to_string(), x.ty)).collect(),
+        declared: HashSet::new(),
+        functions: HashMap::new(),
+        errors: vec![],
+    };
+
+    checker.block(block);
+    checker.errors
+}
+
+/// The parameters and return type of a function declared with `fn`
+#[derive(Clone)]
+struct FunctionType {
+    params: Vec<(String, Type)>,
+    returns: Type,
+}
+
+impl FunctionType {
+    fn describe(&self, name: &str) -> String {
+        let params: Vec<String> = self
+            .params
+            .iter()
+            .map(|(name, ty)| format!("{}: {}", name, ty))
+            .collect();
+        format!("{}({}) -> {}", name, params.join(", "), self.returns)
+    }
+}
+
+struct Checker {
+    variables: HashMap<String, Type>,
+    /// The variables declared by the script itself
+    declared: HashSet<String>,
+    /// The variables which are functions declared with `fn`, their type is `Function(None)`
+    functions: HashMap<String, FunctionType>,
+    errors: Vec<MakerError>,
+}
+
+impl Checker {
+    fn error(&mut self, error: MakerError) -> Type {
+        self.errors.push(error);
+        Type::Any
+    }
+
+    /// The type an annotation names, unknown ones are reported and checked as `any`
+    fn annotation(&mut self, annotation: &Identifier) -> Type {
+        match Type::from_name(&annotation.name) {
+            Some(ty) => ty,
+            None => self.error(
+                MakerError::lang(
+                    format!("Unknown type {}", annotation.name),
+                    annotation.location.clone(),
+                    MakerErrorType::TypeError,
+                )
+                .with_note(
+                    "the types are any, null, number, string, boolean, object, function, snowflake, datetime and duration",
+                ),
+            ),
+        }
+    }
+
+    /// Reports a value which does not match the annotation it is given to
+    fn expect(&mut self, expected: Type, found: Type, location: Location, annotation: &Identifier) {
+        if !expected.accepts(&found) {
+            self.errors.push(
+                MakerError::lang(
+                    format!("Expected {} but found {}", expected, found),
+                    location,
+                    MakerErrorType::TypeError,
+                )
+                .with_label(
+                    annotation.location.clone(),
+                    "expected because of this annotation",
+                ),
+            );
+        }
+    }
+
+    /// Redeclaring is reported by the semantic analysis, the first one wins,
+    /// unless what it declares over is a builtin which can be shadowed
+    fn declare(&mut self, name: &str, ty: Type) -> bool {
+        let shadows = builtins::SHADOWABLE.contains(&name);
+        if self.declared.insert(name.to_string()) && (shadows || !self.variables.contains_key(name))
+        {
+            self.variables.insert(name.to_string(), ty);
+            self.functions.remove(name);
+            return true;
+        }
+        false
+    }
+
+    fn function(&mut self, dec: &FunctionDeclaration) {
+        let mut function = FunctionType {
+            params: vec![],
+            returns: Type::Any,
+        };
+        for param in &dec.params {
+            let ty = match &param.type_annotation {
+                Some(annotation) => self.annotation(annotation),
+                None => Type::Any,
+            };
+            function.params.push((param.name.name.clone(), ty));
+        }
+        let returns = dec
+            .return_annotation
+            .as_ref()
+            .map(|annotation| (self.annotation(annotation), annotation));
+        if let Some((ty, _)) = returns {
+            function.returns = ty;
+        }
+
+        let name = &dec.name.name;
+        let declared = self.declare(name, Type::Function(None));
+        if declared {
+            self.functions.insert(name.clone(), function.clone());
+        }
+
+        // The body has its own scope, with the parameters hiding what they are named after
+        let variables = self.variables.clone();
+        let functions = self.functions.clone();
+        let declared_before = self.declared.clone();
+        for (param, ty) in &function.params {
+            self.variables.insert(param.clone(), *ty);
+            self.functions.remove(param);
+            self.declared.insert(param.clone());
+        }
+
+        let body = self.block(&dec.body);
+        if let Some((expected, annotation)) = returns {
+            let location = match dec.body.nodes.last() {
+                Some(last) => last.get_location(),
+                None => dec.body.location.clone(),
+            };
+            self.expect(expected, body, location, annotation);
+        }
+
+        self.variables = variables;
+        self.functions = functions;
+        self.declared = declared_before;
+
+        // What it returns is only inferred when it is not annotated
+        if declared && returns.is_none() {
+            if let Some(function) = self.functions.get_mut(name) {
+                function.returns = body;
+            }
+        }
+    }
+
+    /// Checks the arguments of a call against the parameters of the function,
+    /// `note` describes the function
+    fn call(
+        &mut self,
+        call: &Call,
+        args: &[Type],
+        params: &[(&str, Type, &ParamDefault)],
+        note: String,
+    ) {
+        if args.len() > params.len() {
+            self.errors.push(
+                MakerError::lang(
+                    format!(
+                        "Too many arguments, expected at most {} but found {}",
+                        params.len(),
+                        args.len()
+                    ),
+                    call.args[params.len()].get_location(),
+                    MakerErrorType::TypeError,
+                )
+                .with_note(note.clone()),
+            );
+        }
+
+        for (i, (name, ty, default)) in params.iter().enumerate() {
+            match args.get(i) {
+                // Optional parameters can be given null on purpose
+                Some(Type::Null) if **default == ParamDefault::Optional => {}
+                Some(arg) if !ty.accepts(arg) => {
+                    self.errors.push(
+                        MakerError::lang(
+                            format!(
+                                "Invalid type for argument {} ({}), expected {} but found {}",
+                                i, name, ty, arg
+                            ),
+                            call.args[i].get_location(),
+                            MakerErrorType::TypeError,
+                        )
+                        .with_note(note.clone()),
+                    );
+                }
+                Some(_) => {}
+                None if **default == ParamDefault::Required => {
+                    self.errors.push(
+                        MakerError::lang(
+                            format!("Missing argument {} ({})", i, name),
+                            call.location.clone(),
+                            MakerErrorType::TypeError,
+                        )
+                        .with_note(note.clone()),
+                    );
+                    break;
+                }
+                None => {}
+            }
+        }
+    }
+
+    fn block(&mut self, block: &Block) -> Type {
+        let mut last = Type::Null;
+
+        for node in &block.nodes {
+            last = self.expression(node);
+        }
+
+        last
+    }
+
+    fn expression(&mut self, expression: &Expression) -> Type {
+        match expression {
+            Expression::Block(block) => self.block(block),
+            Expression::VariableDeclaration(dec) => {
+                let value = self.expression(&dec.value);
+
+                let declared = match &dec.type_annotation {
+                    Some(annotation) => {
+                        let expected = self.annotation(annotation);
+                        self.expect(expected, value, dec.value.get_location(), annotation);
+                        expected
+                    }
+                    None => value,
+                };
+
+                self.declare(&dec.name.name, declared);
+                Type::Null
+            }
+            Expression::FunctionDeclaration(dec) => {
+                self.function(dec);
+                Type::Null
+            }
+            Expression::IfBlock(block) => {
+                self.expression(&block.test);
+                let success = self.block(&block.success);
+
+                match &block.alternate {
+                    Some(alternate) if self.expression(alternate) == success => success,
+                    _ => Type::Any,
+                }
+            }
+            Expression::Call(call) => {
+                let callee = self.expression(&call.callee);
+                let args: Vec<Type> = call.args.iter().map(|x| self.expression(x)).collect();
+
+                let function = match &*call.callee {
+                    Expression::Identifier(ident) => self.functions.get(&ident.name).cloned(),
+                    _ => None,
+                };
+
+                match (callee, function) {
+                    (Type::Function(None), Some(function)) => {
+                        let note = format!(
+                            "the function is {}",
+                            function.describe(&function_name(&call.callee))
+                        );
+                        let params: Vec<(&str, Type, &ParamDefault)> = function
+                            .params
+                            .iter()
+                            .map(|(name, ty)| (name.as_str(), *ty, &ParamDefault::Required))
+                            .collect();
+
+                        self.call(call, &args, &params, note);
+                        function.returns
+                    }
+                    (Type::Function(Some(signature)), _) => {
+                        let note = format!(
+                            "the function is {}",
+                            signature.describe(&function_name(&call.callee))
+                        );
+                        let params: Vec<(&str, Type, &ParamDefault)> = signature
+                            .params
+                            .iter()
+                            .map(|x| (x.name, x.ty, &x.default))
+                            .collect();
+
+                        self.call(call, &args, &params, note);
+                        signature.returns
+                    }
+                    (Type::Function(None) | Type::Any, _) => Type::Any,
+                    (other, _) => self.error(MakerError::lang(
+                        format!("Cannot call a {}", other),
+                        call.location.clone(),
+                        MakerErrorType::TypeError,
+                    )),
+                }
+            }
+            Expression::Member(member) => {
+                let left = self.expression(&member.left);
+
+                match (left, left.members(), &*member.right) {
+                    // Only the members of builtins are known
+                    (Type::Object([]), _, _) | (Type::Any, _, _) => Type::Any,
+                    (_, Some(members), Expression::Identifier(key)) => {
+                        match members.iter().find(|x| x.name == key.name) {
+                            Some(found) => found.ty,
+                            None => self.error(MakerError::lang(
+                                format!("{} does not have key {}", describe(left), key.name),
+                                key.location.clone(),
+                                MakerErrorType::TypeError,
+                            )),
+                        }
+                    }
+                    (_, Some(_), _) => Type::Any,
+                    (other, None, _) => self.error(MakerError::lang(
+                        format!("Cannot index a {}", other),
+                        member.left.get_location(),
+                        MakerErrorType::TypeError,
+                    )),
+                }
+            }
+            Expression::Logical(logical) => {
+                self.expression(&logical.left);
+                self.expression(&logical.right);
+                Type::Boolean
+            }
+            // Unknown variables are reported by the semantic analysis
+            Expression::Identifier(ident) => self
+                .variables
+                .get(&ident.name)
+                .copied()
+                .unwrap_or(Type::Any),
+            Expression::Number(_) => Type::Number,
+            Expression::StringNode(_) => Type::String,
+            // The library is only known once the bot runs
+            Expression::Import(_) => Type::Object(&[]),
+        }
+    }
+}
+
+/// How a type is named at the start of an error, objects keep the wording the runtime uses
+fn describe(ty: Type) -> String {
+    match ty {
+        Type::Object(_) => "Object".to_string(),
+        ty => format!("A {}", ty),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lang::{builtins::BUILTINS, edition::Edition, lexer::lex, parser::Parser};
+
+    fn errors(code: &str) -> Vec<String> {
+        let tokens = lex(code, "test".to_string(), Edition::LATEST).unwrap();
+        let block = Parser::new(&tokens, Edition::LATEST).parse().unwrap();
+        check(&block, BUILTINS)
+            .into_iter()
+            .map(|x| x.message.clone())
+            .collect()
+    }
+
+    #[test]
+    fn annotations_are_checked() {
+        assert!(errors("var n: number = 1").is_empty());
+        assert_eq!(
+            errors("var n: number = \"one\""),
+            vec!["Expected number but found string"]
+        );
+    }
+
+    #[test]
+    fn declaring_over_a_builtin_gives_it_the_new_type() {
+        assert!(errors("var assert = 1\nvar n: number = assert").is_empty());
+        assert_eq!(errors("var n: number = assert").len(), 1);
+    }
+
+    #[test]
+    fn calls_to_functions_are_checked() {
+        let function = "fn greet(name: string) -> string do\n    name\nend\n";
+
+        assert!(errors(&format!("{}var s: string = greet(\"you\")", function)).is_empty());
+        assert_eq!(
+            errors(&format!("{}greet(1)", function)),
+            vec!["Invalid type for argument 0 (name), expected string but found number"]
+        );
+        assert_eq!(
+            errors(&format!("{}greet()", function)),
+            vec!["Missing argument 0 (name)"]
+        );
+        assert_eq!(
+            errors(&format!("{}var n: number = greet(\"you\")", function)),
+            vec!["Expected number but found string"]
+        );
+    }
+
+    #[test]
+    fn function_bodies_are_checked_against_their_annotations() {
+        assert_eq!(
+            errors("fn f(n: number) -> string do\n    n\nend"),
+            vec!["Expected string but found number"]
+        );
+        assert_eq!(errors("fn f(n: numbr) do\nend"), vec!["Unknown type numbr"]);
+    }
+
+    #[test]
+    fn what_functions_return_is_inferred() {
+        assert_eq!(
+            errors("fn one() do\n    1\nend\nvar s: string = one()"),
+            vec!["Expected string but found number"]
+        );
+        // Parameters hide variables of the same name, but only in the body
+        assert!(errors(
+            "var n = 1\nfn f(n: string) do\n    var s: string = n\nend\nvar m: number = n"
+        )
+        .is_empty());
+    }
+}
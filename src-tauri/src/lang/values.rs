@@ -1,54 +1,97 @@
 use std::{collections::HashMap, fmt, future::Future, pin::Pin, sync::Arc};
 
-use tokio::sync::Mutex;
+use chrono::{SecondsFormat, TimeDelta, Utc};
 
 use crate::errors::MakerError;
 
+use super::{
+    edition::Edition, lexer::Location, natives::Signature, nodes::FunctionDeclaration, scope::Scope,
+};
+
 #[derive(Debug, Clone)]
 pub enum RuntimeValue {
     Null(Null),
     StringValue(StringValue),
     Number(Number),
     NativeFunction(NativeFunction),
-    Future(MakerFuture),
+    Function(Function),
     Object(Object),
     Boolean(Boolean),
+    Snowflake(Snowflake),
+    DateTime(DateTime),
+    Duration(Duration),
 }
 
-unsafe impl Send for RuntimeValue {}
-unsafe impl Sync for RuntimeValue {}
-
 impl RuntimeValue {
     pub fn type_name(&self) -> &str {
         match self {
-            RuntimeValue::Future(_) => "future",
             RuntimeValue::NativeFunction(_) => "native_function",
+            RuntimeValue::Function(_) => "function",
             RuntimeValue::Null(_) => "null",
             RuntimeValue::Number(_) => "number",
             RuntimeValue::Object(_) => "object",
             RuntimeValue::StringValue(_) => "string",
             RuntimeValue::Boolean(_) => "boolean",
+            RuntimeValue::Snowflake(_) => "snowflake",
+            RuntimeValue::DateTime(_) => "datetime",
+            RuntimeValue::Duration(_) => "duration",
         }
     }
 
     pub fn is_truthy(&self) -> bool {
         match self {
             RuntimeValue::Number(v) => v.value > 0f64,
-            RuntimeValue::StringValue(v) => v.value.len() != 0,
+            RuntimeValue::StringValue(v) => !v.value.is_empty(),
             RuntimeValue::Boolean(v) => v.value,
             _ => false,
         }
     }
 
-    pub fn to_string(&self) -> String {
+    /// What `==` evaluates to
+    pub fn equals(&self, other: &RuntimeValue) -> bool {
+        // IDs used to be strings, so comparing them to one still works
+        if let (RuntimeValue::Snowflake(l), RuntimeValue::StringValue(r))
+        | (RuntimeValue::StringValue(r), RuntimeValue::Snowflake(l)) = (self, other)
+        {
+            return l.value.to_string() == r.value;
+        }
+
+        // Check if types are the same
+        if self.type_name() != other.type_name() {
+            return false;
+        }
+
+        match (self, other) {
+            (RuntimeValue::Boolean(l), RuntimeValue::Boolean(r)) => l.value == r.value,
+            (RuntimeValue::StringValue(l), RuntimeValue::StringValue(r)) => l.value == r.value,
+            (RuntimeValue::Number(l), RuntimeValue::Number(r)) => l.value == r.value,
+            (RuntimeValue::Snowflake(l), RuntimeValue::Snowflake(r)) => l.value == r.value,
+            (RuntimeValue::DateTime(l), RuntimeValue::DateTime(r)) => l.value == r.value,
+            (RuntimeValue::Duration(l), RuntimeValue::Duration(r)) => l.value == r.value,
+            (RuntimeValue::Null(_), _) => true,
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for RuntimeValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
-            RuntimeValue::Future(_) => "future".to_string(),
-            RuntimeValue::NativeFunction(_) => "function".to_string(),
-            RuntimeValue::Null(_) => "null".to_string(),
-            RuntimeValue::Number(v) => v.value.to_string(),
-            RuntimeValue::Object(_) => "object".to_string(),
-            RuntimeValue::StringValue(v) => v.value.to_string(),
-            RuntimeValue::Boolean(v) => v.value.to_string(),
+            RuntimeValue::NativeFunction(_) | RuntimeValue::Function(_) => write!(f, "function"),
+            RuntimeValue::Null(_) => write!(f, "null"),
+            RuntimeValue::Number(v) => write!(f, "{}", v.value),
+            RuntimeValue::Object(_) => write!(f, "object"),
+            RuntimeValue::StringValue(v) => write!(f, "{}", v.value),
+            RuntimeValue::Boolean(v) => write!(f, "{}", v.value),
+            RuntimeValue::Snowflake(v) => write!(f, "{}", v.value),
+            RuntimeValue::DateTime(v) => {
+                write!(
+                    f,
+                    "{}",
+                    v.value.to_rfc3339_opts(SecondsFormat::AutoSi, true)
+                )
+            }
+            RuntimeValue::Duration(v) => write!(f, "{}", Duration::describe(v.value)),
         }
     }
 }
@@ -106,12 +149,105 @@ impl Object {
     }
 }
 
+/// A Discord ID, which has when it was made in it
+#[derive(Debug, Clone)]
+pub struct Snowflake {
+    pub value: u64,
+}
+
+impl Snowflake {
+    /// The first millisecond of 2015, which Discord counts from
+    const DISCORD_EPOCH: i64 = 1_420_070_400_000;
+
+    pub fn make(value: u64) -> RuntimeValue {
+        RuntimeValue::Snowflake(Snowflake { value })
+    }
+
+    pub fn created_at(&self) -> chrono::DateTime<Utc> {
+        let millis = (self.value >> 22) as i64 + Self::DISCORD_EPOCH;
+        chrono::DateTime::from_timestamp_millis(millis).unwrap_or_default()
+    }
+}
+
+/// A point in time, always kept in UTC, time zones only matter when formatting
+#[derive(Debug, Clone)]
+pub struct DateTime {
+    pub value: chrono::DateTime<Utc>,
+}
+
+impl DateTime {
+    pub fn make(value: chrono::DateTime<Utc>) -> RuntimeValue {
+        RuntimeValue::DateTime(DateTime { value })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Duration {
+    pub value: TimeDelta,
+}
+
+impl Duration {
+    pub fn make(value: TimeDelta) -> RuntimeValue {
+        RuntimeValue::Duration(Duration { value })
+    }
+
+    /// e.g. `1d 2h 30m`, units which are zero are left out
+    pub fn describe(value: TimeDelta) -> String {
+        let sign = if value < TimeDelta::zero() { "-" } else { "" };
+        let value = value.abs();
+
+        let units = [
+            (value.num_days(), "d"),
+            (value.num_hours() % 24, "h"),
+            (value.num_minutes() % 60, "m"),
+            (value.num_seconds() % 60, "s"),
+        ];
+        let parts: Vec<String> = units
+            .iter()
+            .filter(|(amount, _)| *amount != 0)
+            .map(|(amount, unit)| format!("{}{}", amount, unit))
+            .collect();
+
+        if parts.is_empty() {
+            return format!("{}{}ms", sign, value.num_milliseconds());
+        }
+        format!("{}{}", sign, parts.join(" "))
+    }
+}
+
+/// What calling a native function gives, the interpreter awaits it straight away
+pub type NativeFuture = Pin<Box<dyn Future<Output = Result<RuntimeValue, MakerError>> + Send>>;
+
+/// Called with the arguments and where it was called from, if that is known
+pub type NativeFn = dyn Fn(Vec<RuntimeValue>, Option<&Location>) -> NativeFuture + Send + Sync;
+
 #[derive(Clone)]
 pub struct NativeFunction {
-    pub func: Arc<dyn Fn(Vec<RuntimeValue>) -> Result<RuntimeValue, MakerError>>,
+    pub func: Arc<NativeFn>,
+    /// Natives from a `NativeRegistry` have their arguments checked against this before being called
+    pub signature: Option<&'static Signature>,
 }
 
-unsafe impl Send for NativeFunction {}
+impl NativeFunction {
+    pub fn make<F, R>(func: F) -> RuntimeValue
+    where
+        F: Fn(Vec<RuntimeValue>) -> R + Send + Sync + 'static,
+        R: Future<Output = Result<RuntimeValue, MakerError>> + Send + 'static,
+    {
+        RuntimeValue::NativeFunction(NativeFunction {
+            func: Arc::new(move |args, _| Box::pin(func(args))),
+            signature: None,
+        })
+    }
+
+    /// A native function which does not need to await anything
+    pub fn make_sync<F>(func: F) -> RuntimeValue
+    where
+        F: Fn(Vec<RuntimeValue>) -> Result<RuntimeValue, MakerError> + Send + Sync + 'static,
+    {
+        Self::make(move |args| std::future::ready(func(args)))
+    }
+}
 
 impl fmt::Debug for NativeFunction {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
@@ -119,13 +255,49 @@ impl fmt::Debug for NativeFunction {
     }
 }
 
+/// A function declared with `fn`
 #[derive(Clone)]
-pub struct MakerFuture {
-    pub value: Arc<Mutex<Pin<Box<dyn Future<Output = Result<RuntimeValue, MakerError>> + Send>>>>,
+pub struct Function {
+    pub declaration: Arc<FunctionDeclaration>,
+    /// The variables which existed when it was declared, which the body runs with
+    pub scope: Arc<Scope>,
+    /// The edition of the code piece it was declared in, which may be a library
+    pub edition: Edition,
 }
 
-impl fmt::Debug for MakerFuture {
+impl Function {
+    pub fn make(declaration: FunctionDeclaration, scope: Scope, edition: Edition) -> RuntimeValue {
+        RuntimeValue::Function(Function {
+            declaration: Arc::new(declaration),
+            scope: Arc::new(scope),
+            edition,
+        })
+    }
+}
+
+impl fmt::Debug for Function {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "native_function")
+        write!(f, "function {}", self.declaration.name.name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn values_display_like_scripts_see_them() {
+        assert_eq!(Number::make(1.5).to_string(), "1.5");
+        assert_eq!(StringValue::make("hi".to_string()).to_string(), "hi");
+        assert_eq!(Boolean::make(false).to_string(), "false");
+        assert_eq!(Null::make().to_string(), "null");
+        assert_eq!(Snowflake::make(42).to_string(), "42");
+        assert_eq!(Object::make(HashMap::new()).to_string(), "object");
+    }
+
+    #[test]
+    fn only_non_empty_strings_are_truthy() {
+        assert!(StringValue::make("a".to_string()).is_truthy());
+        assert!(!StringValue::make(String::new()).is_truthy());
     }
 }
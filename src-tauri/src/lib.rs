@@ -0,0 +1,6 @@
+pub mod cli;
+pub mod errors;
+pub mod export;
+pub mod lang;
+#[cfg(feature = "lsp")]
+pub mod lsp;
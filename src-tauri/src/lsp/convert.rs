@@ -0,0 +1,130 @@
+use lsp_types::{Position, Range};
+
+use crate::lang::lexer::Location;
+
+/// Converts between our locations (bytes, characters) and LSP positions (UTF-16)
+pub struct LineIndex<'a> {
+    lines: Vec<&'a str>,
+}
+
+impl<'a> LineIndex<'a> {
+    pub fn new(source: &'a str) -> LineIndex<'a> {
+        LineIndex {
+            lines: source.split('\n').collect(),
+        }
+    }
+
+    /// The UTF-16 offset of the `column`th (0-based) character of a line
+    fn utf16_column(&self, line: usize, column: usize) -> u32 {
+        let text = self.lines.get(line).copied().unwrap_or("");
+        let mut chars = text.chars();
+        let mut result = 0;
+
+        for _ in 0..column {
+            result += chars.next().map(char::len_utf16).unwrap_or(1);
+        }
+
+        result as u32
+    }
+
+    pub fn position(&self, line: u32, column: u32) -> Position {
+        let line = line.saturating_sub(1) as usize;
+        Position {
+            line: line as u32,
+            character: self.utf16_column(line, column.saturating_sub(1) as usize),
+        }
+    }
+
+    pub fn range(&self, location: &Location) -> Range {
+        Range {
+            start: self.position(location.line, location.column),
+            end: self.position(location.end_line, location.end_column),
+        }
+    }
+
+    /// The byte offset into the source of an LSP position
+    pub fn offset(&self, position: Position) -> usize {
+        let line = position.line as usize;
+        let mut offset: usize = self.lines.iter().take(line).map(|x| x.len() + 1).sum();
+
+        let text = self.lines.get(line).copied().unwrap_or("");
+        let mut character = 0;
+        for c in text.chars() {
+            if character >= position.character {
+                break;
+            }
+            character += c.len_utf16() as u32;
+            offset += c.len_utf8();
+        }
+
+        offset
+    }
+
+    /// The length in UTF-16 of a location, cut off at the end of its first line
+    pub fn length(&self, location: &Location) -> u32 {
+        let start = self.position(location.line, location.column);
+        let end = if location.end_line == location.line {
+            self.position(location.end_line, location.end_column)
+        } else {
+            let line = location.line.saturating_sub(1) as usize;
+            let text = self.lines.get(line).copied().unwrap_or("");
+            Position {
+                line: start.line,
+                character: self.utf16_column(line, text.chars().count()),
+            }
+        };
+
+        end.character.saturating_sub(start.character)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lang::{edition::Edition, lexer::lex};
+
+    // `é` is two bytes but one UTF-16 unit, `😀` is four bytes and two units
+    const SOURCE: &str = "var é = \"😀\"\nx";
+
+    #[test]
+    fn columns_are_counted_in_utf16() {
+        let tokens = lex(SOURCE, "test".to_string(), Edition::LATEST).unwrap();
+        let index = LineIndex::new(SOURCE);
+        let string = &tokens[3].location;
+
+        assert_eq!(
+            index.range(string),
+            Range {
+                start: Position::new(0, 8),
+                end: Position::new(0, 12),
+            }
+        );
+        assert_eq!(index.length(string), 4);
+        assert_eq!(index.range(&tokens[4].location).start, Position::new(1, 0));
+    }
+
+    #[test]
+    fn positions_are_turned_back_into_byte_offsets() {
+        let index = LineIndex::new(SOURCE);
+
+        assert_eq!(index.offset(Position::new(0, 5)), 6);
+        assert_eq!(index.offset(Position::new(0, 11)), 14);
+        assert_eq!(&SOURCE[index.offset(Position::new(1, 0))..], "x");
+        // Past the end of a line is its end
+        assert_eq!(index.offset(Position::new(0, 100)), 15);
+    }
+
+    #[test]
+    fn lengths_stop_at_the_end_of_the_first_line() {
+        let index = LineIndex::new(SOURCE);
+        let location = Location {
+            line: 1,
+            column: 5,
+            end_line: 2,
+            end_column: 2,
+            ..Location::no_location()
+        };
+
+        assert_eq!(index.length(&location), 8);
+    }
+}
@@ -0,0 +1,743 @@
+mod convert;
+
+use std::{collections::HashMap, error::Error};
+
+use lsp_server::{Connection, ErrorCode, Message, Notification, Request, Response};
+use lsp_types::{
+    notification::{
+        DidChangeTextDocument, DidCloseTextDocument, DidOpenTextDocument,
+        Notification as NotificationTrait, PublishDiagnostics,
+    },
+    request::{
+        Completion, GotoDefinition, HoverRequest, Request as RequestTrait,
+        SemanticTokensFullRequest,
+    },
+    CompletionItem, CompletionItemKind, CompletionOptions, CompletionParams, CompletionResponse,
+    Diagnostic, DiagnosticRelatedInformation, DiagnosticSeverity, DidChangeTextDocumentParams,
+    DidCloseTextDocumentParams, DidOpenTextDocumentParams, Documentation, GotoDefinitionParams,
+    GotoDefinitionResponse, Hover, HoverContents, HoverParams, HoverProviderCapability,
+    InitializeParams, MarkupContent, MarkupKind, OneOf, PublishDiagnosticsParams, SemanticToken,
+    SemanticTokenType, SemanticTokens, SemanticTokensFullOptions, SemanticTokensLegend,
+    SemanticTokensOptions, SemanticTokensParams, SemanticTokensResult,
+    SemanticTokensServerCapabilities, ServerCapabilities, TextDocumentSyncCapability,
+    TextDocumentSyncKind, Url,
+};
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::{
+    errors::MakerError,
+    lang::{
+        builtins::{self, BUILTINS},
+        edition::Edition,
+        lexer::{lex, lex_with_comments, Location, Token, TokenType},
+        parser::Parser,
+        semantic::{self, Analysis},
+        types,
+    },
+};
+
+use convert::LineIndex;
+
+type Result<T> = std::result::Result<T, Box<dyn Error + Sync + Send>>;
+
+/// Each keyword, with the edition it became one in
+const KEYWORDS: &[(&str, Edition)] = &[
+    ("var", Edition::V1),
+    ("do", Edition::V1),
+    ("end", Edition::V1),
+    ("if", Edition::V1),
+    ("else", Edition::V1),
+    ("import", Edition::V2),
+    ("fn", Edition::V3),
+];
+
+const TOKEN_TYPES: &[SemanticTokenType] = &[
+    SemanticTokenType::KEYWORD,
+    SemanticTokenType::VARIABLE,
+    SemanticTokenType::STRING,
+    SemanticTokenType::NUMBER,
+    SemanticTokenType::OPERATOR,
+    SemanticTokenType::COMMENT,
+    SemanticTokenType::PROPERTY,
+    SemanticTokenType::TYPE,
+];
+
+pub fn capabilities() -> ServerCapabilities {
+    ServerCapabilities {
+        text_document_sync: Some(TextDocumentSyncCapability::Kind(TextDocumentSyncKind::FULL)),
+        completion_provider: Some(CompletionOptions {
+            trigger_characters: Some(vec![".".to_string()]),
+            ..Default::default()
+        }),
+        hover_provider: Some(HoverProviderCapability::Simple(true)),
+        definition_provider: Some(OneOf::Left(true)),
+        semantic_tokens_provider: Some(SemanticTokensServerCapabilities::SemanticTokensOptions(
+            SemanticTokensOptions {
+                legend: SemanticTokensLegend {
+                    token_types: TOKEN_TYPES.to_vec(),
+                    token_modifiers: vec![],
+                },
+                full: Some(SemanticTokensFullOptions::Bool(true)),
+                ..Default::default()
+            },
+        )),
+        ..Default::default()
+    }
+}
+
+/// What an editor can give as `initializationOptions`, e.g. `{ "edition": 1 }`
+#[derive(serde::Deserialize, Default)]
+#[serde(default)]
+struct Options {
+    /// The edition documents are written in, the latest if not given
+    edition: Option<u8>,
+}
+
+/// Runs the language server until the client shuts it down
+pub fn run(connection: Connection) -> Result<()> {
+    let params: InitializeParams =
+        serde_json::from_value(connection.initialize(serde_json::to_value(capabilities())?)?)?;
+    let options: Options = params
+        .initialization_options
+        .map(serde_json::from_value)
+        .transpose()?
+        .unwrap_or_default();
+
+    let edition = match options.edition {
+        Some(number) => {
+            Edition::from_number(number).ok_or_else(|| format!("There is no edition {}", number))?
+        }
+        None => Edition::LATEST,
+    };
+
+    let mut server = Server {
+        connection: &connection,
+        documents: HashMap::new(),
+        edition,
+    };
+    server.main_loop()
+}
+
+struct Server<'a> {
+    connection: &'a Connection,
+    documents: HashMap<Url, String>,
+    edition: Edition,
+}
+
+/// The result of lexing, parsing and analyzing a document
+struct Compiled<'a> {
+    tokens: Vec<Token<'a>>,
+    analysis: Analysis,
+    errors: Vec<MakerError>,
+}
+
+fn compile<'a>(uri: &Url, text: &'a str, edition: Edition) -> Compiled<'a> {
+    let globals: Vec<&str> = BUILTINS.iter().map(|x| x.name).collect();
+    let mut compiled = Compiled {
+        tokens: vec![],
+        analysis: Analysis::default(),
+        errors: vec![],
+    };
+
+    match lex(text, uri.to_string(), edition) {
+        Ok(tokens) => compiled.tokens = tokens,
+        Err(err) => {
+            compiled.errors.push(err);
+            return compiled;
+        }
+    }
+
+    match Parser::new(&compiled.tokens, edition).parse() {
+        Ok(block) => {
+            compiled.analysis = semantic::analyze(&block, &globals);
+            compiled.errors = compiled.analysis.errors.clone();
+            compiled.errors.extend(types::check(&block, BUILTINS));
+        }
+        Err(err) => compiled.errors.push(err),
+    }
+
+    compiled
+}
+
+impl<'a> Server<'a> {
+    fn main_loop(&mut self) -> Result<()> {
+        for message in &self.connection.receiver {
+            match message {
+                Message::Request(request) => {
+                    if self.connection.handle_shutdown(&request)? {
+                        return Ok(());
+                    }
+
+                    let response = self.handle_request(request);
+                    self.connection.sender.send(Message::Response(response))?;
+                }
+                Message::Notification(notification) => self.handle_notification(notification)?,
+                Message::Response(_) => {}
+            }
+        }
+
+        Ok(())
+    }
+
+    fn handle_request(&self, request: Request) -> Response {
+        match request.method.as_str() {
+            Completion::METHOD => self.respond(request, Self::completion),
+            HoverRequest::METHOD => self.respond(request, Self::hover),
+            GotoDefinition::METHOD => self.respond(request, Self::definition),
+            SemanticTokensFullRequest::METHOD => self.respond(request, Self::semantic_tokens),
+            _ => Response::new_err(
+                request.id,
+                ErrorCode::MethodNotFound as i32,
+                format!("Unknown request {}", request.method),
+            ),
+        }
+    }
+
+    fn respond<P: DeserializeOwned, R: Serialize>(
+        &self,
+        request: Request,
+        handler: fn(&Self, P) -> R,
+    ) -> Response {
+        match serde_json::from_value::<P>(request.params) {
+            Ok(params) => Response::new_ok(request.id, handler(self, params)),
+            Err(err) => {
+                Response::new_err(request.id, ErrorCode::InvalidParams as i32, err.to_string())
+            }
+        }
+    }
+
+    fn handle_notification(&mut self, notification: Notification) -> Result<()> {
+        match notification.method.as_str() {
+            DidOpenTextDocument::METHOD => {
+                let Some(params) = parse::<DidOpenTextDocumentParams>(notification) else {
+                    return Ok(());
+                };
+                let uri = params.text_document.uri;
+                self.documents
+                    .insert(uri.clone(), params.text_document.text);
+                self.publish_diagnostics(uri)?;
+            }
+            DidChangeTextDocument::METHOD => {
+                let Some(params) = parse::<DidChangeTextDocumentParams>(notification) else {
+                    return Ok(());
+                };
+                let uri = params.text_document.uri;
+
+                // Only full syncs are supported, so the last change is the whole document
+                if let Some(change) = params.content_changes.into_iter().last() {
+                    self.documents.insert(uri.clone(), change.text);
+                }
+                self.publish_diagnostics(uri)?;
+            }
+            DidCloseTextDocument::METHOD => {
+                let Some(params) = parse::<DidCloseTextDocumentParams>(notification) else {
+                    return Ok(());
+                };
+                self.documents.remove(&params.text_document.uri);
+                self.send_diagnostics(params.text_document.uri, vec![])?;
+            }
+            _ => {}
+        }
+
+        Ok(())
+    }
+
+    fn publish_diagnostics(&self, uri: Url) -> Result<()> {
+        let text = match self.documents.get(&uri) {
+            Some(text) => text,
+            None => return Ok(()),
+        };
+
+        let index = LineIndex::new(text);
+        let diagnostics = compile(&uri, text, self.edition)
+            .errors
+            .iter()
+            .map(|err| to_diagnostic(&uri, &index, err))
+            .collect();
+
+        self.send_diagnostics(uri, diagnostics)
+    }
+
+    fn send_diagnostics(&self, uri: Url, diagnostics: Vec<Diagnostic>) -> Result<()> {
+        let params = PublishDiagnosticsParams {
+            uri,
+            diagnostics,
+            version: None,
+        };
+
+        self.connection
+            .sender
+            .send(Message::Notification(Notification::new(
+                PublishDiagnostics::METHOD.to_string(),
+                params,
+            )))?;
+
+        Ok(())
+    }
+
+    fn completion(&self, params: CompletionParams) -> Option<CompletionResponse> {
+        let position = params.text_document_position;
+        let text = self.documents.get(&position.text_document.uri)?;
+        let offset = LineIndex::new(text).offset(position.position);
+
+        // Find what is being typed, and whether it comes after a .
+        let before = &text[..offset];
+        let rest = before.trim_end_matches(is_identifier_char);
+
+        if let Some(object) = rest.strip_suffix('.') {
+            let object_start = object.trim_end_matches(is_identifier_char).len();
+            let object = &object[object_start..];
+
+            let items = builtins::find(object)?
+                .members
+                .iter()
+                .map(|member| CompletionItem {
+                    label: member.name.to_string(),
+                    kind: Some(if matches!(member.ty, types::Type::Function(_)) {
+                        CompletionItemKind::METHOD
+                    } else {
+                        CompletionItemKind::FIELD
+                    }),
+                    detail: Some(member.detail(Some(object))),
+                    documentation: Some(Documentation::String(member.documentation.to_string())),
+                    ..Default::default()
+                })
+                .collect();
+
+            return Some(CompletionResponse::Array(items));
+        }
+
+        let mut items: Vec<CompletionItem> = KEYWORDS
+            .iter()
+            .filter(|(_, since)| *since <= self.edition)
+            .map(|(keyword, _)| CompletionItem {
+                label: keyword.to_string(),
+                kind: Some(CompletionItemKind::KEYWORD),
+                ..Default::default()
+            })
+            .collect();
+
+        for builtin in BUILTINS {
+            items.push(CompletionItem {
+                label: builtin.name.to_string(),
+                kind: Some(CompletionItemKind::VARIABLE),
+                detail: Some(builtin.detail(None)),
+                documentation: Some(Documentation::String(builtin.documentation.to_string())),
+                ..Default::default()
+            });
+        }
+
+        // Look at the tokens rather than the tree, as the code is usually
+        // half-written (and therefore unparseable) while completing
+        if let Ok(tokens) = lex(text, String::new(), self.edition) {
+            for pair in tokens.windows(2) {
+                if matches!(pair[0].token_type, TokenType::Var | TokenType::Fn)
+                    && matches!(pair[1].token_type, TokenType::Identifier)
+                    && pair[1].location.end < offset
+                    && !items.iter().any(|x| x.label == pair[1].value)
+                {
+                    items.push(CompletionItem {
+                        label: pair[1].value.to_string(),
+                        kind: Some(CompletionItemKind::VARIABLE),
+                        detail: Some(format!("{} {}", pair[0].value, pair[1].value)),
+                        ..Default::default()
+                    });
+                }
+            }
+        }
+
+        Some(CompletionResponse::Array(items))
+    }
+
+    fn hover(&self, params: HoverParams) -> Option<Hover> {
+        let position = params.text_document_position_params;
+        let uri = &position.text_document.uri;
+        let text = self.documents.get(uri)?;
+        let index = LineIndex::new(text);
+        let offset = index.offset(position.position);
+
+        let compiled = compile(uri, text, self.edition);
+        let tokens = &compiled.tokens;
+        let i = tokens.iter().position(|x| {
+            matches!(x.token_type, TokenType::Identifier)
+                && x.location.start <= offset
+                && offset <= x.location.end
+        })?;
+        let token = &tokens[i];
+
+        // Check whether it is object.member
+        let object = match i {
+            0 | 1 => None,
+            _ if matches!(tokens[i - 1].token_type, TokenType::Dot)
+                && matches!(tokens[i - 2].token_type, TokenType::Identifier) =>
+            {
+                Some(tokens[i - 2].value)
+            }
+            _ => None,
+        };
+
+        let value = match object {
+            Some(object) => {
+                let member = builtins::find_member(object, token.value)?;
+                format!(
+                    "```dbm\n{}\n```\n{}",
+                    member.detail(Some(object)),
+                    member.documentation
+                )
+            }
+            None => match builtins::find(token.value) {
+                Some(builtin) => {
+                    format!(
+                        "```dbm\n{}\n```\n{}",
+                        builtin.detail(None),
+                        builtin.documentation
+                    )
+                }
+                None => {
+                    let definition = compiled.analysis.definition_at(offset)?;
+                    format!("```dbm\nvar {}\n```", definition.name)
+                }
+            },
+        };
+
+        Some(Hover {
+            contents: HoverContents::Markup(MarkupContent {
+                kind: MarkupKind::Markdown,
+                value,
+            }),
+            range: Some(index.range(&token.location)),
+        })
+    }
+
+    fn definition(&self, params: GotoDefinitionParams) -> Option<GotoDefinitionResponse> {
+        let position = params.text_document_position_params;
+        let uri = position.text_document.uri;
+        let text = self.documents.get(&uri)?;
+        let index = LineIndex::new(text);
+
+        let compiled = compile(&uri, text, self.edition);
+        let definition = compiled
+            .analysis
+            .definition_at(index.offset(position.position))?;
+
+        Some(GotoDefinitionResponse::Scalar(lsp_types::Location {
+            uri: uri.clone(),
+            range: index.range(&definition.location),
+        }))
+    }
+
+    fn semantic_tokens(&self, params: SemanticTokensParams) -> Option<SemanticTokensResult> {
+        let text = self.documents.get(&params.text_document.uri)?;
+        let index = LineIndex::new(text);
+        let (tokens, comments) = lex_with_comments(text, String::new(), self.edition).ok()?;
+
+        // Work out the type of everything which should be highlighted
+        let mut highlights: Vec<(&Location, u32)> = vec![];
+        for (i, token) in tokens.iter().enumerate() {
+            let token_type = match token.token_type {
+                TokenType::Var
+                | TokenType::Do
+                | TokenType::End
+                | TokenType::If
+                | TokenType::Else
+                | TokenType::Import
+                | TokenType::Fn => 0,
+                TokenType::Identifier
+                    if i > 0 && matches!(tokens[i - 1].token_type, TokenType::Dot) =>
+                {
+                    6
+                }
+                TokenType::Identifier
+                    if i > 0
+                        && matches!(
+                            tokens[i - 1].token_type,
+                            TokenType::Colon | TokenType::Arrow
+                        ) =>
+                {
+                    7
+                }
+                TokenType::Identifier => 1,
+                TokenType::String => 2,
+                TokenType::Number => 3,
+                TokenType::Logical(_) | TokenType::Assign | TokenType::Arrow => 4,
+                _ => continue,
+            };
+            highlights.push((&token.location, token_type));
+        }
+        for comment in &comments {
+            highlights.push((&comment.location, 5));
+        }
+        highlights.sort_by_key(|x| x.0.start);
+
+        // Encode them relative to each other, as LSP wants
+        let mut data = vec![];
+        let mut previous = lsp_types::Position::default();
+        for (location, token_type) in highlights {
+            let start = index.position(location.line, location.column);
+            let delta_line = start.line - previous.line;
+            let delta_start = if delta_line == 0 {
+                start.character - previous.character
+            } else {
+                start.character
+            };
+
+            data.push(SemanticToken {
+                delta_line,
+                delta_start,
+                length: index.length(location),
+                token_type,
+                token_modifiers_bitset: 0,
+            });
+            previous = start;
+        }
+
+        Some(SemanticTokensResult::Tokens(SemanticTokens {
+            result_id: None,
+            data,
+        }))
+    }
+}
+
+/// Reads the params of a notification, a client sending bad ones is logged
+/// rather than stopping the server, as there is nothing to reply to
+fn parse<P: DeserializeOwned>(notification: Notification) -> Option<P> {
+    match serde_json::from_value(notification.params) {
+        Ok(params) => Some(params),
+        Err(err) => {
+            eprintln!("Invalid params for {}: {}", notification.method, err);
+            None
+        }
+    }
+}
+
+fn is_identifier_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+fn to_diagnostic(uri: &Url, index: &LineIndex, err: &MakerError) -> Diagnostic {
+    let mut message = err.message.clone();
+    for note in &err.notes {
+        message.push_str(&format!("\nnote: {}", note));
+    }
+
+    let related_information = err
+        .labels
+        .iter()
+        .map(|label| DiagnosticRelatedInformation {
+            location: lsp_types::Location {
+                uri: uri.clone(),
+                range: index.range(&label.location),
+            },
+            message: label.message.clone(),
+        })
+        .collect();
+
+    Diagnostic {
+        range: err
+            .location
+            .as_ref()
+            .map(|x| index.range(x))
+            .unwrap_or_default(),
+        severity: Some(DiagnosticSeverity::ERROR),
+        source: Some("dbm".to_string()),
+        message,
+        related_information: Some(related_information),
+        ..Default::default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use lsp_server::RequestId;
+    use lsp_types::{
+        notification::{Exit, Initialized},
+        request::{Initialize, Shutdown},
+        InitializeParams, InitializedParams, Position, TextDocumentIdentifier, TextDocumentItem,
+        TextDocumentPositionParams,
+    };
+    use serde_json::json;
+
+    use super::*;
+
+    /// Talks to a server running on another thread, like an editor would
+    struct Client {
+        connection: Connection,
+        server: Option<thread::JoinHandle<Result<()>>>,
+        next_id: i32,
+    }
+
+    impl Client {
+        fn start() -> Client {
+            Client::start_with(InitializeParams::default())
+        }
+
+        fn start_with(params: InitializeParams) -> Client {
+            let (server, connection) = Connection::memory();
+            let mut client = Client {
+                connection,
+                server: Some(thread::spawn(move || run(server))),
+                next_id: 0,
+            };
+
+            client.request::<Initialize>(params);
+            client.notify::<Initialized>(InitializedParams {});
+            client
+        }
+
+        fn request<R: RequestTrait>(&mut self, params: R::Params) -> serde_json::Value {
+            self.next_id += 1;
+            let id = RequestId::from(self.next_id);
+            self.send(Message::Request(Request::new(
+                id.clone(),
+                R::METHOD.to_string(),
+                params,
+            )));
+
+            match self.receive() {
+                Message::Response(response) if response.id == id => {
+                    response.result.unwrap_or_default()
+                }
+                message => panic!("expected a response to {}, got {:?}", R::METHOD, message),
+            }
+        }
+
+        fn notify<N: NotificationTrait>(&self, params: N::Params) {
+            self.send(Message::Notification(Notification::new(
+                N::METHOD.to_string(),
+                params,
+            )));
+        }
+
+        fn send(&self, message: Message) {
+            self.connection.sender.send(message).unwrap();
+        }
+
+        fn receive(&self) -> Message {
+            self.connection
+                .receiver
+                .recv_timeout(std::time::Duration::from_secs(5))
+                .expect("the server stopped replying")
+        }
+
+        fn diagnostics(&self) -> PublishDiagnosticsParams {
+            match self.receive() {
+                Message::Notification(notification)
+                    if notification.method == PublishDiagnostics::METHOD =>
+                {
+                    serde_json::from_value(notification.params).unwrap()
+                }
+                message => panic!("expected diagnostics, got {:?}", message),
+            }
+        }
+
+        fn open(&self, uri: &Url, text: &str) -> PublishDiagnosticsParams {
+            self.notify::<DidOpenTextDocument>(DidOpenTextDocumentParams {
+                text_document: TextDocumentItem {
+                    uri: uri.clone(),
+                    language_id: "dbm".to_string(),
+                    version: 0,
+                    text: text.to_string(),
+                },
+            });
+            self.diagnostics()
+        }
+
+        fn stop(mut self) {
+            self.request::<Shutdown>(());
+            self.notify::<Exit>(());
+            let server = self.server.take().unwrap();
+            assert!(server.join().unwrap().is_ok());
+        }
+    }
+
+    fn uri() -> Url {
+        Url::parse("file:///command.dbm").unwrap()
+    }
+
+    #[test]
+    fn diagnostics_are_published_for_open_documents() {
+        let client = Client::start();
+
+        let diagnostics = client.open(&uri(), "var x = 1\nprint(y)");
+        assert_eq!(diagnostics.uri, uri());
+        assert_eq!(diagnostics.diagnostics.len(), 1);
+        assert_eq!(diagnostics.diagnostics[0].range.start.line, 1);
+
+        assert!(client
+            .open(&uri(), "var x = 1\nprint(x)")
+            .diagnostics
+            .is_empty());
+        client.stop();
+    }
+
+    #[test]
+    fn bad_notifications_do_not_stop_the_server() {
+        let client = Client::start();
+
+        client.send(Message::Notification(Notification::new(
+            DidOpenTextDocument::METHOD.to_string(),
+            json!({ "textDocument": "not a document" }),
+        )));
+        client.send(Message::Notification(Notification::new(
+            DidChangeTextDocument::METHOD.to_string(),
+            json!(null),
+        )));
+
+        // The next thing the server sends is for the next (valid) notification
+        assert!(client.open(&uri(), "print(1)").diagnostics.is_empty());
+        client.stop();
+    }
+
+    #[test]
+    fn requests_are_answered() {
+        let mut client = Client::start();
+        client.open(&uri(), "var name = 1\nprint(name)");
+
+        let at = |line, character| TextDocumentPositionParams {
+            text_document: TextDocumentIdentifier { uri: uri() },
+            position: Position { line, character },
+        };
+
+        let hover = client.request::<HoverRequest>(HoverParams {
+            text_document_position_params: at(1, 1),
+            work_done_progress_params: Default::default(),
+        });
+        let hover: Hover = serde_json::from_value(hover).unwrap();
+        let HoverContents::Markup(contents) = hover.contents else {
+            panic!("expected markdown");
+        };
+        assert!(contents.value.contains("print"));
+
+        let definition = client.request::<GotoDefinition>(GotoDefinitionParams {
+            text_document_position_params: at(1, 7),
+            work_done_progress_params: Default::default(),
+            partial_result_params: Default::default(),
+        });
+        let Ok(GotoDefinitionResponse::Scalar(location)) = serde_json::from_value(definition)
+        else {
+            panic!("expected a single definition");
+        };
+        assert_eq!(location.range.start, Position::new(0, 4));
+
+        client.stop();
+    }
+
+    #[test]
+    fn documents_are_checked_in_the_configured_edition() {
+        // `fn` is only a keyword from edition 3
+        let source = "var fn = 1\nprint(fn)";
+
+        let client = Client::start();
+        assert!(!client.open(&uri(), source).diagnostics.is_empty());
+        client.stop();
+
+        let client = Client::start_with(InitializeParams {
+            initialization_options: Some(json!({ "edition": 1 })),
+            ..Default::default()
+        });
+        assert!(client.open(&uri(), source).diagnostics.is_empty());
+        client.stop();
+    }
+}
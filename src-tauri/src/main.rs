@@ -2,12 +2,11 @@
 
 mod database;
 mod discord;
-mod errors;
-mod lang;
 mod rs2js;
 mod tauri_commands;
 
-use discord::runner::BotRunner;
+use dbm::{errors, export, lang};
+use discord::{runner::BotRunner, symbols::Symbols};
 use log::{Level, LevelFilter, Metadata, Record};
 use rs2js::SenderType;
 use tauri::{generate_handler, Manager};
@@ -45,6 +44,16 @@ static LOGGER: SimpleLogger = SimpleLogger;
 
 #[tokio::main]
 async fn main() {
+    // Subcommands like `dbm run` don't need the app at all
+    let args: Vec<String> = std::env::args().collect();
+    // Unlike the others, `dbm test` needs the app's database
+    if args.get(1).is_some_and(|x| x == "test") {
+        std::process::exit(discord::testing::cli(args.get(2)).await);
+    }
+    if let Some(code) = dbm::cli::run(&args).await {
+        std::process::exit(code);
+    }
+
     log::set_logger(&LOGGER)
         .map(|()| log::set_max_level(LevelFilter::max()))
         .unwrap();
@@ -52,13 +61,17 @@ async fn main() {
     let (async_proc_input_tx, _async_proc_input_rx) = mpsc::channel::<SenderType>(1);
     let (async_proc_output_tx, mut async_proc_output_rx) = mpsc::channel::<SenderType>(1);
 
-    let database = Database::new().await;
+    let database = match Database::new().await {
+        Ok(database) => database,
+        Err(err) => panic!("Failed to open the database: {}", err),
+    };
     let bot_runner = BotRunner::new(async_proc_output_tx.clone());
 
-    let _ = tauri::Builder::default()
+    tauri::Builder::default()
         .manage(async_proc_input_tx)
         .manage(database)
         .manage(bot_runner)
+        .manage(Symbols::new())
         .invoke_handler(generate_handler![
             get_bot_by_id,
             create_bot,
@@ -69,7 +82,11 @@ async fn main() {
             get_all_commands,
             create_code_piece,
             set_code_piece,
+            set_code_piece_language,
             get_code_piece,
+            create_library,
+            get_libraries,
+            set_library_name,
             run_bot,
             stop_bot,
             set_bot_description,
@@ -77,7 +94,31 @@ async fn main() {
             set_bot_prefix,
             set_command_name,
             set_command_description,
-            set_bot_token
+            set_bot_token,
+            check_code,
+            get_plugins,
+            set_plugin_enabled,
+            export_bot,
+            format_code_piece,
+            code_to_ast,
+            ast_to_code,
+            migrate_code_piece,
+            start_debugging,
+            stop_debugging,
+            debug_resume,
+            start_profiling,
+            get_console,
+            clear_console,
+            stop_profiling,
+            get_profile,
+            export_profile,
+            get_command_tests,
+            create_command_test,
+            set_command_test,
+            delete_command_test,
+            run_command_tests,
+            find_references,
+            rename_symbol
         ])
         .setup(|app| {
             let app_handle = app.handle();
@@ -8,7 +8,14 @@ CREATE TABLE IF NOT EXISTS bots (
 
 CREATE TABLE IF NOT EXISTS code_pieces (
     id INTEGER UNIQUE PRIMARY KEY AUTOINCREMENT NOT NULL,
-    code TEXT NOT NULL DEFAULT ''
+    code TEXT NOT NULL DEFAULT '',
+    -- Only set for libraries, which are imported by name
+    name TEXT DEFAULT NULL,
+    bot_id INTEGER REFERENCES bots(id) DEFAULT NULL,
+    -- Code pieces from before editions existed are edition 1, new ones are set to the latest
+    edition INTEGER NOT NULL DEFAULT 1,
+    -- The language the code is written in, see discord::engines
+    language TEXT NOT NULL DEFAULT 'dbm'
 );
 
 CREATE TABLE IF NOT EXISTS commands (
@@ -19,6 +26,25 @@ CREATE TABLE IF NOT EXISTS commands (
     description TEXT NOT NULL DEFAULT 'A command'
 );
 
+-- Messages to run a command with and the replies it should give, see lang::testing
+CREATE TABLE IF NOT EXISTS command_tests (
+    id INTEGER UNIQUE PRIMARY KEY AUTOINCREMENT NOT NULL,
+    command_id INTEGER REFERENCES commands(id) NOT NULL,
+    name TEXT NOT NULL,
+    content TEXT NOT NULL DEFAULT '',
+    author TEXT NOT NULL DEFAULT 'user',
+    channel TEXT NOT NULL DEFAULT '0',
+    -- A JSON array of strings
+    expected_replies TEXT NOT NULL DEFAULT '[]'
+);
+
+-- The plugins each bot uses, by the name of their file in the plugins directory
+CREATE TABLE IF NOT EXISTS bot_plugins (
+    bot_id INTEGER REFERENCES bots(id) NOT NULL,
+    name TEXT NOT NULL,
+    UNIQUE (bot_id, name)
+);
+
 CREATE TABLE IF NOT EXISTS settings (
     current_bot INTEGER REFERENCES bots(id) DEFAULT NULL
 );
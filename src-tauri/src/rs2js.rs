@@ -1,4 +1,7 @@
+use crate::discord::console;
 use crate::errors::MakerError;
+use crate::lang::debugger::DebugSnapshot;
+use crate::lang::profiler::Profile;
 
 macro_rules! tx_error {
     ($tx:expr,$msg:expr,$type:ident) => {
@@ -17,6 +20,10 @@ pub(crate) use tx_error;
 pub enum SenderType {
     RunningBotsUpdate(RunningBotsUpdate),
     Error(Error),
+    DebugPaused(DebugPaused),
+    DebugFinished(DebugFinished),
+    ProfileUpdate(ProfileUpdate),
+    ConsoleEntry(ConsoleEntry),
 }
 
 impl SenderType {
@@ -24,6 +31,10 @@ impl SenderType {
         match self {
             SenderType::RunningBotsUpdate(_) => "running_bots_update",
             SenderType::Error(_) => "error",
+            SenderType::DebugPaused(_) => "debug_paused",
+            SenderType::DebugFinished(_) => "debug_finished",
+            SenderType::ProfileUpdate(_) => "profile_update",
+            SenderType::ConsoleEntry(_) => "console_entry",
         }
     }
 }
@@ -37,3 +48,28 @@ pub struct RunningBotsUpdate {
 pub struct Error {
     pub error: MakerError,
 }
+
+#[derive(serde::Serialize, Clone, Debug)]
+pub struct DebugPaused {
+    pub bot_id: u8,
+    pub code_id: u8,
+    pub snapshot: DebugSnapshot,
+}
+
+#[derive(serde::Serialize, Clone, Debug)]
+pub struct DebugFinished {
+    pub bot_id: u8,
+}
+
+#[derive(serde::Serialize, Clone, Debug)]
+pub struct ProfileUpdate {
+    pub bot_id: u8,
+    pub code_id: u8,
+    pub profile: Profile,
+}
+
+#[derive(serde::Serialize, Clone, Debug)]
+pub struct ConsoleEntry {
+    pub bot_id: u8,
+    pub entry: console::ConsoleEntry,
+}
@@ -1,9 +1,30 @@
+use std::{fs, path::PathBuf};
+
 use crate::{
     database::{
         database::Database,
-        structures::{Bot, CodePiece, Command, Settings},
+        structures::{Bot, CodePiece, Command, CommandTest, Settings},
+    },
+    discord::{
+        console::ConsoleEntry,
+        engines::engine_for,
+        plugins::{self, PluginInfo},
+        runner::{BotRunner, RunnerContext},
+        symbols::Symbols,
+        testing::{self, CommandTestResults},
+    },
+    export::{self, ExportedBot, ExportedScript},
+    lang::{
+        ast::SyntaxTree,
+        debugger::DebugCommand,
+        diagnostics::Diagnostic,
+        edition::Edition,
+        formatter,
+        migrate::{migrate, Migration},
+        profiler::Profile,
+        symbols::{CodePieceEdits, References},
+        testing::TestCase,
     },
-    discord::runner::{BotRunner, RunnerContext},
 };
 
 macro_rules! unwrap {
@@ -100,6 +121,99 @@ pub async fn stop_bot(id: u8, bot_runner: tauri::State<'_, BotRunner>) -> Result
     Ok(())
 }
 
+#[tauri::command]
+pub async fn start_debugging(
+    id: u8,
+    code_id: u8,
+    breakpoints: Vec<u32>,
+    pause_on_start: bool,
+    bot_runner: tauri::State<'_, BotRunner>,
+) -> Result<(), String> {
+    bot_runner
+        .start_debugging(id, code_id, breakpoints, pause_on_start)
+        .await;
+
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn stop_debugging(id: u8, bot_runner: tauri::State<'_, BotRunner>) -> Result<(), String> {
+    bot_runner.stop_debugging(id).await;
+
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn debug_resume(
+    id: u8,
+    command: DebugCommand,
+    bot_runner: tauri::State<'_, BotRunner>,
+) -> Result<(), String> {
+    unwrap!(bot_runner.debug_resume(id, command).await)
+}
+
+#[tauri::command]
+pub async fn start_profiling(
+    id: u8,
+    bot_runner: tauri::State<'_, BotRunner>,
+) -> Result<(), String> {
+    bot_runner.start_profiling(id).await;
+
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn stop_profiling(id: u8, bot_runner: tauri::State<'_, BotRunner>) -> Result<(), String> {
+    bot_runner.stop_profiling(id).await;
+
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn get_profile(
+    id: u8,
+    code_id: u8,
+    bot_runner: tauri::State<'_, BotRunner>,
+) -> Result<Option<Profile>, String> {
+    Ok(bot_runner.get_profile(id, code_id).await)
+}
+
+/// What the bot's scripts have printed, oldest first
+#[tauri::command]
+pub async fn get_console(
+    id: u8,
+    bot_runner: tauri::State<'_, BotRunner>,
+) -> Result<Vec<ConsoleEntry>, String> {
+    Ok(bot_runner.get_console(id).await)
+}
+
+#[tauri::command]
+pub async fn clear_console(id: u8, bot_runner: tauri::State<'_, BotRunner>) -> Result<(), String> {
+    bot_runner.clear_console(id).await;
+    Ok(())
+}
+
+/// Writes the profile as a flamegraph in the folded stack format, returning where it was written
+#[tauri::command]
+pub async fn export_profile(
+    id: u8,
+    code_id: u8,
+    bot_runner: tauri::State<'_, BotRunner>,
+) -> Result<String, String> {
+    let profile = match bot_runner.get_profile(id, code_id).await {
+        Some(profile) => profile,
+        None => return Err(format!("Code piece {} has not been profiled", code_id)),
+    };
+
+    // Next to the database
+    let mut path = PathBuf::from("../profiles");
+    unwrap!(fs::create_dir_all(&path))?;
+    path.push(format!("bot_{}_code_piece_{}.folded", id, code_id));
+    unwrap!(fs::write(&path, profile.to_folded()))?;
+
+    Ok(unwrap!(path.canonicalize())?.display().to_string())
+}
+
 // ----- Command Commands -----
 
 #[tauri::command]
@@ -146,8 +260,54 @@ pub async fn set_code_piece(
     id: u8,
     code: &str,
     db: tauri::State<'_, Database>,
+    symbols: tauri::State<'_, Symbols>,
+) -> Result<CodePiece, String> {
+    let code_piece = unwrap!(db.inner().code_pieces.set(id, code).await)?;
+    symbols.update(&code_piece).await;
+    Ok(code_piece)
+}
+
+/// Changes the language a code piece is written in, see `discord::engines`
+#[tauri::command]
+pub async fn set_code_piece_language(
+    id: u8,
+    language: &str,
+    db: tauri::State<'_, Database>,
+    symbols: tauri::State<'_, Symbols>,
 ) -> Result<CodePiece, String> {
-    unwrap!(db.inner().code_pieces.set(id, code).await)
+    unwrap!(engine_for(language))?;
+    let code_piece = unwrap!(db.inner().code_pieces.set_language(id, language).await)?;
+    symbols.update(&code_piece).await;
+    Ok(code_piece)
+}
+
+#[tauri::command]
+pub async fn create_library(
+    bot_id: u8,
+    name: &str,
+    db: tauri::State<'_, Database>,
+) -> Result<CodePiece, String> {
+    unwrap!(db.inner().code_pieces.create_library(bot_id, name).await)
+}
+
+#[tauri::command]
+pub async fn get_libraries(
+    bot_id: u8,
+    db: tauri::State<'_, Database>,
+) -> Result<Vec<CodePiece>, String> {
+    unwrap!(db.inner().code_pieces.get_libraries(bot_id).await)
+}
+
+#[tauri::command]
+pub async fn set_library_name(
+    id: u8,
+    name: &str,
+    db: tauri::State<'_, Database>,
+    symbols: tauri::State<'_, Symbols>,
+) -> Result<CodePiece, String> {
+    let code_piece = unwrap!(db.inner().code_pieces.set_name(id, name).await)?;
+    symbols.update(&code_piece).await;
+    Ok(code_piece)
 }
 
 #[tauri::command]
@@ -168,6 +328,242 @@ pub async fn set_command_description(
     unwrap!(db.inner().commands.set_description(id, description).await)
 }
 
+// ----- Test Commands -----
+
+#[tauri::command]
+pub async fn get_command_tests(
+    command_id: u8,
+    db: tauri::State<'_, Database>,
+) -> Result<Vec<CommandTest>, String> {
+    unwrap!(db.inner().tests.get_all(command_id).await)
+}
+
+#[tauri::command]
+pub async fn create_command_test(
+    command_id: u8,
+    name: &str,
+    db: tauri::State<'_, Database>,
+) -> Result<CommandTest, String> {
+    unwrap!(db.inner().tests.create(command_id, name).await)
+}
+
+#[tauri::command]
+pub async fn set_command_test(
+    id: u8,
+    test: TestCase,
+    db: tauri::State<'_, Database>,
+) -> Result<CommandTest, String> {
+    unwrap!(db.inner().tests.set(id, &test).await)
+}
+
+#[tauri::command]
+pub async fn delete_command_test(id: u8, db: tauri::State<'_, Database>) -> Result<(), String> {
+    unwrap!(db.inner().tests.delete(id).await)?;
+    Ok(())
+}
+
+/// Runs the tests of every command of the bot, or only those of `command_id`
+#[tauri::command]
+pub async fn run_command_tests(
+    bot_id: u8,
+    command_id: Option<u8>,
+    db: tauri::State<'_, Database>,
+) -> Result<Vec<CommandTestResults>, String> {
+    unwrap!(testing::run_tests(db.inner(), bot_id, command_id).await)
+}
+
+// ----- Language Commands -----
+
+fn get_edition(edition: u8) -> Result<Edition, String> {
+    Edition::from_number(edition).ok_or_else(|| format!("Unknown edition {}", edition))
+}
+
+#[tauri::command]
+pub async fn check_code(
+    code: &str,
+    edition: u8,
+    language: &str,
+) -> Result<Vec<Diagnostic>, String> {
+    let edition = get_edition(edition)?;
+    let errors = unwrap!(engine_for(language))?.check(code, edition);
+
+    Ok(errors
+        .iter()
+        .map(|err| Diagnostic::from_error(err, code))
+        .collect())
+}
+
+/// The syntax tree of a code piece as JSON, for the block editor
+#[tauri::command]
+pub async fn code_to_ast(code: &str, edition: u8) -> Result<SyntaxTree, String> {
+    unwrap!(SyntaxTree::parse(code, get_edition(edition)?))
+}
+
+/// Turns a tree from `code_to_ast` back into source code
+#[tauri::command]
+pub async fn ast_to_code(ast: SyntaxTree) -> Result<String, String> {
+    Ok(ast.print())
+}
+
+#[tauri::command]
+pub async fn format_code_piece(code: &str, edition: u8) -> Result<String, String> {
+    unwrap!(formatter::format(code, get_edition(edition)?))
+}
+
+/// Rewrites a code piece to the latest edition, returning what was changed
+#[tauri::command]
+pub async fn migrate_code_piece(
+    id: u8,
+    db: tauri::State<'_, Database>,
+    symbols: tauri::State<'_, Symbols>,
+) -> Result<Migration, String> {
+    let code_piece = unwrap!(db.inner().code_pieces.get(id).await)?;
+    let migration = unwrap!(migrate(&code_piece.code, get_edition(code_piece.edition)?))?;
+
+    let code_piece = unwrap!(
+        db.inner()
+            .code_pieces
+            .set_with_edition(id, &migration.source, Edition::LATEST)
+            .await
+    )?;
+    symbols.update(&code_piece).await;
+    Ok(migration)
+}
+
+// ----- Symbol Commands -----
+
+/// Everywhere the variable at `line`:`column` of a code piece is used, across the bot's code pieces
+#[tauri::command]
+pub async fn find_references(
+    bot_id: u8,
+    code_piece_id: u8,
+    line: u32,
+    column: u32,
+    db: tauri::State<'_, Database>,
+    symbols: tauri::State<'_, Symbols>,
+) -> Result<Vec<References>, String> {
+    let references = unwrap!(
+        symbols
+            .with_index(db.inner(), bot_id, |index| {
+                index.find_references(code_piece_id, line, column)
+            })
+            .await
+    )?;
+    unwrap!(references)
+}
+
+/// The edits which rename the variable at `line`:`column` of a code piece, they are not applied
+#[tauri::command]
+pub async fn rename_symbol(
+    bot_id: u8,
+    code_piece_id: u8,
+    line: u32,
+    column: u32,
+    new_name: &str,
+    db: tauri::State<'_, Database>,
+    symbols: tauri::State<'_, Symbols>,
+) -> Result<Vec<CodePieceEdits>, String> {
+    // Plugins are declared as globals too
+    let plugins: Vec<String> = unwrap!(db.inner().plugins.get_enabled(bot_id).await)?
+        .into_iter()
+        .map(|x| x.name)
+        .collect();
+    let globals: Vec<&str> = plugins.iter().map(|x| x.as_str()).collect();
+
+    let edits = unwrap!(
+        symbols
+            .with_index(db.inner(), bot_id, |index| {
+                index.rename(code_piece_id, line, column, new_name, &globals)
+            })
+            .await
+    )?;
+    unwrap!(edits)
+}
+
+// ----- Plugin Commands -----
+
+/// Every plugin in the plugins directory, and whether the bot uses it
+#[tauri::command]
+pub async fn get_plugins(
+    bot_id: u8,
+    db: tauri::State<'_, Database>,
+) -> Result<Vec<PluginInfo>, String> {
+    let enabled: Vec<String> = unwrap!(db.inner().plugins.get_enabled(bot_id).await)?
+        .into_iter()
+        .map(|x| x.name)
+        .collect();
+    Ok(plugins::list(&enabled))
+}
+
+/// Takes effect the next time the bot is started
+#[tauri::command]
+pub async fn set_plugin_enabled(
+    bot_id: u8,
+    name: &str,
+    enabled: bool,
+    db: tauri::State<'_, Database>,
+) -> Result<Vec<PluginInfo>, String> {
+    if enabled {
+        unwrap!(db.inner().plugins.enable(bot_id, name).await)?;
+    } else {
+        unwrap!(db.inner().plugins.disable(bot_id, name).await)?;
+    }
+    get_plugins(bot_id, db).await
+}
+
+// ----- Export Commands -----
+
+/// Writes the bot as a standalone Rust crate, giving the directory it is in
+#[tauri::command]
+pub async fn export_bot(id: u8, db: tauri::State<'_, Database>) -> Result<String, String> {
+    let database = db.inner();
+    let bot = unwrap!(database.bots.get(id).await)?;
+    let commands = unwrap!(database.commands.get_all(id).await)?;
+    let code_pieces = unwrap!(database.code_pieces.get_all(id).await)?;
+    let libraries = unwrap!(database.code_pieces.get_libraries(id).await)?;
+
+    let mut exported = vec![];
+    for command in commands {
+        let code_piece = match code_pieces.iter().find(|x| x.id == command.code_id) {
+            Some(code_piece) => code_piece,
+            None => continue,
+        };
+        exported.push(ExportedScript {
+            name: command.name,
+            code: code_piece.code.clone(),
+            edition: code_piece.edition,
+            language: code_piece.language.clone(),
+        });
+    }
+
+    let bot = ExportedBot {
+        prefix: bot.prefix,
+        commands: exported,
+        libraries: libraries
+            .into_iter()
+            .filter_map(|x| {
+                Some(ExportedScript {
+                    name: x.name?,
+                    code: x.code,
+                    edition: x.edition,
+                    language: x.language,
+                })
+            })
+            .collect(),
+        name: bot.name,
+    };
+
+    // The name could contain anything, including `..`
+    let directory_name: String = bot
+        .name
+        .chars()
+        .map(|x| if x.is_alphanumeric() { x } else { '_' })
+        .collect();
+    let directory = PathBuf::from(export::EXPORTS_DIRECTORY).join(directory_name);
+    unwrap!(export::write(&bot, &directory))?;
+    unwrap!(fs::canonicalize(&directory).map(|x| x.display().to_string()))
+}
+
 // ----- Settings Commands -----
 
 #[tauri::command]
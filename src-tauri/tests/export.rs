@@ -0,0 +1,318 @@
+//! Builds and runs exported bots
+//!
+//! Building one as it is needs serenity and its dependencies vendored, e.g. with
+//! `cargo vendor` in a previously exported bot:
+//!
+//! `DBM_VENDOR_DIR=/path/to/vendor cargo test --test export -- --ignored`
+//!
+//! The other tests build the generated scripts against a small stand-in for serenity
+//! instead, so they run anywhere and can check what the commands reply
+
+use std::{fs, path::PathBuf, process::Command};
+
+use dbm::export::{self, ExportedBot, ExportedScript};
+
+fn script(name: &str, code: &str) -> ExportedScript {
+    ExportedScript {
+        name: name.to_string(),
+        code: code.to_string(),
+        edition: 2,
+        language: "dbm".to_string(),
+    }
+}
+
+/// What the generated scripts use from serenity, replies are collected instead of sent
+const SERENITY_STUB: &str = r#"
+pub mod http {
+    pub struct Http;
+}
+
+pub mod model {
+    pub mod prelude {
+        pub struct User {
+            pub id: u64,
+            pub name: String,
+        }
+
+        pub struct Message {
+            pub id: u64,
+            pub content: String,
+            pub author: User,
+            pub channel_id: u64,
+        }
+
+        impl Message {
+            pub async fn reply(&self, _: &crate::http::Http, content: String) -> Result<(), String> {
+                crate::REPLIES.lock().unwrap().push(content);
+                Ok(())
+            }
+        }
+    }
+}
+
+pub mod prelude {
+    pub struct Context {
+        pub http: crate::http::Http,
+    }
+}
+
+pub static REPLIES: std::sync::Mutex<Vec<String>> = std::sync::Mutex::new(Vec::new());
+"#;
+
+/// Exports `bot` next to the stand-in for serenity, with a `main` which runs each of
+/// `runs` (the function of a command and the message content) and prints what it did
+fn run_exported(name: &str, bot: &ExportedBot, runs: &[(&str, &str)]) -> Vec<String> {
+    let directory = std::env::temp_dir().join(format!("dbm-export-{}", name));
+    let _ = fs::remove_dir_all(&directory);
+    let stub = directory.join("serenity");
+    let crate_directory = directory.join("bot");
+
+    fs::create_dir_all(stub.join("src")).unwrap();
+    fs::write(
+        stub.join("Cargo.toml"),
+        "[package]\nname = \"serenity\"\nversion = \"0.0.0\"\nedition = \"2021\"\n",
+    )
+    .unwrap();
+    fs::write(stub.join("src/lib.rs"), SERENITY_STUB).unwrap();
+
+    // Everything but the real `main`, which needs a Discord client
+    fs::create_dir_all(crate_directory.join("src")).unwrap();
+    for (path, contents) in export::generate(bot).unwrap() {
+        if path.ends_with("runtime.rs")
+            || path.ends_with("commands.rs")
+            || path.ends_with("libraries.rs")
+        {
+            fs::write(crate_directory.join(path), contents).unwrap();
+        }
+    }
+    fs::write(
+        crate_directory.join("Cargo.toml"),
+        "[package]\nname = \"bot\"\nversion = \"0.1.0\"\nedition = \"2021\"\n\n[dependencies]\nserenity = { path = \"../serenity\" }\n",
+    )
+    .unwrap();
+
+    let calls: String = runs
+        .iter()
+        .map(|(function, content)| {
+            format!(
+                "    let result = block_on(commands::{}(&ctx, &message({:?})));\n    println!(\"{{:?}} {{:?}}\", result, serenity::REPLIES.lock().unwrap().drain(..).collect::<Vec<_>>());\n",
+                function, content
+            )
+        })
+        .collect();
+    fs::write(
+        crate_directory.join("src/main.rs"),
+        format!(
+            r#"mod commands;
+mod libraries;
+mod runtime;
+
+use std::{{future::Future, pin::pin, task}};
+
+use serenity::{{http::Http, model::prelude::*, prelude::*}};
+
+/// The stand-in never waits, so every future is ready the first time
+fn block_on<F: Future>(future: F) -> F::Output {{
+    let mut context = task::Context::from_waker(task::Waker::noop());
+    match pin!(future).poll(&mut context) {{
+        task::Poll::Ready(output) => output,
+        task::Poll::Pending => panic!("the stand-in for serenity never waits"),
+    }}
+}}
+
+fn message(content: &str) -> Message {{
+    Message {{
+        id: 1,
+        content: content.to_string(),
+        author: User {{ id: 2, name: "someone".to_string() }},
+        channel_id: 3,
+    }}
+}}
+
+fn main() {{
+    let ctx = Context {{ http: Http }};
+{}}}
+"#,
+            calls
+        ),
+    )
+    .unwrap();
+
+    let output = Command::new(env!("CARGO"))
+        .args(["run", "--quiet", "--offline"])
+        .current_dir(&crate_directory)
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "the exported bot in {} failed to build:\n{}",
+        crate_directory.display(),
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    String::from_utf8(output.stdout)
+        .unwrap()
+        .lines()
+        .map(|x| x.to_string())
+        .collect()
+}
+
+#[test]
+fn exported_commands_reply_like_they_do_in_the_app() {
+    let mut old = script(
+        "old",
+        "if message.content != \"!old\" do\n    message.reply(\"same\")\nend",
+    );
+    old.edition = 1;
+
+    let bot = ExportedBot {
+        name: "Replies".to_string(),
+        prefix: "!".to_string(),
+        commands: vec![
+            script(
+                "ping",
+                "var reply: string = \"pong\"\nif message.content == \"!ping\" do\n    message.reply(reply)\nend else do\n    message.reply(\"?\")\nend",
+            ),
+            script(
+                "greet",
+                "var utils = import \"utils\"\nassert_eq(message.channel, \"3\", \"wrong channel\")\nmessage.reply(utils.greeting)\nmessage.reply(message.author)",
+            ),
+            script(
+                "check",
+                "assert(message.content == \"!check ok\", \"not ok\")\nmessage.reply(\"ok\")",
+            ),
+            old,
+        ],
+        libraries: vec![script(
+            "utils",
+            "var greeting = \"hello\"\nassert(greeting == \"hello\")",
+        )],
+    };
+
+    let output = run_exported(
+        "replies",
+        &bot,
+        &[
+            ("script_ping", "!ping"),
+            ("script_ping", "!ping me"),
+            ("script_greet", "!greet"),
+            ("script_check", "!check ok"),
+            ("script_check", "!check"),
+            // `!=` still compares for equality in edition 1
+            ("script_old", "!old"),
+        ],
+    );
+
+    assert_eq!(
+        output,
+        vec![
+            "Ok(Null) [\"pong\"]",
+            "Ok(Null) [\"?\"]",
+            "Ok(Null) [\"hello\", \"someone\"]",
+            "Ok(Null) [\"ok\"]",
+            "Err(\"Assertion failed: not ok\") []",
+            "Ok(Null) [\"same\"]",
+        ]
+    );
+}
+
+#[test]
+fn commands_are_dispatched_by_name() {
+    let bot = ExportedBot {
+        name: "1 Bot!".to_string(),
+        prefix: "?".to_string(),
+        commands: vec![
+            script("Hi", "message.reply(1)"),
+            script("hi", "message.reply(2)"),
+            script("hi there", "message.reply(3)"),
+        ],
+        libraries: vec![],
+    };
+    let files = export::generate(&bot).unwrap();
+    let file = |name: &str| {
+        files
+            .iter()
+            .find(|(path, _)| path.ends_with(name))
+            .map(|(_, contents)| contents.as_str())
+            .unwrap()
+    };
+
+    assert!(file("Cargo.toml").contains("name = \"bot-1-bot\""));
+    assert!(file("main.rs").contains("const PREFIX: &str = \"?\";"));
+    assert!(file("main.rs").contains("\"hi\" => commands::script_hi(&ctx, &msg).await"));
+    assert!(file("main.rs").contains("\"hi there\" => commands::script_hi_there(&ctx, &msg).await"));
+    // Like the app, only the first command with a name ever runs
+    assert!(!file("commands.rs").contains("Value::Number(2.0)"));
+}
+
+#[test]
+fn scripts_which_can_not_be_exported_are_refused() {
+    let mut rhai = script("rhai", "message.reply(\"hi\");");
+    rhai.language = "rhai".to_string();
+    let mut future = script("future", "");
+    future.edition = 100;
+
+    for (command, error) in [
+        (rhai, "rhai is written in rhai, only dbm can be exported"),
+        (future, "future uses unknown edition 100"),
+    ] {
+        let bot = ExportedBot {
+            name: "Refused".to_string(),
+            prefix: "!".to_string(),
+            commands: vec![command],
+            libraries: vec![],
+        };
+
+        assert_eq!(export::generate(&bot).unwrap_err().message, error);
+    }
+}
+
+#[test]
+#[ignore = "needs vendored dependencies, see the top of the file"]
+fn exported_bot_compiles_offline() {
+    let vendor = PathBuf::from(std::env::var("DBM_VENDOR_DIR").expect("DBM_VENDOR_DIR is not set"));
+    let directory = std::env::temp_dir().join("dbm-export-test");
+    let _ = fs::remove_dir_all(&directory);
+
+    let bot = ExportedBot {
+        name: "Export Test".to_string(),
+        prefix: "!".to_string(),
+        commands: vec![
+            script(
+                "ping",
+                "var reply: string = \"pong\"\nif message.content == \"!ping\" do\n    message.reply(reply)\nend else do\n    message.reply(\"?\")\nend",
+            ),
+            script(
+                "greet",
+                "var utils = import \"utils\"\nassert_eq(message.channel, \"0\", \"wrong channel\")\nmessage.reply(utils.greeting)",
+            ),
+            script("empty", ""),
+        ],
+        libraries: vec![script(
+            "utils",
+            "var greeting = \"hello\"\nassert(greeting == \"hello\")",
+        )],
+    };
+    export::write(&bot, &directory).unwrap();
+
+    fs::create_dir_all(directory.join(".cargo")).unwrap();
+    fs::write(
+        directory.join(".cargo/config.toml"),
+        format!(
+            "[source.crates-io]\nreplace-with = \"vendored-sources\"\n\n[source.vendored-sources]\ndirectory = {:?}\n",
+            vendor.display().to_string()
+        ),
+    )
+    .unwrap();
+
+    let status = Command::new(env!("CARGO"))
+        .args(["build", "--offline"])
+        .current_dir(&directory)
+        .status()
+        .unwrap();
+    assert!(
+        status.success(),
+        "the exported bot in {} failed to build",
+        directory.display()
+    );
+}
@@ -0,0 +1,51 @@
+//! Large code pieces used to take quadratic time to check, as the lexer and parser
+//! removed what they had read from the front of a vector
+
+use std::time::{Duration, Instant};
+
+use dbm::lang::{edition::Edition, engine::Engine, formatter, lexer::lex, parser::Parser};
+
+fn generate(lines: usize) -> String {
+    (0..lines / 2)
+        .map(|i| format!("var value{i}: string = \"value {i}\"\nif value{i} == \"x\" do\n    var copy{i} = value{i}\nend\n"))
+        .collect()
+}
+
+/// The fastest of a few runs, so that a busy machine does not fail the test
+fn time(code: &str) -> Duration {
+    (0..3)
+        .map(|_| {
+            let started = Instant::now();
+            let tokens = lex(code, "large".to_string(), Edition::LATEST).unwrap();
+            Parser::new(&tokens, Edition::LATEST).parse().unwrap();
+            started.elapsed()
+        })
+        .min()
+        .unwrap()
+}
+
+#[test]
+fn large_code_pieces_are_checked_in_linear_time() {
+    let small = time(&generate(2_000));
+    let large = time(&generate(16_000));
+
+    // 8 times the code, which would take 64 times as long if it was quadratic
+    assert!(
+        large < small * 24,
+        "2,000 lines took {:?} but 16,000 took {:?}",
+        small,
+        large
+    );
+}
+
+#[test]
+fn large_code_pieces_compile_and_format() {
+    let code = generate(20_000);
+
+    Engine::new().compile(&code).unwrap();
+    let formatted = formatter::format(&code, Edition::LATEST).unwrap();
+    assert_eq!(
+        formatter::format(&formatted, Edition::LATEST).unwrap(),
+        formatted
+    );
+}
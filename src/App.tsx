@@ -19,6 +19,7 @@ import { TauriEvents } from './types/tauri';
 import ModelManager, { pushModel } from './components/models/ModelManager';
 import { errorMessage, messageModel } from './components/models/modelTemplates';
 import BotSettings from './pages/BotSettings';
+import ConsolePage from './pages/Console';
 import Button from './components/Button';
 
 export let save: (() => void) | null = null;
@@ -47,7 +48,14 @@ function App() {
             });
 
             listen<TauriEvents["error"]>("error", data => {
-                errorMessage(data.payload.error.message);
+                let error = data.payload.error;
+                errorMessage(<>
+                    {error.message}
+                    {error.stack.map(frame => <>
+                        <br />
+                        <small>in {frame.function}, called from line {frame.location.line}</small>
+                    </>)}
+                </>);
             })
         })();
     }, []);
@@ -102,7 +110,8 @@ function App() {
                             "home": <CreateBot />,
                             "bots": <BotList />,
                             "commands": <CommandsPage />,
-                            "bot-settings": <BotSettings />
+                            "bot-settings": <BotSettings />,
+                            "console": <ConsolePage />
                         }[currentPage]
                     }
                 </PageContainer>
@@ -11,7 +11,8 @@ export default function Sidebar(props: { setPage: (name: string) => void }) {
                     ["robot", "bots"],
                     [],
                     ["robot", "bot-settings"],
-                    ["code", "commands"]
+                    ["code", "commands"],
+                    ["terminal", "console"]
                 ].map(part => part[0]
                     ? <Icon icon={part[0]} className="sidebar-icon" onClick={() => props.setPage(part[1])} />
                     : <hr />)
@@ -1,20 +1,46 @@
-import React, { useState } from "react";
+import React, { useEffect, useState } from "react";
 import { useMakerSelector } from "../reduxStore";
 import BotController from "../components/BotController";
 import Input from "../components/Input";
 import { useDispatch } from "react-redux";
-import { asyncBasicInputModel } from "../components/models/modelTemplates";
+import { asyncBasicInputModel, messageModel } from "../components/models/modelTemplates";
 import { invoke } from "@tauri-apps/api";
 import { Bot } from "../types/structures";
+import { PluginInfo } from "../types/tauri";
 import { addBot } from "../stores/bots";
 import HiddenDiv from "../components/HiddenDiv";
 import { setSave } from "../App";
+import Button from "../components/Button";
 
 export default function BotSettings() {
     const dispatch = useDispatch();
     const bots = useMakerSelector(state => state.bots);
     const settings = useMakerSelector(state => state.settings);
     const [updatedSettings, setUpdatedSettings] = useState<{ [key: string]: HTMLInputElement }>({});
+    const [plugins, setPlugins] = useState<PluginInfo[]>([]);
+
+    useEffect(() => {
+        (async () => {
+            if (!settings.current_bot) return;
+            setPlugins(await invoke<PluginInfo[]>("get_plugins", { botId: settings.current_bot }));
+        })();
+    }, [settings]);
+
+    async function setPluginEnabled(name: string, enabled: boolean) {
+        setPlugins(await invoke<PluginInfo[]>("set_plugin_enabled", { botId: settings.current_bot, name, enabled }));
+    }
+
+    async function exportBot() {
+        try {
+            let path = await invoke<string>("export_bot", { id: settings.current_bot });
+            messageModel(<>
+                The bot was exported to <b>{path}</b>
+                <p>Run it with <code>cargo run</code>, with DISCORD_TOKEN set to its token</p>
+            </>, "Export");
+        } catch (err) {
+            messageModel(<>{err}</>, "Export failed");
+        }
+    }
 
     async function setBotName() {
         let name = await asyncBasicInputModel(`Change ${bots[settings.current_bot || -1].name}'s name`, "", "New Name");
@@ -59,6 +85,17 @@ export default function BotSettings() {
                         <label>Token</label>
                         <Input onKeyUp={v => setUpdate("token", (v.target as HTMLInputElement))} className="jumbo" defaultValue={bots[settings.current_bot].token} />
                     </HiddenDiv>
+                    <h1>Plugins</h1>
+                    {plugins.length === 0
+                        ? <p>Put .wasm files in the plugins folder to use them in scripts</p>
+                        : plugins.map(plugin => <p>
+                            <input type="checkbox" checked={plugin.enabled} onChange={e => setPluginEnabled(plugin.name, e.target.checked)} />
+                            <b>{plugin.name}</b>: {plugin.error ?? plugin.natives.map(native => `${plugin.name}.${native}`).join(", ")}
+                        </p>)
+                    }
+                    <h1>Export</h1>
+                    <p>Turns the bot into a Rust project which runs without the app, only dbm scripts can be exported</p>
+                    <Button className="jumbo" onClick={exportBot}>Export</Button>
                 </>
             }
         </>
@@ -1,22 +1,43 @@
 import React, { useEffect, useRef, useState } from "react";
 import Container from "../components/Container";
-import { CodePiece, Command } from "../types/structures";
+import { CodePiece, Command, CommandTest } from "../types/structures";
 import { invoke } from "@tauri-apps/api";
+import { listen } from "@tauri-apps/api/event";
 import { useMakerSelector, useSettingsSelector } from "../reduxStore";
 import { useDispatch } from "react-redux";
 import { addCommand } from "../stores/commands";
 import Button from "../components/Button";
 import HeaderText from "../components/HeaderText";
 import { Editor } from "@monaco-editor/react";
-import { asyncBasicInputModel } from "../components/models/modelTemplates";
+import { asyncBasicInputModel, messageModel } from "../components/models/modelTemplates";
+import { CodePieceEdits, DebugCommand, DebugSnapshot, Diagnostic, Migration, Profile, References, TauriEvents, TestCase, TestResult, TextEdit } from "../types/tauri";
+import Input from "../components/Input";
+
+const LATEST_EDITION = 3;
+const LANGUAGES = ["dbm", "rhai"];
 
 export default function CommandsPage() {
     const dispatch = useDispatch();
     const commands = useMakerSelector(state => state.commands);
     const settings = useSettingsSelector(state => state.settings);
     const [currentCommand, setCurrentCommand] = useState<number | null>(null);
+    const [libraries, setLibraries] = useState<CodePiece[]>([]);
+    const [currentLibrary, setCurrentLibrary] = useState<CodePiece | null>(null);
     const [currentCode, setCurrentCode] = useState<string>("");
+    const [currentEdition, setCurrentEdition] = useState<number>(LATEST_EDITION);
+    const [currentLanguage, setCurrentLanguage] = useState<string>("dbm");
     const editorRef = useRef<any>(null);
+    const monacoRef = useRef<any>(null);
+    const breakpointsRef = useRef<number[]>([]);
+    const decorationsRef = useRef<string[]>([]);
+    const [debugging, setDebugging] = useState<boolean>(false);
+    const [paused, setPaused] = useState<DebugSnapshot | null>(null);
+    const pausedRef = useRef<DebugSnapshot | null>(null);
+    const [profiling, setProfiling] = useState<boolean>(false);
+    const [profile, setProfile] = useState<Profile | null>(null);
+    const profiledCodeRef = useRef<number | null>(null);
+    const [tests, setTests] = useState<CommandTest[]>([]);
+    const [testResults, setTestResults] = useState<TestResult[] | null>(null);
 
     useEffect(() => {
         (async () => {
@@ -24,9 +45,39 @@ export default function CommandsPage() {
             let commands = await invoke("get_all_commands", { botId: settings.current_bot }) as Command[];
             for (const command of commands)
                 dispatch(addCommand(command));
+            setLibraries(await invoke<CodePiece[]>("get_libraries", { botId: settings.current_bot }));
         })();
     }, [settings]);
 
+    useEffect(() => {
+        let unlisteners = [
+            listen<TauriEvents["debug_paused"]>("debug_paused", data => {
+                if (data.payload.bot_id !== settings.current_bot) return;
+                setPaused(data.payload.snapshot);
+                editorRef.current?.revealLineInCenter(data.payload.snapshot.location.line);
+            }),
+            listen<TauriEvents["debug_finished"]>("debug_finished", data => {
+                if (data.payload.bot_id !== settings.current_bot) return;
+                setPaused(null);
+            }),
+            listen<TauriEvents["profile_update"]>("profile_update", data => {
+                if (data.payload.bot_id !== settings.current_bot) return;
+                if (data.payload.code_id !== profiledCodeRef.current) return;
+                setProfile(data.payload.profile);
+            }),
+        ];
+
+        return () => {
+            for (const unlisten of unlisteners)
+                unlisten.then(f => f());
+        };
+    }, [settings]);
+
+    useEffect(() => {
+        pausedRef.current = paused;
+        updateDecorations();
+    }, [paused]);
+
     async function createCommand() {
         let name = await asyncBasicInputModel("Create Command", "", "Name");
         if (!settings.current_bot || !name) return;
@@ -34,6 +85,32 @@ export default function CommandsPage() {
         dispatch(addCommand(command));
     }
 
+    async function createLibrary() {
+        let name = await asyncBasicInputModel("Create Library", "", "Name");
+        if (!settings.current_bot || !name) return;
+        const library = await invoke<CodePiece>("create_library", { botId: settings.current_bot, name });
+        setLibraries([...libraries, library]);
+    }
+
+    async function loadLibrary(library: CodePiece) {
+        let codePiece = await invoke<CodePiece>("get_code_piece", { id: library.id });
+        setCurrentCode(codePiece.code);
+        setCurrentEdition(codePiece.edition);
+        setCurrentLanguage(codePiece.language);
+        setCurrentCommand(null);
+        setCurrentLibrary(codePiece);
+        editorRef.current?.getModel().setValue(codePiece.code);
+        await stopDebugging();
+    }
+
+    async function changeLibraryName(library: CodePiece) {
+        let name = await asyncBasicInputModel(`Change ${library.name}'s Name`, null, "New Name");
+        if (!name) return;
+        let newLibrary = await invoke<CodePiece>("set_library_name", { id: library.id, name });
+        setLibraries(libraries.map(x => x.id === newLibrary.id ? newLibrary : x));
+        setCurrentLibrary(newLibrary);
+    }
+
     async function loadCommand(id: number) {
         let command = commands[id];
         if (!command.code_id) {
@@ -44,8 +121,20 @@ export default function CommandsPage() {
 
         let codePiece = await invoke<CodePiece>("get_code_piece", { id: command.code_id });
         setCurrentCode(codePiece.code);
+        setCurrentEdition(codePiece.edition);
+        setCurrentLanguage(codePiece.language);
         setCurrentCommand(command.id);
-        editorRef.current.getModel().setValue(codePiece.code);
+        setCurrentLibrary(null);
+        editorRef.current?.getModel().setValue(codePiece.code);
+
+        breakpointsRef.current = [];
+        await stopDebugging();
+
+        setTests(await invoke<CommandTest[]>("get_command_tests", { commandId: command.id }));
+        setTestResults(null);
+
+        profiledCodeRef.current = command.code_id;
+        setProfile(await invoke<Profile | null>("get_profile", { id: settings.current_bot, codeId: command.code_id }));
     }
 
     async function changeCommandName(id: number) {
@@ -55,13 +144,235 @@ export default function CommandsPage() {
         dispatch(addCommand(newCommand));
     }
 
+    function currentCodeId() {
+        return currentLibrary ? currentLibrary.id : commands[currentCommand as number].code_id;
+    }
+
+    async function changeLanguage(language: string) {
+        let codePiece = await invoke<CodePiece>("set_code_piece_language", { id: currentCodeId(), language });
+        setCurrentLanguage(codePiece.language);
+        await stopDebugging();
+        await checkCode(editorRef.current.getModel().getValue(), codePiece.language);
+    }
+
     async function save() {
         let value = editorRef.current.getModel().getValue();
-        await invoke("set_code_piece", { id: commands[currentCommand as number].code_id, code: value })
+        await invoke("set_code_piece", { id: currentCodeId(), code: value })
+        await checkCode(value);
+    }
+
+    async function format() {
+        let value = editorRef.current.getModel().getValue();
+        try {
+            let formatted = await invoke<string>("format_code_piece", { code: value, edition: currentEdition });
+            editorRef.current.getModel().setValue(formatted);
+        } catch {
+            await checkCode(value);
+        }
+    }
+
+    async function migrate() {
+        // Migrating works on the saved code, so save first
+        await save();
+        let migration = await invoke<Migration>("migrate_code_piece", { id: currentCodeId() });
+        editorRef.current.getModel().setValue(migration.source);
+        setCurrentEdition(migration.to);
+
+        messageModel(<>
+            Migrated from edition {migration.from} to {migration.to}
+            {migration.changes.length === 0 ? <p>Nothing had to be changed</p> : <ul>
+                {migration.changes.map(change => <li>{change.location.line}:{change.location.column} {change.message}</li>)}
+            </ul>}
+        </>, "Migration");
+    }
+
+    function codePieceName(id: number) {
+        let library = libraries.find(x => x.id === id);
+        if (library) return library.name;
+        return Object.values(commands).find(x => x.code_id === id)?.name ?? `code_piece_${id}`;
     }
 
-    function onMount(e: any) {
+    async function findReferences() {
+        // The index has the saved code
+        await save();
+        let position = editorRef.current.getPosition();
+        try {
+            let references = await invoke<References[]>("find_references", {
+                botId: settings.current_bot,
+                codePieceId: currentCodeId(),
+                line: position.lineNumber,
+                column: position.column,
+            });
+            messageModel(<ul>
+                {references.flatMap(x => x.locations.map(location => <li>
+                    {codePieceName(x.code_piece_id)} {location.line}:{location.column}
+                </li>))}
+            </ul>, "References");
+        } catch (err) {
+            messageModel(<>{err}</>, "Find References");
+        }
+    }
+
+    async function renameSymbol() {
+        await save();
+        let position = editorRef.current.getPosition();
+        let newName = await asyncBasicInputModel("Rename", "", "New Name");
+        if (!newName) return;
+
+        let changes: CodePieceEdits[];
+        try {
+            changes = await invoke<CodePieceEdits[]>("rename_symbol", {
+                botId: settings.current_bot,
+                codePieceId: currentCodeId(),
+                line: position.lineNumber,
+                column: position.column,
+                newName,
+            });
+        } catch (err) {
+            messageModel(<>{err}</>, "Rename");
+            return;
+        }
+
+        for (const change of changes) {
+            let codePiece = await invoke<CodePiece>("get_code_piece", { id: change.code_piece_id });
+            let code = applyEdits(codePiece.code, change.edits);
+            await invoke("set_code_piece", { id: change.code_piece_id, code });
+            if (change.code_piece_id === currentCodeId())
+                editorRef.current.getModel().setValue(code);
+        }
+    }
+
+    async function checkCode(code: string, language: string = currentLanguage) {
+        let diagnostics = await invoke<Diagnostic[]>("check_code", { code, edition: currentEdition, language });
+        let markers = [];
+
+        for (const diagnostic of diagnostics) {
+            if (!diagnostic.location) continue;
+            markers.push({
+                severity: monacoRef.current.MarkerSeverity.Error,
+                message: [diagnostic.message, ...diagnostic.notes].join("\n"),
+                startLineNumber: diagnostic.location.line,
+                startColumn: diagnostic.location.column,
+                endLineNumber: diagnostic.location.end_line,
+                endColumn: diagnostic.location.end_column,
+            });
+
+            for (const label of diagnostic.labels) {
+                markers.push({
+                    severity: monacoRef.current.MarkerSeverity.Info,
+                    message: label.message,
+                    startLineNumber: label.location.line,
+                    startColumn: label.location.column,
+                    endLineNumber: label.location.end_line,
+                    endColumn: label.location.end_column,
+                });
+            }
+        }
+
+        monacoRef.current.editor.setModelMarkers(editorRef.current.getModel(), "dbm", markers);
+    }
+
+    function updateDecorations() {
+        if (!editorRef.current) return;
+        let decorations = breakpointsRef.current.map(line => ({
+            range: new monacoRef.current.Range(line, 1, line, 1),
+            options: { isWholeLine: true, glyphMarginClassName: "breakpoint" },
+        }));
+
+        let line = pausedRef.current?.location.line;
+        if (line) {
+            decorations.push({
+                range: new monacoRef.current.Range(line, 1, line, 1),
+                options: { isWholeLine: true, className: "paused-line" } as any,
+            });
+        }
+
+        decorationsRef.current = editorRef.current.deltaDecorations(decorationsRef.current, decorations);
+    }
+
+    function toggleBreakpoint(line: number) {
+        if (breakpointsRef.current.includes(line))
+            breakpointsRef.current = breakpointsRef.current.filter(x => x !== line);
+        else breakpointsRef.current = [...breakpointsRef.current, line];
+        updateDecorations();
+    }
+
+    async function startDebugging() {
+        if (!settings.current_bot || currentCommand === null) return;
+        await invoke("start_debugging", {
+            id: settings.current_bot,
+            codeId: commands[currentCommand].code_id,
+            breakpoints: breakpointsRef.current,
+            pauseOnStart: breakpointsRef.current.length === 0,
+        });
+        setDebugging(true);
+    }
+
+    async function stopDebugging() {
+        if (!settings.current_bot) return;
+        await invoke("stop_debugging", { id: settings.current_bot });
+        setDebugging(false);
+        setPaused(null);
+    }
+
+    async function resume(command: DebugCommand) {
+        setPaused(null);
+        await invoke("debug_resume", { id: settings.current_bot, command });
+    }
+
+    async function startProfiling() {
+        if (!settings.current_bot) return;
+        await invoke("start_profiling", { id: settings.current_bot });
+        setProfiling(true);
+        setProfile(null);
+    }
+
+    async function stopProfiling() {
+        if (!settings.current_bot) return;
+        await invoke("stop_profiling", { id: settings.current_bot });
+        setProfiling(false);
+    }
+
+    async function exportProfile() {
+        let path = await invoke<string>("export_profile", { id: settings.current_bot, codeId: profiledCodeRef.current });
+        messageModel(<>
+            The flamegraph was written to <code>{path}</code>, it can be viewed with tools like inferno or speedscope
+        </>, "Exported Profile");
+    }
+
+    async function createTest() {
+        let name = await asyncBasicInputModel("Create Test", "", "Name");
+        if (currentCommand === null || !name) return;
+        let test = await invoke<CommandTest>("create_command_test", { commandId: currentCommand, name });
+        setTests([...tests, test]);
+    }
+
+    async function setTest(test: CommandTest, changes: Partial<TestCase>) {
+        let testCase: TestCase = { ...toTestCase(test), ...changes };
+        let newTest = await invoke<CommandTest>("set_command_test", { id: test.id, test: testCase });
+        setTests(tests => tests.map(x => x.id === newTest.id ? newTest : x));
+    }
+
+    async function deleteTest(test: CommandTest) {
+        await invoke("delete_command_test", { id: test.id });
+        setTests(tests.filter(x => x.id !== test.id));
+    }
+
+    async function runTests() {
+        // Tests run the saved code
+        await save();
+        let results = await invoke<{ results: TestResult[] }[]>("run_command_tests", { botId: settings.current_bot, commandId: currentCommand });
+        setTestResults(results.flatMap(x => x.results));
+    }
+
+    function onMount(e: any, monaco: any) {
         editorRef.current = e;
+        monacoRef.current = monaco;
+
+        e.onMouseDown((event: any) => {
+            if (event.target.type === monaco.editor.MouseTargetType.GUTTER_GLYPH_MARGIN)
+                toggleBreakpoint(event.target.position.lineNumber);
+        });
     }
 
     return (
@@ -74,18 +385,135 @@ export default function CommandsPage() {
                             <label className="container-item" onClick={() => loadCommand(commands[x].id)}>{commands[x].name}</label>
                         </>)
                     }
+                    <br />
+                    <Button className="jumbo" onClick={createLibrary}>New Library</Button><br />
+                    {
+                        libraries.map(library => <>
+                            <label className="container-item" onClick={() => loadLibrary(library)}>{library.name}</label>
+                        </>)
+                    }
                 </Container>
                 <div className="flex-1">
-                    {currentCommand &&
+                    {(currentCommand || currentLibrary) &&
                         <>
-                            <HeaderText onClick={() => changeCommandName(currentCommand)} className="editable">{commands[currentCommand].name}</HeaderText>
-                            <i className="editable">Some kind of editable description goes here</i>
-                            <Editor theme="vs-dark" onMount={e => onMount(e)} height={400} value={currentCode} />
+                            {currentLibrary
+                                ? <HeaderText onClick={() => changeLibraryName(currentLibrary)} className="editable">{currentLibrary.name}</HeaderText>
+                                : <HeaderText onClick={() => changeCommandName(currentCommand as number)} className="editable">{commands[currentCommand as number].name}</HeaderText>
+                            }
+                            <i className="editable">{currentLibrary ? `Import with import "${currentLibrary.name}"` : "Some kind of editable description goes here"}</i>
+                            {currentCommand && <p>
+                                Language <select value={currentLanguage} onChange={e => changeLanguage(e.target.value)}>
+                                    {LANGUAGES.map(language => <option value={language}>{language}</option>)}
+                                </select>
+                            </p>}
+                            {currentLanguage === "dbm" && currentEdition < LATEST_EDITION && <p>
+                                This is written in edition {currentEdition}, the latest is {LATEST_EDITION}
+                                <Button type="secondary" onClick={migrate}>Migrate</Button>
+                            </p>}
+                            <Editor theme="vs-dark" onMount={(e, monaco) => onMount(e, monaco)} height={400} value={currentCode} options={{ glyphMargin: true }} />
                             <Button className="jumbo" onClick={save}>Save</Button>
+                            {currentLanguage === "dbm" && <Button className="jumbo" type="secondary" onClick={format}>Format</Button>}
+                            {currentLanguage === "dbm" && <Button className="jumbo" type="secondary" onClick={findReferences}>Find References</Button>}
+                            {currentLanguage === "dbm" && <Button className="jumbo" type="secondary" onClick={renameSymbol}>Rename</Button>}
+                            {currentCommand && currentLanguage === "dbm" && (debugging
+                                ? <Button className="jumbo" type="secondary" onClick={stopDebugging}>Stop Debugging</Button>
+                                : <Button className="jumbo" type="secondary" onClick={startDebugging}>Debug</Button>
+                            )}
+                            {currentCommand && currentLanguage === "dbm" && (profiling
+                                ? <Button className="jumbo" type="secondary" onClick={stopProfiling}>Stop Profiling</Button>
+                                : <Button className="jumbo" type="secondary" onClick={startProfiling}>Profile</Button>
+                            )}
+                            {paused &&
+                                <Container>
+                                    <b>Paused on line {paused.location.line}</b><br />
+                                    <Button type="secondary" onClick={() => resume("continue")}>Continue</Button>
+                                    <Button type="secondary" onClick={() => resume("step_over")}>Step Over</Button>
+                                    <Button type="secondary" onClick={() => resume("step_in")}>Step In</Button>
+                                    <Button type="secondary" onClick={() => resume("step_out")}>Step Out</Button>
+                                    <Button type="secondary" onClick={() => resume("stop")}>Stop</Button>
+                                    {paused.variables.map(variable => <>
+                                        <br />
+                                        <code>{variable.name}: {variable.type_name} = {variable.value}</code>
+                                    </>)}
+                                </Container>
+                            }
+                            {currentCommand && currentLanguage === "dbm" &&
+                                <Container>
+                                    <b>Tests</b><br />
+                                    <Button type="secondary" onClick={createTest}>New Test</Button>
+                                    {tests.length !== 0 && <Button type="secondary" onClick={runTests}>Run Tests</Button>}
+                                    {tests.map(test => <div key={test.id} className="command-test">
+                                        <Input defaultValue={test.name} onBlur={e => setTest(test, { name: e.target.value })} placeholder="Name" />
+                                        <Input defaultValue={test.content} onBlur={e => setTest(test, { content: e.target.value })} placeholder="Message" />
+                                        <Input defaultValue={test.author} onBlur={e => setTest(test, { author: e.target.value })} placeholder="Author" />
+                                        <Input defaultValue={test.channel} onBlur={e => setTest(test, { channel: e.target.value })} placeholder="Channel ID" />
+                                        <textarea
+                                            className="input"
+                                            defaultValue={toTestCase(test).expected_replies.join("\n")}
+                                            onBlur={e => setTest(test, { expected_replies: e.target.value.split("\n").filter(x => x.length !== 0) })}
+                                            placeholder="Expected replies, one per line"
+                                        />
+                                        <Button type="error" onClick={() => deleteTest(test)}>Delete</Button>
+                                    </div>)}
+                                    {testResults && testResults.map(result => <div className={`test-result ${result.passed ? "passed" : "failed"}`}>
+                                        <b>{result.passed ? "PASS" : "FAIL"} {result.name}</b>
+                                        {!result.passed && result.diff.map(line => <div className={`test-diff ${line.kind}`}>
+                                            {{ same: " ", missing: "-", unexpected: "+" }[line.kind]} {line.reply}
+                                        </div>)}
+                                        {!result.passed && result.logs.map(log => <div className="test-diff">{log}</div>)}
+                                        {result.error && <div className="test-diff">{result.error.message}{result.error.location && ` (line ${result.error.location.line})`}</div>}
+                                    </div>)}
+                                </Container>
+                            }
+                            {currentCommand && profile &&
+                                <Container>
+                                    <b>{profile.executions} execution(s), {formatTime(profile.total / profile.executions)} on average</b><br />
+                                    <Button type="secondary" onClick={exportProfile}>Export Flamegraph</Button>
+                                    <table>
+                                        <tr><th>Node</th><th>Calls</th><th>Self</th><th>Total</th></tr>
+                                        {profile.nodes.slice(0, 10).map(node => <tr onClick={() => editorRef.current?.revealLineInCenter(node.location.line)}>
+                                            <td><code>{node.label}</code></td>
+                                            <td>{node.calls}</td>
+                                            <td>{formatTime(node.own)}</td>
+                                            <td>{formatTime(node.total)}</td>
+                                        </tr>)}
+                                    </table>
+                                    {profile.natives.length !== 0 && <table>
+                                        <tr><th>Native Function</th><th>Calls</th><th>Awaited</th></tr>
+                                        {profile.natives.map(native => <tr>
+                                            <td><code>{native.name}</code></td>
+                                            <td>{native.calls}</td>
+                                            <td>{formatTime(native.awaited)}</td>
+                                        </tr>)}
+                                    </table>}
+                                </Container>
+                            }
                         </>
                     }
                 </div>
             </div>
         </>
     )
-}
\ No newline at end of file
+}
+
+function toTestCase(test: CommandTest): TestCase {
+    return { ...test, expected_replies: JSON.parse(test.expected_replies) };
+}
+
+/** Applies edits from `rename_symbol`, which are in order and each on one line */
+function applyEdits(code: string, edits: TextEdit[]) {
+    let lines = code.split("\n");
+    for (const edit of [...edits].reverse()) {
+        let { line, column, end_column } = edit.location;
+        // Columns count characters, not UTF-16 code units
+        let characters = Array.from(lines[line - 1]);
+        characters.splice(column - 1, end_column - column, edit.text);
+        lines[line - 1] = characters.join("");
+    }
+    return lines.join("\n");
+}
+
+/** Formats a time in microseconds */
+function formatTime(micros: number) {
+    return micros >= 1000 ? `${(micros / 1000).toFixed(1)}ms` : `${Math.round(micros)}µs`;
+}
@@ -0,0 +1,58 @@
+import React, { useEffect, useState } from "react";
+import { invoke } from "@tauri-apps/api";
+import { listen } from "@tauri-apps/api/event";
+import { useMakerSelector } from "../reduxStore";
+import Button from "../components/Button";
+import { ConsoleEntry, TauriEvents } from "../types/tauri";
+
+// The same as the backend keeps
+const MAX_ENTRIES = 500;
+
+export default function ConsolePage() {
+    const settings = useMakerSelector(state => state.settings);
+    const [entries, setEntries] = useState<ConsoleEntry[]>([]);
+
+    useEffect(() => {
+        (async () => {
+            if (!settings.current_bot) return;
+            setEntries(await invoke<ConsoleEntry[]>("get_console", { id: settings.current_bot }));
+        })();
+
+        let unlisten = listen<TauriEvents["console_entry"]>("console_entry", data => {
+            if (data.payload.bot_id !== settings.current_bot) return;
+            setEntries(entries => [...entries, data.payload.entry].slice(-MAX_ENTRIES));
+        });
+
+        return () => {
+            unlisten.then(f => f());
+        };
+    }, [settings]);
+
+    async function clear() {
+        await invoke("clear_console", { id: settings.current_bot });
+        setEntries([]);
+    }
+
+    return (
+        <>
+            {!settings.current_bot ? <></>
+                :
+                <>
+                    <Button type="secondary" onClick={clear}>Clear</Button>
+                    {entries.length === 0
+                        ? <p>Nothing has been printed yet, use print, log.info or log.warn in a command</p>
+                        : entries.map(entry => <div className={`console-entry ${entry.level}`}>
+                            <small>{new Date(entry.time).toLocaleTimeString()} </small>
+                            <b>[{entry.level}]</b> {entry.message}
+                            <br />
+                            <small>
+                                in {entry.command}, run by {entry.user}
+                                {entry.location && `, at ${entry.location.context}:${entry.location.line}:${entry.location.column}`}
+                            </small>
+                        </div>)
+                    }
+                </>
+            }
+        </>
+    );
+}
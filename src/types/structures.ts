@@ -21,4 +21,18 @@ export interface Command {
 export interface CodePiece {
     id: number,
     code: string,
-}
\ No newline at end of file
+    name: string | null,
+    bot_id: number | null,
+    edition: number,
+    language: string,
+}
+export interface CommandTest {
+    id: number,
+    command_id: number,
+    name: string,
+    content: string,
+    author: string,
+    channel: string,
+    /** A JSON array of strings */
+    expected_replies: string,
+}
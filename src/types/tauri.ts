@@ -7,12 +7,206 @@ export interface TauriEvents {
             message: string,
             error_type: string,
             source: string | null,
-            location: {
-                start: number,
-                end: number,
-                line: number,
-                context: string
-            } | null,
+            location: Location | null,
+            labels: Label[],
+            notes: string[],
+            stack: StackFrame[],
         }
+    },
+    "debug_paused": {
+        bot_id: number,
+        code_id: number,
+        snapshot: DebugSnapshot,
+    },
+    "debug_finished": {
+        bot_id: number,
+    },
+    "profile_update": {
+        bot_id: number,
+        code_id: number,
+        profile: Profile,
+    },
+    "console_entry": {
+        bot_id: number,
+        entry: ConsoleEntry,
     }
-}
\ No newline at end of file
+}
+
+export interface Location {
+    start: number,
+    end: number,
+    line: number,
+    column: number,
+    end_line: number,
+    end_column: number,
+    context: string,
+}
+
+export type LogLevel = "print" | "info" | "warn";
+
+export interface ConsoleEntry {
+    level: LogLevel,
+    message: string,
+    command: string,
+    user: string,
+    location: Location | null,
+    time: number,
+}
+
+/** A message to run a command with, and the replies it should give to it */
+export interface TestCase {
+    name: string,
+    content: string,
+    author: string,
+    channel: string,
+    expected_replies: string[],
+}
+
+export type DiffKind = "same" | "missing" | "unexpected";
+
+export interface DiffLine {
+    kind: DiffKind,
+    reply: string,
+}
+
+export interface TestResult {
+    name: string,
+    passed: boolean,
+    replies: string[],
+    diff: DiffLine[],
+    logs: string[],
+    error: TauriEvents["error"]["error"] | null,
+}
+
+export interface CommandTestResults {
+    command_id: number,
+    command: string,
+    results: TestResult[],
+}
+
+export interface Label {
+    location: Location,
+    message: string,
+}
+
+export interface StackFrame {
+    function: string,
+    location: Location,
+    native: boolean,
+}
+
+export interface Diagnostic {
+    message: string,
+    error_type: string,
+    location: Location | null,
+    labels: Label[],
+    notes: string[],
+    stack: StackFrame[],
+    code_frame: string,
+}
+
+export type DebugCommand = "continue" | "step_in" | "step_over" | "step_out" | "stop";
+
+export interface DebugVariable {
+    name: string,
+    type_name: string,
+    value: string,
+}
+
+export interface DebugSnapshot {
+    location: Location,
+    depth: number,
+    variables: DebugVariable[],
+}
+
+export interface MigrationChange {
+    location: Location,
+    message: string,
+}
+
+export interface Migration {
+    source: string,
+    from: number,
+    to: number,
+    changes: MigrationChange[],
+}
+
+/** Times are in microseconds */
+export interface NodeProfile {
+    location: Location,
+    label: string,
+    calls: number,
+    total: number,
+    own: number,
+}
+
+export interface NativeProfile {
+    name: string,
+    calls: number,
+    awaited: number,
+}
+
+export interface Profile {
+    name: string,
+    executions: number,
+    total: number,
+    nodes: NodeProfile[],
+    natives: NativeProfile[],
+}
+
+export interface PluginInfo {
+    name: string,
+    natives: string[],
+    enabled: boolean,
+    /** Why the plugin could not be loaded */
+    error: string | null,
+}
+
+/** The `location` of a node can be left out, e.g. for blocks which were just dragged in */
+export type Expression = { location?: Location } & (
+    | { type: "Identifier", name: string }
+    | { type: "Block", nodes: Expression[] }
+    | { type: "Number", value: number }
+    | { type: "StringNode", value: string }
+    | { type: "Call", callee: Expression, args: Expression[] }
+    | { type: "Member", left: Expression, right: Expression }
+    | { type: "Logical", left: Expression, right: Expression, operator: "Eq" | "Neq" }
+    | { type: "IfBlock", test: Expression, success: Block, alternate: Expression | null }
+    | { type: "VariableDeclaration", name: Identifier, type_annotation: Identifier | null, value: Expression }
+    | { type: "FunctionDeclaration", name: Identifier, params: { name: Identifier, type_annotation: Identifier | null }[], return_annotation: Identifier | null, body: Block }
+    | { type: "Import", name: { value: string, location?: Location } }
+);
+
+export interface Identifier {
+    name: string,
+    location?: Location,
+}
+
+export interface Block {
+    nodes: Expression[],
+    location?: Location,
+}
+
+/** What `code_to_ast` gives and `ast_to_code` takes */
+export interface SyntaxTree {
+    edition: number,
+    block: Block,
+    comments: { value: string, location?: Location }[],
+}
+
+/** Where a variable is used in one code piece, from `find_references` */
+export interface References {
+    code_piece_id: number,
+    locations: Location[],
+}
+
+export interface TextEdit {
+    location: Location,
+    text: string,
+}
+
+/** What `rename_symbol` would change in one code piece */
+export interface CodePieceEdits {
+    code_piece_id: number,
+    edits: TextEdit[],
+}